@@ -0,0 +1,127 @@
+// Throughput of the interpreter without a window, run with `cargo bench`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use emuchip::{decode::OpCodes, quirks::Quirks, Chip8};
+
+// Instruction mixes that loop forever, so any number of steps can be run
+const ALU: &[u8] = &[
+    0x60, 0x05, // V0 = 5
+    0x61, 0x07, // V1 = 7
+    0x80, 0x14, // V0 += V1
+    0x80, 0x15, // V0 -= V1
+    0x80, 0x12, // V0 &= V1
+    0x80, 0x1E, // V0 <<= 1
+    0x30, 0x00, // skip the next one if V0 == 0
+    0x70, 0x01, // V0 += 1
+    0x12, 0x04, // jump back to the adds
+];
+const MEMORY: &[u8] = &[
+    0xA3, 0x00, // I = 0x300
+    0x60, 0xFE, // V0 = 254
+    0xF0, 0x33, // BCD of V0 at I
+    0xF2, 0x65, // V0-V2 = [I]
+    0xF2, 0x55, // [I] = V0-V2
+    0x12, 0x00, // loop
+];
+const DRAW: &[u8] = &[
+    0x60, 0x08, // V0 = 8
+    0xF0, 0x29, // I = font sprite for V0
+    0xD0, 0x05, // draw it at (V0, V0)
+    0x70, 0x01, // V0 += 1
+    0xC1, 0x3F, // V1 = random
+    0xD1, 0x05, // draw at (V1, V0)
+    0x12, 0x04, // loop
+];
+
+fn chip8(rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.set_seed(0);
+    chip8.load_rom(rom).unwrap();
+    chip8
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1 << 16));
+    group.bench_function("every opcode", |b| {
+        b.iter(|| {
+            for ins in 0..=u16::MAX {
+                black_box(OpCodes::decode_raw(black_box(ins)));
+            }
+        })
+    });
+    group.finish();
+}
+
+// what the cache saves every step, decoding the word at the program counter
+fn fetch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fetch");
+    group.throughput(Throughput::Elements(ALU.len() as u64 / 2));
+    let mut chip8 = chip8(ALU);
+    let addrs = (0x200..0x200 + ALU.len() as u16).step_by(2);
+    group.bench_function("decoded", |b| {
+        b.iter(|| {
+            for addr in addrs.clone() {
+                let ins = chip8.mem.instruction_at(black_box(addr)).unwrap();
+                black_box(OpCodes::decode_raw(ins));
+            }
+        })
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for addr in addrs.clone() {
+                black_box(chip8.mem.op_at(black_box(addr)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn execute(c: &mut Criterion) {
+    const STEPS: u64 = 1000;
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(STEPS));
+    for (name, rom) in [("alu", ALU), ("memory", MEMORY), ("draw", DRAW)] {
+        let mut chip8 = chip8(rom);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..STEPS {
+                    chip8.step().unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    // 700 instructions a second
+    const CYCLES: u32 = 11;
+    let mut chip8 = chip8(DRAW);
+    c.bench_function("frame", |b| {
+        b.iter(|| {
+            chip8.run_frame(CYCLES).unwrap();
+            black_box(chip8.framebuffer_mut().take_update());
+        })
+    });
+}
+
+// a frame's worth of the alu loop at clock rates far past the original
+// interpreters, where fetching and decoding is most of the work
+fn clock(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clock");
+    for speed in [700, 10_000, 1_000_000] {
+        let cycles = speed / 60;
+        group.throughput(Throughput::Elements(cycles as u64));
+        let mut chip8 = chip8(ALU);
+        group.bench_function(format!("{speed} Hz"), |b| {
+            b.iter(|| chip8.run_frame(cycles).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, fetch, execute, frame, clock);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+// Any bytes as a rom: every word decodes to something, prints, and what
+// encodes decodes back to the same instruction
+
+#![no_main]
+
+use emuchip::{decode::OpCodes, disasm, memory::PROGRAM_START};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|rom: &[u8]| {
+    for word in rom.chunks_exact(2) {
+        let op = OpCodes::decode_raw(u16::from_be_bytes([word[0], word[1]]));
+        let _ = op.to_string();
+        if let Some(code) = op.encode() {
+            assert_eq!(OpCodes::decode_raw(code), op, "{code:04X}");
+        }
+    }
+    for line in disasm::decode_rom(rom, PROGRAM_START) {
+        let _ = line.op.to_string();
+    }
+});
@@ -0,0 +1,59 @@
+// Short random programs on every platform, with the keys held each frame
+// also picked by the fuzzer. Whatever a rom does has to end in an
+// EmulatorError at worst, never a panic
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use emuchip::{
+    quirks::{MachineRoutines, Platform, Quirks},
+    Chip8,
+};
+use libfuzzer_sys::fuzz_target;
+
+const PLATFORMS: [Platform; 5] = [
+    Platform::Cosmac,
+    Platform::Eti660,
+    Platform::Chip48,
+    Platform::Schip,
+    Platform::XoChip,
+];
+// enough for loops to come around a few times
+const FRAMES: usize = 16;
+const CYCLES: usize = 30;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    platform: u8,
+    seed: u64,
+    // one bit per key, for each frame
+    keys: Vec<u16>,
+    program: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let platform = PLATFORMS[input.platform as usize % PLATFORMS.len()];
+    let quirks = Quirks::from_platform(platform);
+    let mut chip8 = Chip8::with_seed(quirks, input.seed);
+    // past the VIP routines, which stop everything otherwise
+    chip8.machine_routines = MachineRoutines::Skip;
+    if chip8.load_rom(&input.program).is_err() {
+        return;
+    }
+    for held in input.keys.iter().take(FRAMES) {
+        for key in 0..16 {
+            chip8.set_key(key, (held >> key) & 1 != 0);
+        }
+        for _ in 0..CYCLES {
+            if chip8.step().is_err() {
+                return;
+            }
+            assert!(chip8.mem.stack.addresses().len() <= quirks.stack_depth as usize);
+        }
+        chip8.sync();
+        let fb = chip8.framebuffer_mut();
+        if let Some(rows) = fb.take_dirty_rows() {
+            assert!(rows.end <= fb.height());
+        }
+    }
+});
@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{
+    decode::OpCodes,
+    disasm::{flow, reachable, word_at},
+    memory::TypeAddr,
+    quirks::Platform,
+};
+
+// Instructions later interpreters added to the original CHIP-8
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extension {
+    Schip,
+    XoChip,
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Schip => "SCHIP",
+            Self::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+pub fn extension(op: &OpCodes) -> Option<Extension> {
+    match op {
+        OpCodes::DisplayLarge(..)
+        | OpCodes::LowRes
+        | OpCodes::HighRes
+        | OpCodes::Exit
+        | OpCodes::ScrollDown(_)
+        | OpCodes::ScrollRight
+        | OpCodes::ScrollLeft
+        | OpCodes::PointBigChar(_)
+        | OpCodes::StoreFlags(_)
+        | OpCodes::LoadFlags(_) => Some(Extension::Schip),
+        OpCodes::LoadAudio | OpCodes::SetPitch(_) => Some(Extension::XoChip),
+        _ => None,
+    }
+}
+
+// the opcode the way documentation writes it
+fn pattern(op: &OpCodes) -> &'static str {
+    match op {
+        OpCodes::ClearScreen => "00E0",
+        OpCodes::TwoPageClear => "0230",
+        OpCodes::Jump(_) => "1NNN",
+        OpCodes::SetRegister(..) => "6XNN",
+        OpCodes::AddToRegister(..) => "7XNN",
+        OpCodes::SetIndexRegister(_) => "ANNN",
+        OpCodes::Display(..) => "DXYN",
+        OpCodes::DisplayLarge(..) => "DXY0",
+        OpCodes::LowRes => "00FE",
+        OpCodes::HighRes => "00FF",
+        OpCodes::Exit => "00FD",
+        OpCodes::MachineRoutine(_) => "0NNN",
+        OpCodes::ScrollDown(_) => "00CN",
+        OpCodes::ScrollRight => "00FB",
+        OpCodes::ScrollLeft => "00FC",
+        OpCodes::PushSubroutine(_) => "2NNN",
+        OpCodes::PopSubroutine => "00EE",
+        OpCodes::SkipEqualConstant(..) => "3XNN",
+        OpCodes::SkipNotEqualConstant(..) => "4XNN",
+        OpCodes::SkipEqualRegister(..) => "5XY0",
+        OpCodes::SkipNotEqualRegister(..) => "9XY0",
+        OpCodes::CopyRegister(..) => "8XY0",
+        OpCodes::Or(..) => "8XY1",
+        OpCodes::And(..) => "8XY2",
+        OpCodes::XOr(..) => "8XY3",
+        OpCodes::Add(..) => "8XY4",
+        OpCodes::SubtractForward(..) => "8XY5",
+        OpCodes::RightShift(..) => "8XY6",
+        OpCodes::SubtractBackward(..) => "8XY7",
+        OpCodes::LeftShift(..) => "8XYE",
+        OpCodes::JumpWithOffset(_) => "BNNN",
+        OpCodes::Random(..) => "CXNN",
+        OpCodes::SkipIfPressed(_) => "EX9E",
+        OpCodes::SkipIfNotPressed(_) => "EXA1",
+        OpCodes::LoadAudio => "F002",
+        OpCodes::CopyDelayToRegister(_) => "FX07",
+        OpCodes::GetKey(_) => "FX0A",
+        OpCodes::CopyRegisterToDelay(_) => "FX15",
+        OpCodes::CopyRegisterToSound(_) => "FX18",
+        OpCodes::AddToIndex(_) => "FX1E",
+        OpCodes::PointChar(_) => "FX29",
+        OpCodes::PointBigChar(_) => "FX30",
+        OpCodes::ToDecimal(_) => "FX33",
+        OpCodes::SetPitch(_) => "FX3A",
+        OpCodes::StoreRegisterToMemory(_) => "FX55",
+        OpCodes::LoadRegisterFromMemory(_) => "FX65",
+        OpCodes::StoreFlags(_) => "FX75",
+        OpCodes::LoadFlags(_) => "FX85",
+        OpCodes::Unimplemented => "????",
+    }
+}
+
+// How often the code uses an opcode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub pattern: &'static str,
+    // the mnemonic without operands
+    pub name: String,
+    pub count: usize,
+    pub extension: Option<Extension>,
+}
+
+// What can be told about a rom without running it, from the instructions
+// reachable from its start
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub instructions: usize,
+    // bytes no instruction covers
+    pub data: usize,
+    // in the order of their patterns
+    pub opcodes: Vec<Usage>,
+    // what may go wrong or run differently between interpreters, in the
+    // order of the addresses they're about
+    pub warnings: Vec<(TypeAddr, String)>,
+    // the preset the rom most likely wants, None when nothing points to one
+    pub platform: Option<Platform>,
+    pub reason: &'static str,
+}
+
+// Reads a rom loaded at `start` the way the disassembler does. Writes are
+// only caught when I was loaded with an address on the way to them
+pub fn analyze(rom: &[u8], start: TypeAddr) -> Report {
+    let op_at = |addr| word_at(rom, start, addr).map(OpCodes::decode_raw);
+    let code = reachable(rom, start);
+    let ops: Vec<(TypeAddr, OpCodes)> = code
+        .iter()
+        .filter_map(|&addr| Some((addr, op_at(addr)?)))
+        .collect();
+    let covered: BTreeSet<TypeAddr> = code.iter().flat_map(|&addr| [addr, addr + 1]).collect();
+
+    let mut opcodes: BTreeMap<&'static str, Usage> = BTreeMap::new();
+    for (_, op) in &ops {
+        let mnemonic = op.to_string();
+        opcodes
+            .entry(pattern(op))
+            .or_insert_with(|| Usage {
+                pattern: pattern(op),
+                name: mnemonic.split_whitespace().next().unwrap_or("").to_string(),
+                count: 0,
+                extension: extension(op),
+            })
+            .count += 1;
+    }
+
+    let mut warnings = vec![];
+    let returns = |addr| matches!(op_at(addr), Some(OpCodes::PopSubroutine | OpCodes::Exit));
+    for addr in flow(rom, start, start, false) {
+        if matches!(op_at(addr), Some(OpCodes::PopSubroutine)) {
+            warnings.push((addr, "RET is reached without a call".to_string()));
+        }
+    }
+    let subroutines: BTreeSet<TypeAddr> = ops
+        .iter()
+        .filter_map(|(_, op)| match op {
+            OpCodes::PushSubroutine(addr) => Some(*addr),
+            _ => None,
+        })
+        .collect();
+    for sub in subroutines {
+        if !flow(rom, start, sub, false).into_iter().any(returns) {
+            warnings.push((
+                sub,
+                "subroutine never returns, each call leaves an address on the stack".to_string(),
+            ));
+        }
+    }
+
+    // where the code is reached from elsewhere than the instruction before,
+    // what's known about I doesn't hold there
+    let mut joins = BTreeSet::new();
+    for &(addr, op) in &ops {
+        match op {
+            OpCodes::Jump(target) => {
+                joins.insert(target);
+            }
+            OpCodes::PushSubroutine(target) => {
+                joins.extend([target, addr + 2]);
+            }
+            OpCodes::SkipEqualConstant(..)
+            | OpCodes::SkipNotEqualConstant(..)
+            | OpCodes::SkipEqualRegister(..)
+            | OpCodes::SkipNotEqualRegister(..)
+            | OpCodes::SkipIfPressed(_)
+            | OpCodes::SkipIfNotPressed(_) => {
+                joins.insert(addr + 4);
+            }
+            _ => {}
+        }
+    }
+    // I when it's known, and the FX55 or FX65 that last moved it, which the
+    // memory quirk decides how far
+    let mut index = None;
+    let mut moved = None;
+    let mut previous: Option<(TypeAddr, OpCodes)> = None;
+    for &(addr, op) in &ops {
+        let falls_through = match previous {
+            Some((before, op)) => {
+                before + 2 == addr
+                    && !matches!(
+                        op,
+                        OpCodes::Jump(_)
+                            | OpCodes::JumpWithOffset(_)
+                            | OpCodes::PopSubroutine
+                            | OpCodes::Exit
+                    )
+            }
+            None => false,
+        };
+        if !falls_through || joins.contains(&addr) {
+            (index, moved) = (None, None);
+        }
+        previous = Some((addr, op));
+
+        let uses_index = matches!(
+            op,
+            OpCodes::Display(..)
+                | OpCodes::DisplayLarge(..)
+                | OpCodes::StoreRegisterToMemory(_)
+                | OpCodes::LoadRegisterFromMemory(_)
+                | OpCodes::ToDecimal(_)
+                | OpCodes::AddToIndex(_)
+                | OpCodes::LoadAudio
+        );
+        if let Some(at) = moved.filter(|_| uses_index) {
+            let message = format!(
+                "I is used at {addr:03X} after it, how far it moved depends on the memory quirk"
+            );
+            warnings.push((at, message));
+            moved = None;
+        }
+        let written = match op {
+            OpCodes::StoreRegisterToMemory(x) => Some(x as TypeAddr + 1),
+            OpCodes::ToDecimal(_) => Some(3),
+            _ => None,
+        };
+        if let (Some(len), Some(from)) = (written, index) {
+            let to = from + len - 1;
+            if (from..=to).any(|byte| covered.contains(&byte)) {
+                warnings.push((
+                    addr,
+                    format!("{op} writes over the code at {from:03X}-{to:03X}"),
+                ));
+            }
+        }
+        match op {
+            OpCodes::SetIndexRegister(nnn) => index = Some(nnn),
+            OpCodes::StoreRegisterToMemory(_) | OpCodes::LoadRegisterFromMemory(_) => {
+                (index, moved) = (None, Some(addr));
+            }
+            OpCodes::AddToIndex(_) | OpCodes::PointChar(_) | OpCodes::PointBigChar(_) => {
+                index = None;
+            }
+            OpCodes::PushSubroutine(_) => (index, moved) = (None, None),
+            _ => {}
+        }
+
+        match op {
+            OpCodes::RightShift(x, y) | OpCodes::LeftShift(x, y) if x != y => {
+                let message =
+                    format!("{op} shifts VY on the COSMAC VIP and VX later on, the shift quirk");
+                warnings.push((addr, message));
+            }
+            OpCodes::JumpWithOffset(nnn) if nnn >> 8 != 0 => {
+                let message = format!("{op} adds V{:X} instead on SCHIP, the jump quirk", nnn >> 8);
+                warnings.push((addr, message));
+            }
+            OpCodes::Unimplemented => {
+                let word = word_at(rom, start, addr).unwrap_or_default();
+                warnings.push((addr, format!("{word:04X} isn't an instruction, but is run")));
+            }
+            _ => {}
+        }
+    }
+    warnings.sort_by_key(|(addr, _)| *addr);
+
+    let uses = |extension| {
+        opcodes
+            .values()
+            .any(|usage| usage.extension == Some(extension))
+    };
+    let cosmac_only = opcodes.contains_key("0NNN") || rom.starts_with(&[0x12, 0x60]);
+    let shifts_vy = ops.iter().any(
+        |(_, op)| matches!(op, OpCodes::RightShift(x, y) | OpCodes::LeftShift(x, y) if x != y),
+    );
+    let (platform, reason) = if uses(Extension::XoChip) {
+        (Some(Platform::XoChip), "it uses XO-CHIP instructions")
+    } else if uses(Extension::Schip) {
+        (Some(Platform::Schip), "it uses SCHIP instructions")
+    } else if cosmac_only {
+        (
+            Some(Platform::Cosmac),
+            "it calls machine code or uses 64x64 hi-res, which only the COSMAC VIP had",
+        )
+    } else if shifts_vy {
+        (
+            Some(Platform::Cosmac),
+            "its shifts name a VY apart from VX, as the COSMAC VIP used it",
+        )
+    } else {
+        (None, "nothing in it points to one interpreter")
+    };
+
+    Report {
+        instructions: ops.len(),
+        data: rom.len().saturating_sub(covered.len()),
+        opcodes: opcodes.into_values().collect(),
+        warnings,
+        platform,
+        reason,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.platform {
+            Some(platform) => writeln!(f, "platform:     {platform}, {}", self.reason)?,
+            None => writeln!(f, "platform:     any, {}", self.reason)?,
+        }
+        writeln!(
+            f,
+            "instructions: {} reachable, {} bytes of data",
+            self.instructions, self.data
+        )?;
+        writeln!(f, "opcodes:")?;
+        for usage in &self.opcodes {
+            let extension = usage
+                .extension
+                .map(|extension| format!("  {extension}"))
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "  {}  {:<5} {:>4}{extension}",
+                usage.pattern, usage.name, usage.count
+            )?;
+        }
+        if self.warnings.is_empty() {
+            return writeln!(f, "warnings:     none");
+        }
+        writeln!(f, "warnings:")?;
+        for (addr, warning) in &self.warnings {
+            writeln!(f, "  {addr:03X}: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_analyze() {
+    let rom = [
+        0x00, 0xFF, // HIGH
+        0xA2, 0x00, // LD I, 0x200
+        0xF1, 0x55, // LD [I], V1, over the first instruction
+        0xD0, 0x12, // DRW V0, V1, 2 with I moved or not
+        0x81, 0x26, // SHR V1, V2
+        0x22, 0x10, // CALL 210
+        0x00, 0xEE, // RET without a call
+        0x00, 0x00, // data
+        0x12, 0x10, // JP 210, the subroutine never returns
+    ];
+    let report = analyze(&rom, 0x200);
+    assert_eq!(report.instructions, 8);
+    assert_eq!(report.data, 2);
+    assert_eq!(report.platform, Some(Platform::Schip));
+    let high = report.opcodes.iter().find(|usage| usage.pattern == "00FF");
+    assert_eq!(
+        high.and_then(|usage| usage.extension),
+        Some(Extension::Schip)
+    );
+    let warnings: Vec<String> = report
+        .warnings
+        .iter()
+        .map(|(addr, warning)| format!("{addr:03X}: {warning}"))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "204: LD [I], V1 writes over the code at 200-201",
+            "204: I is used at 206 after it, how far it moved depends on the memory quirk",
+            "208: SHR V1, V2 shifts VY on the COSMAC VIP and VX later on, the shift quirk",
+            "20C: RET is reached without a call",
+            "210: subroutine never returns, each call leaves an address on the stack",
+        ]
+    );
+}
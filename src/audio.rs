@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+// pitch register value that plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+// XO-CHIP sound, 128 bits played in a loop as a 1-bit waveform while the
+// sound timer runs. Set from memory by F002, its speed by FX3A.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioPattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // bits played per second, float powers need std
+    #[cfg(feature = "std")]
+    pub fn rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    // `phase` is the position within the whole pattern, from 0 to 1
+    pub fn sample(&self, phase: f32) -> bool {
+        let bit = ((phase * 128.0) as usize).min(127);
+        (self.bits[bit / 8] >> (7 - bit % 8)) & 1 == 1
+    }
+}
+
+#[test]
+fn test_audio_pattern() {
+    let mut pattern = AudioPattern {
+        bits: [0; 16],
+        pitch: DEFAULT_PITCH,
+    };
+    pattern.bits[0] = 0x80;
+    pattern.bits[15] = 0x01;
+    assert!(pattern.sample(0.0) && !pattern.sample(1.0 / 128.0));
+    assert!(pattern.sample(0.999));
+    assert_eq!(pattern.rate(), 4000.0);
+    // 48 steps is an octave
+    pattern.pitch = DEFAULT_PITCH + 48;
+    assert_eq!(pattern.rate(), 8000.0);
+}
@@ -0,0 +1,55 @@
+// Roms that come with emuchip, Octo sources under roms/ run as builtin:NAME.
+// The menu is what runs without a rom, it exits with the number of the
+// entry picked in V0
+pub const MENU: &str = "builtin:menu";
+
+// name, title and source, the menu lists the rest in this order
+const ROMS: [(&str, &str, &str); 4] = [
+    ("menu", "Menu", include_str!("../roms/menu.o8")),
+    ("maze", "Maze", include_str!("../roms/maze.o8")),
+    ("keypad", "Keypad", include_str!("../roms/keypad.o8")),
+    ("bounce", "Bounce", include_str!("../roms/bounce.o8")),
+];
+
+// title and source
+pub fn find(path: &str) -> Option<(&'static str, &'static str)> {
+    let name = path.strip_prefix("builtin:")?;
+    ROMS.iter()
+        .find(|(rom, _, _)| *rom == name)
+        .map(|(_, title, source)| (*title, *source))
+}
+
+// the rom the menu's entry stands for
+pub fn pick(entry: u8) -> Option<String> {
+    let (name, _, _) = ROMS.get(entry as usize).filter(|_| entry > 0)?;
+    Some(format!("builtin:{name}"))
+}
+
+#[test]
+fn test_menu_picks() {
+    use emuchip::{error::EmulatorError, octo, quirks::Quirks, Chip8};
+
+    for (name, _, source) in ROMS {
+        assert!(octo::assemble(source).is_ok(), "{name} doesn't assemble");
+    }
+    let (_, menu) = find(MENU).unwrap();
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&octo::assemble(menu).unwrap()).unwrap();
+    chip8.run_frame(1000).unwrap();
+    // three lines of text
+    let rows: Vec<bool> = (0..32)
+        .map(|y| (0..64).any(|x| chip8.framebuffer().get(x, y)))
+        .collect();
+    assert!(rows[4] && rows[13] && rows[22] && !rows[0] && !rows[31]);
+
+    // keys without an entry do nothing
+    chip8.set_key(7, true);
+    chip8.run_frame(100).unwrap();
+    chip8.set_key(7, false);
+    chip8.set_key(2, true);
+    let result = (0..100).try_for_each(|_| chip8.step().map(|_| ()));
+    assert!(matches!(result, Err(EmulatorError::Exited { .. })));
+    assert_eq!(pick(chip8.regs.get(0)).as_deref(), Some("builtin:keypad"));
+    assert_eq!(pick(0), None);
+    assert_eq!(pick(4), None);
+}
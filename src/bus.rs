@@ -0,0 +1,93 @@
+use crate::memory::Memory;
+
+// What instructions read and write data through, memory itself or a
+// peripheral claiming a range of addresses with Chip8::map. Instructions are
+// always fetched from memory, peripherals only see sprite reads, BCD, the
+// register loads and stores, and XO-CHIP audio patterns
+pub trait Bus: Send {
+    // None when nothing answers at addr
+    fn read(&mut self, addr: usize) -> Option<u8>;
+    // false when the write went nowhere
+    fn write(&mut self, addr: usize, val: u8) -> bool;
+}
+
+impl Bus for Memory {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        Memory::read(self, addr)
+    }
+
+    fn write(&mut self, addr: usize, val: u8) -> bool {
+        Memory::write(self, addr, val)
+    }
+}
+
+// A single byte handing every write to a function, e.g. a debug console
+// printing what the rom writes, or a status register a test harness waits
+// on. Reads give back the last byte written
+pub struct Port<F> {
+    on_write: F,
+    last: u8,
+}
+
+impl<F: FnMut(u8) + Send> Port<F> {
+    pub fn new(on_write: F) -> Self {
+        Self { on_write, last: 0 }
+    }
+}
+
+impl<F: FnMut(u8) + Send> Bus for Port<F> {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        (addr == 0).then_some(self.last)
+    }
+
+    fn write(&mut self, addr: usize, val: u8) -> bool {
+        if addr != 0 {
+            return false;
+        }
+        self.last = val;
+        (self.on_write)(val);
+        true
+    }
+}
+
+#[test]
+fn test_mapped_port() {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{quirks::Quirks, Chip8};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 2A, I = F00, store V0, V0 = 0, load V0, I = F01, BCD of V0
+    chip8
+        .load_rom(&[
+            0x60, 0x2A, 0xAF, 0x00, 0xF0, 0x55, 0x60, 0x00, 0xF0, 0x65, 0xAF, 0x01, 0xF0, 0x33,
+        ])
+        .unwrap();
+    let written = Arc::new(Mutex::new(vec![]));
+    let port = written.clone();
+    chip8.map(
+        0xF00..0xF01,
+        Port::new(move |byte| port.lock().unwrap().push(byte)),
+    );
+    for _ in 0..5 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(*written.lock().unwrap(), [0x2A]);
+    assert_eq!(chip8.regs.get(0), 0x2A);
+    assert_eq!(chip8.mem.read(0xF00), Some(0));
+
+    // BCD at F01 goes around the port into memory
+    for _ in 0..2 {
+        chip8.step().unwrap();
+    }
+    let digits: Vec<_> = (0xF01..0xF04).map(|addr| chip8.mem.read(addr)).collect();
+    assert_eq!(digits, [Some(0), Some(4), Some(2)]);
+
+    // the port mapped last wins, and a port only has the one byte
+    chip8.reset();
+    chip8.map(0xEFF..0xF01, Port::new(|_| {}));
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert!(chip8.step().is_err());
+    assert_eq!(written.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{
+    display::{FrameBuffer, HEIGHT, WIDTH},
+    screenshot::{rgb, upscale},
+};
+
+// GIF delays are in 1/100 s, anything shorter than this gets slowed down by viewers
+const MIN_DELAY: u64 = 2;
+
+// Records the screen to an animated GIF, one capture per display refresh.
+// Frames are only written once the screen changes, so static scenes stay small.
+pub struct GifCapture<W: Write> {
+    encoder: Encoder<W>,
+    scale: usize,
+    size: (u16, u16),
+    // screen waiting for its duration to be known, as palette indices
+    pending: Option<Vec<u8>>,
+    // display refreshes captured so far
+    frames: u64,
+    // hundredths of a second written out so far
+    written: u64,
+}
+
+impl GifCapture<BufWriter<File>> {
+    pub fn create(path: &str, scale: usize, palette: (u32, u32)) -> Result<Self, String> {
+        let file = File::create(path).map_err(|err| format!("could not create {path}: {err}"))?;
+        Self::new(BufWriter::new(file), scale, palette)
+    }
+}
+
+impl<W: Write> GifCapture<W> {
+    // `scale` is the size of a low resolution pixel, the palette is
+    // foreground and background as 0xRRGGBB
+    pub fn new(out: W, scale: usize, (foreground, background): (u32, u32)) -> Result<Self, String> {
+        let (width, height) = (WIDTH * scale, HEIGHT * scale);
+        // index 0 is the background, 1 the foreground
+        let palette = [rgb(background), rgb(foreground)].concat();
+        let encoder = Encoder::new(out, width as u16, height as u16, &palette)
+            .and_then(|mut encoder| encoder.set_repeat(Repeat::Infinite).map(|()| encoder))
+            .map_err(|err| format!("could not start gif: {err}"))?;
+        Ok(Self {
+            encoder,
+            scale,
+            size: (width as u16, height as u16),
+            pending: None,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    // call once per display refresh
+    pub fn capture(&mut self, fb: &FrameBuffer) -> Result<(), String> {
+        let (_, _, pixels) = upscale(fb, self.scale);
+        let pixels: Vec<u8> = pixels.into_iter().map(u8::from).collect();
+        if self.pending.as_ref() != Some(&pixels) {
+            // a screen shown too briefly to keep is replaced by the next one
+            if self.delay() >= MIN_DELAY {
+                self.flush()?;
+            }
+            self.pending = Some(pixels);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // writes out the last screen and hands back the output
+    pub fn finish(mut self) -> Result<W, String> {
+        self.flush()?;
+        self.encoder
+            .into_inner()
+            .map_err(|err| format!("could not finish gif: {err}"))
+    }
+
+    // time since the pending screen was first shown, rounding can put the
+    // written delays slightly ahead
+    fn delay(&self) -> u64 {
+        ((self.frames * 100 + 30) / 60).saturating_sub(self.written)
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let Some(pixels) = self.pending.take() else {
+            return Ok(());
+        };
+        let delay = self.delay().max(MIN_DELAY);
+        let (width, height) = self.size;
+        let mut frame = Frame::from_indexed_pixels(width, height, pixels, None);
+        frame.delay = delay as u16;
+        self.written += delay;
+        self.encoder
+            .write_frame(&frame)
+            .map_err(|err| format!("could not write gif frame: {err}"))
+    }
+}
+
+// Records every display refresh to an animated PNG at 60 frames a second.
+// Nothing is merged, so the same run always gives the same file. The header
+// holds the number of frames, so screens are kept until the end
+pub struct ApngCapture<W: Write> {
+    out: W,
+    scale: usize,
+    palette: (u32, u32),
+    screens: Vec<FrameBuffer>,
+}
+
+impl ApngCapture<BufWriter<File>> {
+    pub fn create(path: &str, scale: usize, palette: (u32, u32)) -> Result<Self, String> {
+        let file = File::create(path).map_err(|err| format!("could not create {path}: {err}"))?;
+        Ok(Self::new(BufWriter::new(file), scale, palette))
+    }
+}
+
+impl<W: Write> ApngCapture<W> {
+    // the same arguments as for GifCapture
+    pub fn new(out: W, scale: usize, palette: (u32, u32)) -> Self {
+        Self {
+            out,
+            scale,
+            palette,
+            screens: vec![],
+        }
+    }
+
+    // call once per display refresh
+    pub fn capture(&mut self, fb: &FrameBuffer) {
+        self.screens.push(fb.clone());
+    }
+
+    // writes out every screen and hands back the output
+    pub fn finish(mut self) -> Result<W, String> {
+        let err = |err: png::EncodingError| format!("could not write apng: {err}");
+        // an animation needs a frame, a run without any gets a blank screen
+        if self.screens.is_empty() {
+            self.screens.push(FrameBuffer::new());
+        }
+        let (width, height) = (WIDTH * self.scale, HEIGHT * self.scale);
+        let (foreground, background) = self.palette;
+        let mut encoder = png::Encoder::new(&mut self.out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette([rgb(background), rgb(foreground)].concat());
+        encoder
+            .set_animated(self.screens.len() as u32, 0)
+            .map_err(err)?;
+        encoder.set_frame_delay(1, 60).map_err(err)?;
+        let mut writer = encoder.write_header().map_err(err)?;
+        for fb in &self.screens {
+            let (_, _, pixels) = upscale(fb, self.scale);
+            let pixels: Vec<u8> = pixels.into_iter().map(u8::from).collect();
+            writer.write_image_data(&pixels).map_err(err)?;
+        }
+        writer.finish().map_err(err)?;
+        Ok(self.out)
+    }
+}
+
+#[test]
+fn test_capture_merges_static_frames() {
+    let mut fb = FrameBuffer::new();
+    let mut capture = GifCapture::new(vec![], 1, (0xFFFFFF, 0x000000)).unwrap();
+    // one second blank, one second with a pixel lit
+    for frame in 0..120 {
+        if frame == 60 {
+            fb.paint(0, 0, vec![0x80], false);
+        }
+        capture.capture(&fb).unwrap();
+    }
+    let bytes = capture.finish().unwrap();
+
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(bytes.as_slice())
+        .unwrap();
+    let mut delays = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, vec![100, 100]);
+}
+
+#[test]
+fn test_apng_keeps_every_frame() {
+    let record = || {
+        let mut fb = FrameBuffer::new();
+        let mut capture = ApngCapture::new(vec![], 1, (0xFFFFFF, 0x000000));
+        for frame in 0..3 {
+            if frame == 2 {
+                fb.paint(0, 0, vec![0x80], false);
+            }
+            capture.capture(&fb);
+        }
+        capture.finish().unwrap()
+    };
+    let bytes = record();
+    assert_eq!(bytes, record());
+
+    let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+    let control = reader.info().animation_control.unwrap();
+    assert_eq!(control.num_frames, 3);
+    let mut data = vec![0; reader.output_buffer_size()];
+    let mut first = vec![];
+    for _ in 0..3 {
+        reader.next_frame(&mut data).unwrap();
+        first.push(data[0]);
+    }
+    assert_eq!(first, [0, 0, 1]);
+}
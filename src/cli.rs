@@ -0,0 +1,455 @@
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+use emuchip::{
+    coverage::CoverageFormat,
+    database::RomInfo,
+    quirks::{MachineRoutines, OutOfBounds, Platform, Quirks},
+    trace::TraceFormat,
+};
+
+use crate::{
+    config::{self, Config},
+    crt::Crt,
+    frontend::{Keymap, Layout},
+    sound::{Tone, Waveform},
+};
+
+// instructions per second when neither --speed nor the ROM database says
+pub const DEFAULT_SPEED: u32 = 700;
+
+#[derive(Parser)]
+#[command(name = "emuchip", version, about = "A CHIP-8 emulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM in a window or the terminal
+    Run(RunArgs),
+    /// Print a disassembly of a ROM
+    Disasm {
+        rom: String,
+        /// Symbol file with names for addresses, a hex address and a name on
+        /// each line. ROMs assembled from Octo source bring their labels
+        #[arg(long, value_name = "FILE")]
+        symbols: Option<String>,
+        // for where the ROM loads
+        #[command(flatten)]
+        quirks: QuirkArgs,
+    },
+    /// Assemble Octo source into a ROM
+    Assemble {
+        source: String,
+        /// Where the ROM is written
+        #[arg(long, short)]
+        output: String,
+        /// Also write where the labels ended up to a symbol file, for
+        /// --symbols
+        #[arg(long, value_name = "FILE")]
+        symbols: Option<String>,
+    },
+    /// Print information about a ROM
+    Info {
+        rom: String,
+        #[command(flatten)]
+        quirks: QuirkArgs,
+    },
+    /// Report the opcodes a ROM uses, what in it may go wrong or depend on
+    /// quirks and the platform it most likely wants
+    Analyze {
+        rom: String,
+        #[command(flatten)]
+        quirks: QuirkArgs,
+    },
+    /// Run a ROM without a window or audio and print the final screen and
+    /// its hash, this is the headless mode, also run as `emuchip headless`
+    #[command(visible_alias = "headless")]
+    Test(TestArgs),
+    /// Print the differences between two savestates
+    Statediff { from: String, to: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Backend {
+    /// A desktop window
+    Window,
+    /// Unicode blocks in the terminal
+    #[cfg(feature = "tui")]
+    Tui,
+    /// A window drawn with wgpu, synced to the display
+    #[cfg(feature = "gpu")]
+    Gpu,
+    /// An SDL2 window, with SDL2 audio
+    #[cfg(feature = "sdl2")]
+    Sdl2,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DumpFormat {
+    Png,
+    /// Binary PPM, readable without a decoder
+    Ppm,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// ROMs to run, each in its own window, a menu of the built-in ROMs runs
+    /// without one. - reads a ROM from stdin, http(s) URLs are downloaded
+    /// with curl, which has to be installed, and builtin:maze, builtin:keypad and builtin:bounce come with emuchip
+    pub roms: Vec<String>,
+    /// Where the screen is shown and keys are read from
+    #[arg(long, value_enum, default_value_t = Backend::Window)]
+    pub backend: Backend,
+    /// Instructions per second, + and - change it while running,
+    /// 700 unless the ROM database knows better
+    #[arg(long)]
+    pub speed: Option<u32>,
+    /// Size of a low resolution pixel on screen to start with, resizing the
+    /// window picks the largest whole number that fits
+    #[arg(long, default_value_t = 16, value_parser = parse_scale)]
+    pub scale: usize,
+    /// Foreground and background colors as hex, e.g. aaccff,222233
+    #[arg(long, value_parser = parse_palette)]
+    pub palette: Option<(u32, u32)>,
+    /// Keyboard layout the keypad is laid out for, the sdl2 backend reads
+    /// keys by where they are and goes by QWERTY
+    #[arg(long, value_enum, default_value_t = Layout::Qwerty)]
+    pub layout: Layout,
+    /// Remap keypad keys on top of the layout, e.g. 5=Up,8=Down,4=Left,6=Right
+    #[arg(long, value_name = "KEYPAD=KEY,...", value_parser = parse_keymap)]
+    pub keymap: Option<String>,
+    /// Start fullscreen, F11 toggles it while running; the window backend can
+    /// only drop its borders
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Show a hex keypad over the screen to click or touch, window and gpu
+    /// backends only, F5 toggles it while running
+    #[arg(long)]
+    pub keypad: bool,
+    /// Retro screen effects for the window and gpu backends, F9 toggles them,
+    /// e.g. scanlines,glow or all
+    #[arg(long, value_name = "EFFECT,...")]
+    pub crt: Option<Crt>,
+    /// Shape of the buzzer tone
+    #[arg(long, value_enum, default_value_t = Tone::default().waveform)]
+    pub waveform: Waveform,
+    /// Pitch of the buzzer in Hz
+    #[arg(long, default_value_t = Tone::default().frequency, value_parser = parse_frequency)]
+    pub frequency: f32,
+    /// Buzzer volume in percent, M mutes it while running
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: u8,
+    /// Sound output device by name or by its number in the list the error
+    /// prints, the system default unless given
+    #[arg(long, value_name = "NAME|INDEX")]
+    pub audio_device: Option<String>,
+    /// Run at this percentage of full speed, timers included, F6 toggles it
+    /// while running; 10 when toggled on without it
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u32).range(1..=100))]
+    pub slow: Option<u32>,
+    /// Seconds of history kept for rewinding with Backspace, 0 to disable
+    #[arg(long, default_value_t = 30)]
+    pub rewind: usize,
+    /// Record keypad input to a replay file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<String>,
+    /// Play back keypad input from a replay file
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<String>,
+    /// Continue from where the ROM was when last closed, the state is saved
+    /// next to it on every exit, or in ~/.local/share/emuchip for stdin,
+    /// builtin and downloaded ROMs
+    #[arg(long, conflicts_with_all = ["record", "replay", "host", "join"])]
+    pub resume: bool,
+    /// Start paused with a debug console on stdin
+    #[arg(long)]
+    pub debug: bool,
+    /// Seed for the random number generator, so a run can be repeated exactly,
+    /// random unless given
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Reset and reload the ROM whenever the file changes
+    #[arg(long, conflicts_with_all = ["record", "replay"])]
+    pub watch: bool,
+    /// Count executed instructions and write a report of the hot spots to a
+    /// file on exit, F8 writes it while running
+    #[arg(long, value_name = "FILE")]
+    pub profile: Option<String>,
+    /// Track which ROM addresses run and which bytes are read and written,
+    /// and write a map of it to a file on exit
+    #[arg(long, value_name = "FILE")]
+    pub coverage: Option<String>,
+    /// How the coverage map is written: text with a map of the ROM, or json
+    /// for address ranges
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub coverage_format: CoverageFormat,
+    /// Share control with a peer over the network, waiting on an address for
+    /// them to join, e.g. 0.0.0.0:7777
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["join", "record", "replay", "watch", "debug", "slow"])]
+    pub host: Option<String>,
+    /// Join a peer started with --host, running the same ROM
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["record", "replay", "watch", "debug", "slow"])]
+    pub join: Option<String>,
+    /// Serve a WebSocket API on an address, e.g. 127.0.0.1:9000, streaming
+    /// the screen and taking debugger console commands and key presses
+    #[arg(long, value_name = "ADDR")]
+    pub remote: Option<String>,
+    #[command(flatten)]
+    pub quirks: QuirkArgs,
+    #[command(flatten)]
+    pub trace: TraceArgs,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    /// ROM file, - for stdin or an http(s) URL, downloaded with curl
+    pub rom: String,
+    /// Number of 60Hz frames to run for
+    #[arg(long, default_value_t = 60)]
+    pub frames: u32,
+    /// Run for this many instructions instead of a number of frames
+    #[arg(long, value_name = "N", conflicts_with = "frames")]
+    pub max_cycles: Option<u64>,
+    /// Stop once the ROM jumps to itself, the usual way of halting
+    #[arg(long)]
+    pub exit_on_infinite_loop: bool,
+    /// Instructions per second, 700 unless the ROM database knows better
+    #[arg(long)]
+    pub speed: Option<u32>,
+    /// Also save the final screen as a PNG
+    #[arg(long, value_name = "FILE")]
+    pub screenshot: Option<String>,
+    /// Record the whole run to an animated GIF
+    #[arg(long, value_name = "FILE")]
+    pub gif: Option<String>,
+    /// Record every frame to an animated PNG, the same run always gives the
+    /// same file
+    #[arg(long, value_name = "FILE")]
+    pub apng: Option<String>,
+    /// Write every frame to a numbered image in a directory, byte for byte
+    /// the same on every run
+    #[arg(long, value_name = "DIR")]
+    pub dump_frames: Option<String>,
+    /// Image format of --dump-frames
+    #[arg(long, value_enum, default_value_t = DumpFormat::Png)]
+    pub dump_format: DumpFormat,
+    /// Seed for the random number generator
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Fail unless the final screen has this SHA-1, as printed by a good run
+    #[arg(long, value_name = "SHA1")]
+    pub expect: Option<String>,
+    /// Print every byte the ROM writes to this address as text
+    #[arg(long, value_name = "ADDR", value_parser = parse_port)]
+    pub console: Option<u16>,
+    /// Stop once the ROM writes its result to this address, and fail unless
+    /// it writes 0
+    #[arg(long, value_name = "ADDR", value_parser = parse_port)]
+    pub status: Option<u16>,
+    /// Compare the state before every instruction with a JSON or binary
+    /// trace, from an earlier run or another emulator at the same speed and
+    /// without filters, and fail at the first difference
+    #[arg(long, value_name = "TRACE")]
+    pub against: Option<String>,
+    /// Write a report of the hot spots of the run to a file
+    #[arg(long, value_name = "FILE")]
+    pub profile: Option<String>,
+    /// Write a map of the ROM addresses that ran and the bytes read and
+    /// written to a file
+    #[arg(long, value_name = "FILE")]
+    pub coverage: Option<String>,
+    /// How the coverage map is written: text or json
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub coverage_format: CoverageFormat,
+    #[command(flatten)]
+    pub quirks: QuirkArgs,
+    #[command(flatten)]
+    pub trace: TraceArgs,
+}
+
+#[derive(Args)]
+pub struct TraceArgs {
+    /// Log every executed instruction to stdout
+    #[arg(long)]
+    pub trace: bool,
+    /// Log every executed instruction to a file instead
+    #[arg(long, value_name = "FILE")]
+    pub trace_file: Option<String>,
+    /// Only trace instructions in an address range, e.g. 200-2FF
+    #[arg(long, value_name = "START-END", value_parser = parse_range)]
+    pub trace_range: Option<(u16, u16)>,
+    /// Only trace these mnemonics, e.g. DRW,JP
+    #[arg(long, value_name = "OPS", value_delimiter = ',')]
+    pub trace_ops: Vec<String>,
+    /// How traced instructions are written: text, json for a JSON object per
+    /// line, or binary for 22 byte records of PC, opcode, V0-VF and I
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub trace_format: TraceFormat,
+    /// Write the whole machine state as JSON to a file when the ROM crashes,
+    /// as the debugger's dump command does
+    #[arg(long, value_name = "FILE")]
+    pub dump_on_crash: Option<String>,
+    /// Symbol file with names for addresses, shown in traces and the
+    /// debugger instead of hex. ROMs assembled from Octo source bring their
+    /// labels
+    #[arg(long, value_name = "FILE")]
+    pub symbols: Option<String>,
+}
+
+#[derive(Args)]
+pub struct QuirkArgs {
+    /// Start from the quirks of an interpreter
+    #[arg(long)]
+    pub platform: Option<Platform>,
+    /// Enable a quirk: shift, memory, vf-reset, jump, wrap, display-wait,
+    /// index-overflow, key-release
+    #[arg(long = "quirk", value_name = "NAME")]
+    pub enable: Vec<String>,
+    /// Disable a quirk
+    #[arg(long = "no-quirk", value_name = "NAME")]
+    pub disable: Vec<String>,
+    /// Nested subroutine calls allowed before a stack overflow
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+    pub stack_depth: Option<u8>,
+    /// What memory accesses past 0xFFF do: wrap, clamp or error
+    #[arg(long, value_name = "BEHAVIOR")]
+    pub out_of_bounds: Option<OutOfBounds>,
+    /// What 0NNN calls into COSMAC VIP machine code do: halt with an error,
+    /// or skip them with a warning
+    #[arg(long, value_name = "BEHAVIOR", default_value = "halt")]
+    pub machine_routines: MachineRoutines,
+    /// Where the ROM is loaded and starts running, as hex, 200 unless the
+    /// platform says otherwise, e.g. 600 for the eti660
+    #[arg(long, value_name = "ADDR", value_parser = parse_load_addr)]
+    pub load_addr: Option<u16>,
+    /// Look the ROM up in a chip-8-database programs.json for its platform,
+    /// speed and colors, the flags above still win
+    #[arg(long, value_name = "FILE")]
+    pub database: Option<String>,
+    /// Settings from a TOML file in place of ~/.config/emuchip/config.toml,
+    /// defaults for the flags and [roms."pong.ch8"] sections keyed by file
+    /// name or SHA-1 that win over the database
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<String>,
+}
+
+impl Cli {
+    // flags win over the config file, which wins over the defaults here
+    pub fn parse_with_config() -> Result<Self, String> {
+        let cli = Self::parse();
+        let given = match &cli.command {
+            Command::Run(args) => args.quirks.config.clone(),
+            Command::Test(args) => args.quirks.config.clone(),
+            _ => return Ok(cli),
+        };
+        let path = given.or_else(|| {
+            config::default_path()
+                .filter(|path| path.exists())
+                .map(|path| path.display().to_string())
+        });
+        let Some(path) = path else {
+            return Ok(cli);
+        };
+        let matches = Config::load(&path)?
+            .flag_defaults(Self::command(), &path)
+            .get_matches();
+        Self::from_arg_matches(&matches).map_err(|err| err.to_string())
+    }
+}
+
+impl RunArgs {
+    pub fn tone(&self) -> Tone {
+        Tone {
+            waveform: self.waveform,
+            frequency: self.frequency,
+            volume: self.volume as f32 / 100.0,
+        }
+    }
+}
+
+impl QuirkArgs {
+    // individual quirk flags are applied on top of the platform preset, or the
+    // quirks the database has for the rom
+    pub fn quirks(&self, known: Option<&RomInfo>) -> Result<Quirks, String> {
+        let mut quirks = self
+            .platform
+            .map(Quirks::from_platform)
+            .or(known.and_then(|known| known.quirks))
+            .unwrap_or_default();
+        for name in &self.enable {
+            quirks.set(name, true)?;
+        }
+        for name in &self.disable {
+            quirks.set(name, false)?;
+        }
+        if let Some(depth) = self.stack_depth {
+            quirks.stack_depth = depth;
+        }
+        if let Some(out_of_bounds) = self.out_of_bounds {
+            quirks.out_of_bounds = out_of_bounds;
+        }
+        if let Some(addr) = self.load_addr {
+            quirks.load_addr = addr;
+        }
+        Ok(quirks)
+    }
+}
+
+fn parse_scale(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(scale @ (2 | 4 | 8 | 16 | 32 | 64)) => Ok(scale),
+        _ => Err("scale must be one of 2, 4, 8, 16, 32, 64".to_string()),
+    }
+}
+
+// checked here, applied once the layout is known
+fn parse_keymap(s: &str) -> Result<String, String> {
+    s.parse::<Keymap>()?;
+    Ok(s.to_string())
+}
+
+fn parse_frequency(s: &str) -> Result<f32, String> {
+    match s.parse() {
+        Ok(hz @ 20.0..=20000.0) => Ok(hz),
+        _ => Err("frequency must be between 20 and 20000 Hz".to_string()),
+    }
+}
+
+fn parse_addr(addr: &str) -> Result<u16, String> {
+    u16::from_str_radix(addr.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid address '{addr}'"))
+}
+
+fn parse_load_addr(s: &str) -> Result<u16, String> {
+    match parse_addr(s)? {
+        addr @ 0..=0xFFE => Ok(addr),
+        _ => Err("the ROM has to load below FFF".to_string()),
+    }
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    match parse_addr(s)? {
+        addr @ 0..=0xFFF => Ok(addr),
+        _ => Err("the address has to be in memory, below 1000".to_string()),
+    }
+}
+
+fn parse_range(s: &str) -> Result<(u16, u16), String> {
+    match s.split_once('-') {
+        Some((start, end)) => Ok((parse_addr(start)?, parse_addr(end)?)),
+        None => Err("range must look like 200-2FF".to_string()),
+    }
+}
+
+pub fn parse_palette(s: &str) -> Result<(u32, u32), String> {
+    let parse_color = |color: &str| {
+        u32::from_str_radix(color.trim_start_matches('#'), 16)
+            .map_err(|_| format!("invalid color '{color}'"))
+    };
+    match s.split_once(',') {
+        Some((fg, bg)) => Ok((parse_color(fg)?, parse_color(bg)?)),
+        None => Err("palette must be two colors, e.g. aaccff,222233".to_string()),
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Command;
+use serde::Deserialize;
+
+use emuchip::{
+    database::RomInfo,
+    quirks::{Platform, Quirks},
+};
+
+use crate::cli::parse_palette;
+
+// Settings from a TOML file, defaults for the flags of the same name and
+// sections for specific roms keyed by file name or SHA-1, e.g.
+//
+//   scale = 8
+//   volume = 50
+//   speed = 1000
+//
+//   [roms."pong.ch8"]
+//   speed = 500
+//   platform = "schip"
+//   quirks = ["wrap"]
+//   no-quirks = ["display-wait"]
+//   palette = "aaccff,222233"
+//   keymap = "5=Up,8=Down"
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    scale: Option<usize>,
+    layout: Option<String>,
+    waveform: Option<String>,
+    frequency: Option<f32>,
+    volume: Option<u8>,
+    audio_device: Option<String>,
+    // for every rom, under what the ROM database has
+    speed: Option<u32>,
+    platform: Option<String>,
+    #[serde(default)]
+    quirks: Vec<String>,
+    #[serde(default)]
+    no_quirks: Vec<String>,
+    palette: Option<String>,
+    keymap: Option<String>,
+    #[serde(default)]
+    roms: HashMap<String, RomConfig>,
+}
+
+// What a rom section overrides, on top of the ROM database and under the flags
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RomConfig {
+    speed: Option<u32>,
+    platform: Option<String>,
+    #[serde(default)]
+    quirks: Vec<String>,
+    #[serde(default)]
+    no_quirks: Vec<String>,
+    palette: Option<String>,
+    keymap: Option<String>,
+}
+
+// $XDG_CONFIG_HOME/emuchip/config.toml, or under ~/.config without it
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("emuchip").join("config.toml"))
+}
+
+// $XDG_DATA_HOME/emuchip, or under ~/.local/share without it
+pub fn data_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(base.join("emuchip"))
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.message().to_string())
+    }
+
+    // the file's values become the defaults of the flags, which still win
+    // when given, `path` is kept for looking up rom sections
+    pub fn flag_defaults(&self, command: Command, path: &str) -> Command {
+        let run = [
+            ("scale", self.scale.map(|scale| scale.to_string())),
+            ("layout", self.layout.clone()),
+            ("waveform", self.waveform.clone()),
+            ("frequency", self.frequency.map(|hz| hz.to_string())),
+            ("volume", self.volume.map(|volume| volume.to_string())),
+            ("audio_device", self.audio_device.clone()),
+            ("config", Some(path.to_string())),
+        ];
+        let set = |command: Command, (id, value): &(&str, Option<String>)| match value {
+            Some(value) => command.mut_arg(*id, |arg| arg.default_value(value.clone())),
+            None => command,
+        };
+        command
+            .mut_subcommand("run", |command| run.iter().fold(command, set))
+            .mut_subcommand("test", |command| set(command, &run[run.len() - 1]))
+    }
+
+    // by hash first, so a copy under another name still matches
+    fn rom(&self, path: &str, sha1: &str) -> Option<&RomConfig> {
+        let file = Path::new(path).file_name()?.to_string_lossy();
+        self.roms
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(sha1))
+            .or_else(|| self.roms.iter().find(|(key, _)| **key == file))
+            .map(|(_, rom)| rom)
+    }
+
+    // the settings for every rom, then what the database has on it, then its
+    // own section
+    pub fn rom_info(
+        &self,
+        known: Option<RomInfo>,
+        path: &str,
+        sha1: &str,
+    ) -> Result<RomInfo, String> {
+        let everywhere = RomConfig {
+            speed: self.speed,
+            platform: self.platform.clone(),
+            quirks: self.quirks.clone(),
+            no_quirks: self.no_quirks.clone(),
+            palette: self.palette.clone(),
+            keymap: None,
+        };
+        let mut info = everywhere.apply(None, path)?;
+        if let Some(known) = known {
+            info = RomInfo {
+                title: known.title,
+                quirks: known.quirks.or(info.quirks),
+                speed: known.speed.or(info.speed),
+                palette: known.palette.or(info.palette),
+            };
+        }
+        match self.rom(path, sha1) {
+            Some(rom) => rom.apply(Some(info), path),
+            None => Ok(info),
+        }
+    }
+
+    // the rom's own remapping goes on top of the one for every rom
+    pub fn keymap(&self, path: &str, sha1: &str) -> String {
+        let rom = self.rom(path, sha1).and_then(|rom| rom.keymap.as_deref());
+        [self.keymap.as_deref(), rom]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl RomConfig {
+    // `known` is what there is so far, `path` names the rom when there's
+    // nothing
+    fn apply(&self, known: Option<RomInfo>, path: &str) -> Result<RomInfo, String> {
+        let mut info = known.unwrap_or_else(|| RomInfo {
+            title: Path::new(path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            quirks: None,
+            speed: None,
+            palette: None,
+        });
+        if let Some(platform) = &self.platform {
+            info.quirks = Some(Quirks::from_platform(platform.parse::<Platform>()?));
+        }
+        if !self.quirks.is_empty() || !self.no_quirks.is_empty() {
+            let mut quirks = info.quirks.unwrap_or_default();
+            for name in &self.quirks {
+                quirks.set(name, true)?;
+            }
+            for name in &self.no_quirks {
+                quirks.set(name, false)?;
+            }
+            info.quirks = Some(quirks);
+        }
+        if let Some(palette) = &self.palette {
+            info.palette = Some(parse_palette(palette)?);
+        }
+        info.speed = self.speed.or(info.speed);
+        Ok(info)
+    }
+}
+
+#[test]
+fn test_rom_overrides() {
+    let config = Config::parse(
+        r#"
+        speed = 900
+        palette = "111111,222222"
+        keymap = "1=X"
+
+        [roms."pong.ch8"]
+        speed = 1000
+        platform = "schip"
+        no-quirks = ["wrap"]
+        keymap = "5=Up"
+
+        [roms.A9993E364706816ABA3E25717850C26C9CD0D89D]
+        palette = "ffffff,000000"
+        "#,
+    )
+    .unwrap();
+    let known = RomInfo {
+        title: "Pong".to_string(),
+        quirks: None,
+        speed: Some(500),
+        palette: Some((1, 2)),
+    };
+    let info = config
+        .rom_info(Some(known.clone()), "roms/pong.ch8", "0000")
+        .unwrap();
+    let mut schip = Quirks::from_platform(Platform::Schip);
+    schip.wrap_sprites = false;
+    assert_eq!(info.title, "Pong");
+    assert_eq!(info.quirks, Some(schip));
+    assert_eq!(info.speed, Some(1000));
+    assert_eq!(info.palette, Some((1, 2)));
+    assert_eq!(config.keymap("roms/pong.ch8", "0000"), "1=X,5=Up");
+
+    // the database wins over the settings for every rom
+    let info = config
+        .rom_info(Some(known), "roms/tetris.ch8", "0000")
+        .unwrap();
+    assert_eq!((info.speed, info.palette), (Some(500), Some((1, 2))));
+    let info = config.rom_info(None, "roms/tetris.ch8", "0000").unwrap();
+    assert_eq!(info.title, "tetris.ch8");
+    assert_eq!((info.speed, info.quirks), (Some(900), None));
+    assert_eq!(config.keymap("roms/tetris.ch8", "0000"), "1=X");
+
+    // by hash under any name, in any case
+    let info = config
+        .rom_info(None, "abc.ch8", "a9993e364706816aba3e25717850c26c9cd0d89d")
+        .unwrap();
+    assert_eq!(info.palette, Some((0xFFFFFF, 0)));
+
+    assert!(Config::parse("[roms.x]\nspeeed = 1").is_err());
+    assert!(Config::parse("volum = 1").is_err());
+}
+
+#[test]
+fn test_flag_defaults() {
+    use clap::{CommandFactory, FromArgMatches};
+
+    use crate::cli::{Cli, Command as Subcommand};
+
+    let config = Config::parse("scale = 8\nvolume = 50\nlayout = \"azerty\"").unwrap();
+    let command = config.flag_defaults(Cli::command(), "emuchip.toml");
+    let matches = command.get_matches_from(["emuchip", "run", "pong.ch8", "--volume", "20"]);
+    let Subcommand::Run(args) = Cli::from_arg_matches(&matches).unwrap().command else {
+        unreachable!()
+    };
+    assert_eq!((args.scale, args.volume), (8, 20));
+    assert!(matches!(args.layout, crate::frontend::Layout::Azerty));
+    assert_eq!(args.quirks.config.as_deref(), Some("emuchip.toml"));
+}
@@ -0,0 +1,211 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{ops::Range, str::FromStr};
+
+use serde::Serialize;
+
+use crate::memory::{TypeAddr, MEMORY_SIZE};
+
+const EXECUTED: u8 = 1;
+const READ: u8 = 2;
+const WRITTEN: u8 = 4;
+
+// bytes on each line of the text map
+const ROW: usize = 32;
+
+// How a coverage map is written
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CoverageFormat {
+    // a summary and a map for people to read
+    #[default]
+    Text,
+    // address ranges, for scripts
+    Json,
+}
+
+impl FromStr for CoverageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown coverage format '{s}'")),
+        }
+    }
+}
+
+// The JSON coverage map, ranges are inclusive
+#[derive(Serialize)]
+struct Map {
+    rom: [TypeAddr; 2],
+    executed: Vec<[TypeAddr; 2]>,
+    read: Vec<[TypeAddr; 2]>,
+    written: Vec<[TypeAddr; 2]>,
+    // of the rom, nothing ran, read or wrote there
+    untouched: Vec<[TypeAddr; 2]>,
+}
+
+// Which addresses ran as instructions and which bytes were read and written
+// over a session, for finding what a rom never got to
+pub struct Coverage {
+    // EXECUTED, READ and WRITTEN bits per address
+    marks: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            marks: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    // both bytes of the instruction at pc
+    pub fn executed(&mut self, pc: TypeAddr) {
+        self.mark(pc as usize, EXECUTED);
+        self.mark(pc as usize + 1, EXECUTED);
+    }
+
+    pub fn read(&mut self, addr: usize) {
+        self.mark(addr, READ);
+    }
+
+    pub fn written(&mut self, addr: usize) {
+        self.mark(addr, WRITTEN);
+    }
+
+    fn mark(&mut self, addr: usize, bit: u8) {
+        if let Some(marks) = self.marks.get_mut(addr) {
+            *marks |= bit;
+        }
+    }
+
+    // inclusive ranges of the addresses within `within` whose marks pass
+    fn ranges(&self, within: Range<usize>, pass: impl Fn(u8) -> bool) -> Vec<[TypeAddr; 2]> {
+        let mut ranges: Vec<[TypeAddr; 2]> = vec![];
+        for addr in within {
+            if !pass(self.marks[addr]) {
+                continue;
+            }
+            let addr = addr as TypeAddr;
+            match ranges.last_mut() {
+                Some(range) if range[1] + 1 == addr => range[1] = addr,
+                _ => ranges.push([addr, addr]),
+            }
+        }
+        ranges
+    }
+
+    // what of the rom loaded at `rom` was run, read, written or left alone,
+    // with a map of it
+    pub fn report(&self, rom: Range<TypeAddr>) -> String {
+        let rom = rom.start as usize..(rom.end as usize).min(MEMORY_SIZE);
+        let size = rom.len();
+        let count = |pass: &dyn Fn(u8) -> bool| {
+            let count = self.marks[rom.clone()]
+                .iter()
+                .filter(|&&marks| pass(marks))
+                .count();
+            format!(
+                "{count:>5} bytes {:>5.1}%",
+                count as f64 * 100.0 / size.max(1) as f64
+            )
+        };
+        let mut out = format!(
+            "rom: {:03X}-{:03X}, {size} bytes\n",
+            rom.start,
+            rom.end.saturating_sub(1)
+        );
+        out += &format!("executed:  {}\n", count(&|marks| marks & EXECUTED != 0));
+        out += &format!("read:      {}\n", count(&|marks| marks & READ != 0));
+        out += &format!("written:   {}\n", count(&|marks| marks & WRITTEN != 0));
+        out += &format!("untouched: {}\n", count(&|marks| marks == 0));
+
+        out += "\nuntouched\n";
+        for [start, end] in self.ranges(rom.clone(), |marks| marks == 0) {
+            out += &format!("  {start:03X}-{end:03X}\n");
+        }
+
+        out += "\nmap, x executed, w written, r read, . untouched\n";
+        for row in rom.clone().step_by(ROW) {
+            let line: String = (row..(row + ROW).min(rom.end))
+                .map(|addr| match self.marks[addr] {
+                    marks if marks & EXECUTED != 0 => 'x',
+                    marks if marks & WRITTEN != 0 => 'w',
+                    marks if marks & READ != 0 => 'r',
+                    _ => '.',
+                })
+                .collect();
+            out += &format!("  {row:03X}  {line}\n");
+        }
+        out
+    }
+
+    // the ranges of all of memory, and of the rom the untouched ones
+    pub fn to_json(&self, rom: Range<TypeAddr>) -> String {
+        let all = 0..MEMORY_SIZE;
+        let map = Map {
+            rom: [rom.start, rom.end.saturating_sub(1)],
+            executed: self.ranges(all.clone(), |marks| marks & EXECUTED != 0),
+            read: self.ranges(all.clone(), |marks| marks & READ != 0),
+            written: self.ranges(all, |marks| marks & WRITTEN != 0),
+            untouched: self.ranges(
+                rom.start as usize..(rom.end as usize).min(MEMORY_SIZE),
+                |marks| marks == 0,
+            ),
+        };
+        serde_json::to_string(&map).expect("a coverage map is serializable")
+    }
+
+    pub fn export(&self, rom: Range<TypeAddr>, format: CoverageFormat) -> String {
+        match format {
+            CoverageFormat::Text => self.report(rom),
+            CoverageFormat::Json => self.to_json(rom) + "\n",
+        }
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_coverage() {
+    let mut coverage = Coverage::new();
+    // two instructions, a sprite read and a variable written, out of 8 bytes
+    coverage.executed(0x200);
+    coverage.executed(0x202);
+    coverage.read(0x204);
+    coverage.written(0x206);
+    coverage.written(0xF00);
+
+    let report = coverage.report(0x200..0x208);
+    let lines: Vec<String> = report
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        lines[..5],
+        [
+            "rom: 200-207, 8 bytes",
+            "executed: 4 bytes 50.0%",
+            "read: 1 bytes 12.5%",
+            "written: 1 bytes 12.5%",
+            "untouched: 2 bytes 25.0%",
+        ]
+    );
+    assert!(lines.contains(&"205-205".to_string()));
+    assert!(lines.contains(&"200 xxxxr.w.".to_string()));
+
+    let json: serde_json::Value = serde_json::from_str(&coverage.to_json(0x200..0x208)).unwrap();
+    assert_eq!(json["executed"], serde_json::json!([[0x200, 0x203]]));
+    assert_eq!(
+        json["written"],
+        serde_json::json!([[0x206, 0x206], [0xF00, 0xF00]])
+    );
+    assert_eq!(
+        json["untouched"],
+        serde_json::json!([[0x205, 0x205], [0x207, 0x207]])
+    );
+}
@@ -0,0 +1,216 @@
+use std::{ops::Range, str::FromStr};
+
+use emuchip::display::FrameBuffer;
+
+// how far the corners bulge out, 0 is flat
+const CURVATURE: f32 = 0.06;
+// how much a lit neighbour lights up a dark pixel
+const GLOW: f32 = 0.12;
+
+// Retro tube look for the backends that draw their own pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crt {
+    // darkens the bottom of every row of pixels
+    pub scanlines: bool,
+    // darkens the right edge of every pixel as well
+    pub grid: bool,
+    // lit pixels bleed into their neighbours
+    pub glow: bool,
+    // bends the screen like the front of a tube
+    pub curvature: bool,
+}
+
+impl Crt {
+    pub const OFF: Crt = Crt {
+        scanlines: false,
+        grid: false,
+        glow: false,
+        curvature: false,
+    };
+
+    // Plain pixels for just the screen rows in `rows`, the rest of `out` is
+    // left alone. The effects reach across rows, so there's no such thing with
+    // them on. Returns the rows of `out` drawn
+    pub fn render_rows(
+        fb: &FrameBuffer,
+        (foreground, background): (u32, u32),
+        out: &mut [u32],
+        width: usize,
+        height: usize,
+        rows: Range<usize>,
+    ) -> Range<usize> {
+        // every row of out showing one of the screen rows
+        let out_rows = (rows.start * height).div_ceil(fb.height())
+            ..(rows.end * height).div_ceil(fb.height()).min(height);
+        for y in out_rows.clone() {
+            let row = &mut out[y * width..(y + 1) * width];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let lit = fb.get(x * fb.width() / width, y * fb.height() / height);
+                *pixel = if lit { foreground } else { background };
+            }
+        }
+        out_rows
+    }
+
+    // Draws the screen into `out`, `width` x `height` pixels as 0xRRGGBB, the
+    // palette is foreground and background
+    pub fn render(
+        &self,
+        fb: &FrameBuffer,
+        (foreground, background): (u32, u32),
+        out: &mut [u32],
+        width: usize,
+        height: usize,
+    ) {
+        if *self == Crt::OFF {
+            let rows = 0..fb.height();
+            Self::render_rows(fb, (foreground, background), out, width, height, rows);
+            return;
+        }
+
+        let (on, off) = (channels(foreground), channels(background));
+        for y in 0..height {
+            for x in 0..width {
+                // position on the screen from 0 to 1
+                let mut u = (x as f32 + 0.5) / width as f32;
+                let mut v = (y as f32 + 0.5) / height as f32;
+                if self.curvature {
+                    (u, v) = bend(u, v);
+                    if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                        out[y * width + x] = 0;
+                        continue;
+                    }
+                }
+
+                let (sx, sy) = (u * fb.width() as f32, v * fb.height() as f32);
+                let (px, py) = (sx as usize, sy as usize);
+                let mut color = if fb.get(px, py) { on } else { off };
+                if self.glow && !fb.get(px, py) {
+                    let glow = (GLOW * lit_neighbours(fb, px, py)).min(1.0);
+                    for (channel, lit) in color.iter_mut().zip(on) {
+                        *channel += (lit - *channel) * glow;
+                    }
+                }
+
+                let mut brightness = 1.0;
+                if self.scanlines && sy.fract() >= 0.7 {
+                    brightness *= 0.55;
+                }
+                if self.grid && sx.fract() >= 0.8 {
+                    brightness *= 0.7;
+                }
+                let [r, g, b] = color.map(|channel| (channel * brightness) as u32);
+                out[y * width + x] = r << 16 | g << 8 | b;
+            }
+        }
+    }
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self {
+            scanlines: true,
+            grid: false,
+            glow: true,
+            curvature: true,
+        }
+    }
+}
+
+impl FromStr for Crt {
+    type Err = String;
+
+    // "all" or a list of scanlines, grid, glow and curvature
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut crt = Crt::OFF;
+        for effect in s.split(',').map(str::trim) {
+            match effect.to_ascii_lowercase().as_str() {
+                "all" => {
+                    crt = Crt {
+                        scanlines: true,
+                        grid: true,
+                        glow: true,
+                        curvature: true,
+                    }
+                }
+                "scanlines" => crt.scanlines = true,
+                "grid" => crt.grid = true,
+                "glow" => crt.glow = true,
+                "curvature" => crt.curvature = true,
+                _ => return Err(format!("unknown effect '{effect}'")),
+            }
+        }
+        Ok(crt)
+    }
+}
+
+fn channels(rgb: u32) -> [f32; 3] {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    [r as f32, g as f32, b as f32]
+}
+
+// barrel distortion, points further from the middle are pulled in from further out
+fn bend(u: f32, v: f32) -> (f32, f32) {
+    let (cu, cv) = (u * 2.0 - 1.0, v * 2.0 - 1.0);
+    let bulge = 1.0 + CURVATURE * (cu * cu + cv * cv);
+    ((cu * bulge + 1.0) / 2.0, (cv * bulge + 1.0) / 2.0)
+}
+
+// diagonal neighbours count for half
+fn lit_neighbours(fb: &FrameBuffer, x: usize, y: usize) -> f32 {
+    let mut lit = 0.0;
+    for dy in -1..=1_isize {
+        for dx in -1..=1_isize {
+            let (nx, ny) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+            if (dx, dy) == (0, 0) || nx >= fb.width() || ny >= fb.height() || !fb.get(nx, ny) {
+                continue;
+            }
+            lit += if dx == 0 || dy == 0 { 1.0 } else { 0.5 };
+        }
+    }
+    lit
+}
+
+#[test]
+fn test_crt_effects() {
+    assert_eq!(
+        "scanlines, glow".parse::<Crt>(),
+        Ok(Crt {
+            scanlines: true,
+            grid: false,
+            glow: true,
+            curvature: false,
+        })
+    );
+    assert!("bloom".parse::<Crt>().is_err());
+
+    let mut fb = FrameBuffer::new();
+    // top left pixel only
+    fb.paint(0, 0, vec![0x80], false);
+    // 4x4 per pixel, the last row of each is a scanline
+    let (width, height) = (fb.width() * 4, fb.height() * 4);
+    let mut out = vec![0; width * height];
+    "scanlines,glow".parse::<Crt>().unwrap().render(
+        &fb,
+        (0xFFFFFF, 0x000000),
+        &mut out,
+        width,
+        height,
+    );
+    assert_eq!(out[0], 0xFFFFFF);
+    assert_eq!(out[3 * width], 0x8C8C8C);
+    // the pixel to the right glows a little
+    assert_eq!(out[4], 0x1E1E1E);
+    assert_eq!(out[8], 0);
+
+    // redrawing only the rows that changed gives the same as all of them
+    let mut plain = vec![0; width * height];
+    Crt::OFF.render(&fb, (0xFFFFFF, 0), &mut plain, width, height);
+    fb.take_dirty_rows();
+    fb.paint(8, 10, vec![0xFF, 0x81], false);
+    let rows = fb.take_dirty_rows().unwrap();
+    let drawn = Crt::render_rows(&fb, (0xFFFFFF, 0), &mut plain, width, height, rows);
+    assert_eq!(drawn, 40..48);
+    Crt::OFF.render(&fb, (0xFFFFFF, 0), &mut out, width, height);
+    assert_eq!(plain, out);
+}
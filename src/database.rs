@@ -0,0 +1,133 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde::Deserialize;
+
+use crate::quirks::{Platform, Quirks};
+
+// What's known about a rom, to run it the way it was written for
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub quirks: Option<Quirks>,
+    // instructions per second
+    pub speed: Option<u32>,
+    // foreground and background
+    pub palette: Option<(u32, u32)>,
+}
+
+// Roms keyed by the SHA-1 of their bytes, read from the programs.json of the
+// CHIP-8 community database (github.com/chip-8/chip-8-database)
+pub struct Database {
+    roms: BTreeMap<String, RomInfo>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    // instructions per frame
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    // background first, then the colors of lit pixels
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl Database {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let programs: Vec<Program> =
+            serde_json::from_str(json).map_err(|err| format!("bad rom database: {err}"))?;
+        let mut roms = BTreeMap::new();
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let info = RomInfo {
+                    title: program.title.clone(),
+                    // the first platform emuchip has quirks for
+                    quirks: rom.platforms.iter().find_map(|id| platform_quirks(id)),
+                    speed: rom.tickrate.map(|tickrate| tickrate * 60),
+                    palette: rom.colors.and_then(|colors| palette(&colors.pixels)),
+                };
+                roms.insert(hash.to_ascii_lowercase(), info);
+            }
+        }
+        Ok(Self { roms })
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(sha1)
+    }
+}
+
+// lowercase hex, the way the database writes it
+pub fn sha1(bytes: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytes).digest().to_string()
+}
+
+fn platform_quirks(id: &str) -> Option<Quirks> {
+    let platform = match id {
+        "originalChip8" | "hybridVIP" => Platform::Cosmac,
+        // what emuchip does by default
+        "modernChip8" => return Some(Quirks::default()),
+        "chip48" => Platform::Chip48,
+        "superchip1" | "superchip" => Platform::Schip,
+        "xochip" => Platform::XoChip,
+        _ => return None,
+    };
+    Some(Quirks::from_platform(platform))
+}
+
+fn palette(pixels: &[String]) -> Option<(u32, u32)> {
+    let [background, foreground, ..] = pixels else {
+        return None;
+    };
+    let rgb = |color: &str| u32::from_str_radix(color.trim_start_matches('#'), 16).ok();
+    Some((rgb(foreground)?, rgb(background)?))
+}
+
+#[test]
+fn test_database_lookup() {
+    let json = r##"[
+        {
+            "title": "Test",
+            "roms": {
+                "ABC123": {
+                    "platforms": ["megachip8", "superchip"],
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#ff8800"] }
+                }
+            }
+        },
+        { "title": "Unknown", "roms": { "def456": {} } }
+    ]"##;
+    let database = Database::parse(json).unwrap();
+    assert_eq!(
+        database.lookup("abc123"),
+        Some(&RomInfo {
+            title: "Test".to_string(),
+            quirks: Some(Quirks::from_platform(Platform::Schip)),
+            speed: Some(1800),
+            palette: Some((0xFF8800, 0x000000)),
+        })
+    );
+    let unknown = database.lookup("def456").unwrap();
+    assert_eq!((unknown.quirks, unknown.speed), (None, None));
+    assert!(database.lookup("0123").is_none());
+    assert!(Database::parse("{}").is_err());
+
+    assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+}
@@ -0,0 +1,829 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{
+    decode::OpCodes,
+    error::EmulatorError,
+    memory::{TypeAddr, MEMORY_SIZE},
+    symbols::Symbols,
+    Chip8, StepOutcome,
+};
+
+const HELP: &str = "\
+c, continue          resume execution
+p, pause             pause execution
+s, step [N]          execute N instructions (default 1)
+b, break ADDR        set a breakpoint
+  [if EXPR]          only taken while EXPR holds, e.g. V3 == 1F && I > 300,
+                     made of V0-VF, I, PC, DT, ST, SP (the call depth),
+                     numbers, == != < <= > >=, !, && and || and brackets
+d, delete ADDR       remove a breakpoint
+w, watch COND        break after an instruction matching COND runs: an
+                     opcode like DXYN or FX0A, with X, Y, N and K for any
+                     digit, or VX to break when VX changes
+uw, unwatch COND     remove a watch
+bl                   list breakpoints and watches
+r, regs              show registers, timers and stack
+bt, stack            show the calls being run, innermost first
+m, mem ADDR [LEN]    dump LEN bytes of memory (default 16)
+set REG VALUE        set V0-VF, I, PC, DT or ST
+poke ADDR BYTE...    write bytes to memory
+dump [FILE]          the whole machine state as JSON, to FILE if given
+numbers are hex, with or without 0x, an ADDR can also be a label of the
+rom, e.g. draw or draw+4";
+
+// Breaks on what an instruction is or did rather than where it is
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    // the opcode's bits under mask equal value, as written e.g. DXYN
+    Opcode {
+        mask: u16,
+        value: u16,
+        pattern: String,
+    },
+    // the register holds a different value afterwards
+    Register(u8),
+}
+
+impl Condition {
+    pub fn matches(&self, outcome: &StepOutcome) -> bool {
+        match self {
+            Condition::Opcode { mask, value, .. } => outcome.opcode & mask == *value,
+            Condition::Register(reg) => outcome.wrote(*reg),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    // DXYN, FX0A, 00EE... or V0-VF
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = s.to_uppercase();
+        if let Some(reg) = pattern.strip_prefix('V') {
+            return match u8::from_str_radix(reg, 16) {
+                Ok(reg) if pattern.len() == 2 => Ok(Condition::Register(reg)),
+                _ => Err(format!("unknown register '{s}'")),
+            };
+        }
+        if pattern.len() != 4 {
+            return Err(format!("'{s}' is not an opcode like DXYN or a register"));
+        }
+        let (mut mask, mut value) = (0, 0);
+        for c in pattern.chars() {
+            (mask, value) = (mask << 4, value << 4);
+            if matches!(c, 'X' | 'Y' | 'N' | 'K') {
+                continue;
+            }
+            let digit = c
+                .to_digit(16)
+                .ok_or(format!("'{s}' should be hex digits and X, Y, N or K"))?;
+            mask |= 0xF;
+            value |= digit as u16;
+        }
+        Ok(Condition::Opcode {
+            mask,
+            value,
+            pattern,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Opcode { pattern, .. } => f.write_str(pattern),
+            Condition::Register(reg) => write!(f, "V{reg:X}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(u16),
+    Register(u8),
+    Index,
+    Pc,
+    Delay,
+    Sound,
+    // how many calls deep
+    Depth,
+    Not(Box<Node>),
+    Compare(Box<Node>, Compare, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    // comparisons and the rest give 1 for true and 0 for false
+    fn eval(&self, chip8: &Chip8) -> u16 {
+        match self {
+            Node::Number(n) => *n,
+            Node::Register(reg) => chip8.regs.get(*reg) as u16,
+            Node::Index => chip8.mem.index.0,
+            Node::Pc => chip8.mem.pc.0,
+            Node::Delay => chip8.timers.delay as u16,
+            Node::Sound => chip8.timers.sound as u16,
+            Node::Depth => chip8.mem.stack.addresses().len() as u16,
+            Node::Not(node) => (node.eval(chip8) == 0) as u16,
+            Node::Compare(left, compare, right) => {
+                let (left, right) = (left.eval(chip8), right.eval(chip8));
+                let holds = match compare {
+                    Compare::Equal => left == right,
+                    Compare::NotEqual => left != right,
+                    Compare::Less => left < right,
+                    Compare::LessOrEqual => left <= right,
+                    Compare::Greater => left > right,
+                    Compare::GreaterOrEqual => left >= right,
+                };
+                holds as u16
+            }
+            Node::And(left, right) => (left.eval(chip8) != 0 && right.eval(chip8) != 0) as u16,
+            Node::Or(left, right) => (left.eval(chip8) != 0 || right.eval(chip8) != 0) as u16,
+        }
+    }
+}
+
+// longest first, so != isn't taken for !
+const OPERATORS: [&str; 9] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // a register or a number
+    Word(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => f.write_str(word),
+            Token::Operator(op) => f.write_str(op),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            op.len()
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            1
+        } else {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Err(format!("unexpected '{c}'"));
+            }
+            tokens.push(Token::Word(rest[..len].to_string()));
+            len
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// || over && over comparisons over ! and brackets
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Operator(found)) if *found == op);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.eat("||") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.compare()?;
+        while self.eat("&&") {
+            node = Node::And(Box::new(node), Box::new(self.compare()?));
+        }
+        Ok(node)
+    }
+
+    fn compare(&mut self) -> Result<Node, String> {
+        let left = self.unary()?;
+        let compare = match self.peek() {
+            Some(Token::Operator("==")) => Compare::Equal,
+            Some(Token::Operator("!=")) => Compare::NotEqual,
+            Some(Token::Operator("<")) => Compare::Less,
+            Some(Token::Operator("<=")) => Compare::LessOrEqual,
+            Some(Token::Operator(">")) => Compare::Greater,
+            Some(Token::Operator(">=")) => Compare::GreaterOrEqual,
+            _ => return Ok(left),
+        };
+        self.next += 1;
+        let right = self.unary()?;
+        Ok(Node::Compare(Box::new(left), compare, Box::new(right)))
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        let token = self.peek().cloned().ok_or("the condition ends too soon")?;
+        self.next += 1;
+        match token {
+            Token::Open => {
+                let node = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err("missing )".to_string());
+                }
+                self.next += 1;
+                Ok(node)
+            }
+            Token::Word(word) => operand(&word),
+            token => Err(format!("unexpected {token}")),
+        }
+    }
+}
+
+fn operand(word: &str) -> Result<Node, String> {
+    let upper = word.to_uppercase();
+    match upper.as_str() {
+        "I" => return Ok(Node::Index),
+        "PC" => return Ok(Node::Pc),
+        "DT" => return Ok(Node::Delay),
+        "ST" => return Ok(Node::Sound),
+        "SP" => return Ok(Node::Depth),
+        _ => {}
+    }
+    if let Some(reg) = upper.strip_prefix('V').filter(|reg| reg.len() == 1) {
+        if let Ok(reg) = u8::from_str_radix(reg, 16) {
+            return Ok(Node::Register(reg));
+        }
+    }
+    u16::from_str_radix(upper.trim_start_matches("0X"), 16)
+        .map(Node::Number)
+        .map_err(|_| format!("'{word}' is not a register or a hex number"))
+}
+
+// A condition on the machine state such as V3 == 1F && I > 300, numbers are
+// hex like everywhere else in the console
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    root: Node,
+    text: String,
+}
+
+impl Expr {
+    pub fn holds(&self, chip8: &Chip8) -> bool {
+        self.root.eval(chip8) != 0
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {token} in '{s}'"));
+        }
+        Ok(Self {
+            root,
+            text: s.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Breakpoints and pause state, driven by text commands from a debug console
+pub struct Debugger {
+    // with the condition they're only taken under
+    breakpoints: BTreeMap<TypeAddr, Option<Expr>>,
+    watches: Vec<Condition>,
+    // the watch that paused, and the instruction that set it off
+    hit: Option<String>,
+    paused: bool,
+    // let the instruction under a breakpoint run once after resuming
+    resuming: bool,
+    // names for addresses, in what's shown and in place of ADDR
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            watches: vec![],
+            hit: None,
+            paused: false,
+            resuming: false,
+            symbols: Symbols::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: TypeAddr) {
+        self.breakpoints.insert(addr, None);
+    }
+
+    pub fn add_watch(&mut self, watch: Condition) {
+        self.watches.push(watch);
+    }
+
+    // the watch that paused last and where, once
+    pub fn take_hit(&mut self) -> Option<String> {
+        self.hit.take()
+    }
+
+    // run one instruction, returns false when paused, a breakpoint was hit or
+    // a watch went off on the instruction that just ran, and pauses on
+    // emulator errors
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<bool, EmulatorError> {
+        if self.paused {
+            return Ok(false);
+        }
+        let hit = match self.breakpoints.get(&chip8.mem.pc.0) {
+            Some(Some(condition)) => condition.holds(chip8),
+            Some(None) => true,
+            None => false,
+        };
+        if hit && !self.resuming {
+            self.paused = true;
+            return Ok(false);
+        }
+        self.resuming = false;
+        let outcome = chip8.step().inspect_err(|_| self.paused = true)?;
+        if let Some(watch) = self.watches.iter().find(|watch| watch.matches(&outcome)) {
+            self.hit = Some(format!(
+                "{watch} at {}",
+                self.symbols.describe(outcome.pc_before)
+            ));
+            self.paused = true;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub fn command(&mut self, chip8: &mut Chip8, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let Some(cmd) = words.next() else {
+            return Ok(String::new());
+        };
+        let args: Vec<&str> = words.collect();
+
+        match cmd {
+            "c" | "continue" => {
+                self.resume();
+                Ok("continuing".to_string())
+            }
+            "p" | "pause" => {
+                self.pause();
+                Ok(self.current(chip8))
+            }
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(_) => parse_hex(args.first())?,
+                    None => 1,
+                };
+                self.paused = true;
+                for _ in 0..count {
+                    chip8.step()?;
+                }
+                Ok(self.current(chip8))
+            }
+            "b" | "break" => {
+                let addr = self.addr(args.first())?;
+                let condition = match args.get(1) {
+                    Some(&"if") => Some(args[2..].join(" ").parse::<Expr>()?),
+                    Some(word) => return Err(format!("expected if, not '{word}'")),
+                    None => None,
+                };
+                let at = self.symbols.describe(addr);
+                let set = match &condition {
+                    Some(condition) => format!("breakpoint at {at} if {condition}"),
+                    None => format!("breakpoint at {at}"),
+                };
+                self.breakpoints.insert(addr, condition);
+                Ok(set)
+            }
+            "d" | "delete" => {
+                let addr = self.addr(args.first())?;
+                let at = self.symbols.describe(addr);
+                if self.breakpoints.remove(&addr).is_some() {
+                    Ok(format!("removed breakpoint at {at}"))
+                } else {
+                    Err(format!("no breakpoint at {at}"))
+                }
+            }
+            "w" | "watch" => {
+                let watch: Condition = args.first().ok_or("missing condition")?.parse()?;
+                let added = format!("watching {watch}");
+                self.watches.push(watch);
+                Ok(added)
+            }
+            "uw" | "unwatch" => {
+                let watch: Condition = args.first().ok_or("missing condition")?.parse()?;
+                let count = self.watches.len();
+                self.watches.retain(|other| *other != watch);
+                if self.watches.len() < count {
+                    Ok(format!("stopped watching {watch}"))
+                } else {
+                    Err(format!("not watching {watch}"))
+                }
+            }
+            "bl" => Ok(self
+                .breakpoints
+                .iter()
+                .map(|(&addr, condition)| match condition {
+                    Some(condition) => format!("{} if {condition}", self.symbols.describe(addr)),
+                    None => self.symbols.describe(addr),
+                })
+                .chain(self.watches.iter().map(|watch| format!("watch {watch}")))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "r" | "regs" => Ok(Self::registers(chip8)),
+            "bt" | "stack" => Ok(self.call_stack(chip8)),
+            "m" | "mem" => {
+                let addr = self.addr(args.first())?;
+                let len = if args.len() > 1 {
+                    parse_hex(args.get(1))?
+                } else {
+                    16
+                };
+                Ok(Self::dump(chip8, addr, len))
+            }
+            "set" => {
+                let value = parse_hex(args.get(1))?;
+                Self::set(chip8, args.first().copied().unwrap_or(""), value)?;
+                Ok(Self::registers(chip8))
+            }
+            "poke" => {
+                let addr = self.addr(args.first())?;
+                for (i, byte) in args.iter().skip(1).enumerate() {
+                    let byte = u8::try_from(parse_hex(Some(byte))?)
+                        .map_err(|_| format!("{byte} is not a byte"))?;
+                    let target = addr as usize + i;
+                    if !chip8.mem.write(target, byte) {
+                        return Err(format!("{target:04X} is out of memory"));
+                    }
+                }
+                Ok(Self::dump(chip8, addr, (args.len() - 1) as TypeAddr))
+            }
+            "dump" => match args.first() {
+                Some(path) => {
+                    chip8.snapshot().dump(path)?;
+                    Ok(format!("dumped the state to {path}"))
+                }
+                None => Ok(chip8.snapshot().to_json()),
+            },
+            "h" | "help" => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command '{cmd}', try help")),
+        }
+    }
+
+    // the instruction about to be executed
+    pub fn current(&self, chip8: &Chip8) -> String {
+        let pc = chip8.mem.pc.0;
+        let at = self.symbols.describe(pc);
+        match chip8.mem.instruction_at(pc) {
+            Some(code) => format!("{at}: {code:04X}  {}", self.mnemonic(code)),
+            None => format!("{at}: out of memory"),
+        }
+    }
+
+    // with the names of the addresses symbols have one for
+    fn mnemonic(&self, code: u16) -> String {
+        OpCodes::decode_raw(code).mnemonic(|addr| match self.symbols.name(addr) {
+            Some(name) => name.to_string(),
+            None => format!("0x{addr:03X}"),
+        })
+    }
+
+    // a symbol, or else a hex number
+    fn addr(&self, s: Option<&&str>) -> Result<TypeAddr, String> {
+        match s.and_then(|s| self.symbols.resolve(s)) {
+            Some(addr) => Ok(addr),
+            None => parse_hex(s),
+        }
+    }
+
+    pub fn registers(chip8: &Chip8) -> String {
+        let regs: Vec<String> = (0..16)
+            .map(|reg| format!("V{reg:X}={:02X}", chip8.regs.get(reg)))
+            .collect();
+        let stack: Vec<String> = chip8
+            .mem
+            .stack
+            .addresses()
+            .iter()
+            .map(|addr| format!("{addr:03X}"))
+            .collect();
+        format!(
+            "{}\n{}\nPC={:03X} I={:03X} DT={:02X} ST={:02X}\nstack: [{}]",
+            regs[..8].join(" "),
+            regs[8..].join(" "),
+            chip8.mem.pc.0,
+            chip8.mem.index.0,
+            chip8.timers.delay,
+            chip8.timers.sound,
+            stack.join(" ")
+        )
+    }
+
+    // the subroutines being run, innermost first, each with the call that got
+    // there and where it returns to
+    pub fn call_stack(&self, chip8: &Chip8) -> String {
+        let stack = chip8.mem.stack.addresses();
+        let mut lines = vec![format!(
+            "{} of {} calls deep",
+            stack.len(),
+            chip8.quirks.stack_depth
+        )];
+        for (depth, ret) in stack.iter().enumerate().rev() {
+            // the return address is right after the 2NNN
+            let site = ret.wrapping_sub(2);
+            let call = match chip8.mem.instruction_at(site) {
+                Some(code) => {
+                    let op = OpCodes::decode_raw(code);
+                    // the stack or the code was written to since
+                    let odd = if matches!(op, OpCodes::PushSubroutine(_)) {
+                        ""
+                    } else {
+                        ", not a call"
+                    };
+                    format!("{code:04X}  {}{odd}", self.mnemonic(code))
+                }
+                None => "out of memory".to_string(),
+            };
+            lines.push(format!(
+                "#{} {}: {call}, returns to {}",
+                depth + 1,
+                self.symbols.describe(site),
+                self.symbols.describe(*ret)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn dump(chip8: &Chip8, addr: TypeAddr, len: TypeAddr) -> String {
+        let end = (addr as usize + len as usize).min(MEMORY_SIZE);
+        let mut lines = vec![];
+        for row in (addr as usize..end).step_by(16) {
+            let bytes: Vec<String> = (row..(row + 16).min(end))
+                .filter_map(|a| chip8.mem.read(a))
+                .map(|byte| format!("{byte:02X}"))
+                .collect();
+            lines.push(format!("{row:03X}: {}", bytes.join(" ")));
+        }
+        lines.join("\n")
+    }
+
+    fn set(chip8: &mut Chip8, reg: &str, value: TypeAddr) -> Result<(), String> {
+        let reg = reg.to_uppercase();
+        let byte = || u8::try_from(value).map_err(|_| format!("{value:X} does not fit in {reg}"));
+        match reg.as_str() {
+            "I" => chip8.mem.set_index(value),
+            "PC" => chip8.mem.set_pc(value),
+            "DT" => chip8.timers.delay = byte()?,
+            "ST" => chip8.timers.sound = byte()?,
+            _ => {
+                let n = reg
+                    .strip_prefix('V')
+                    .and_then(|n| u8::from_str_radix(n, 16).ok())
+                    .filter(|n| *n < 16)
+                    .ok_or(format!("unknown register '{reg}'"))?;
+                chip8.regs.set_register(n, byte()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_hex(s: Option<&&str>) -> Result<TypeAddr, String> {
+    let s = s.ok_or("missing number")?;
+    TypeAddr::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("'{s}' is not a hex number"))
+}
+
+#[test]
+fn test_breakpoint_and_resume() {
+    use crate::quirks::Quirks;
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 += 1, jump back to start
+    chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    let mut debugger = Debugger::new();
+    debugger.command(&mut chip8, "b 202").unwrap();
+
+    assert!(debugger.step(&mut chip8).unwrap());
+    assert!(!debugger.step(&mut chip8).unwrap());
+    assert!(debugger.is_paused());
+    assert_eq!(chip8.mem.pc.0, 0x202);
+
+    debugger.command(&mut chip8, "c").unwrap();
+    assert!(debugger.step(&mut chip8).unwrap());
+    assert_eq!(chip8.mem.pc.0, 0x200);
+
+    debugger.command(&mut chip8, "set v3 1f").unwrap();
+    assert_eq!(chip8.regs.get(3), 0x1F);
+    assert!(debugger.command(&mut chip8, "set v3 100").is_err());
+    let dump = debugger.command(&mut chip8, "dump").unwrap();
+    assert!(dump.starts_with('{') && dump.contains("\"pc\":512"));
+}
+
+#[test]
+fn test_call_stack() {
+    use crate::quirks::Quirks;
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // call 206, which calls 20A, which loops
+    chip8
+        .load_rom(&[
+            0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0x22, 0x0A, 0x00, 0x00, 0x12, 0x0A,
+        ])
+        .unwrap();
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.command(&mut chip8, "bt").unwrap(),
+        "0 of 16 calls deep"
+    );
+    debugger.command(&mut chip8, "s 2").unwrap();
+    assert_eq!(
+        debugger.command(&mut chip8, "stack").unwrap(),
+        "2 of 16 calls deep\n\
+         #2 206: 220A  CALL 0x20A, returns to 208\n\
+         #1 200: 2206  CALL 0x206, returns to 202"
+    );
+    // a return address that doesn't follow a call
+    chip8.mem.stack.push(0x20C);
+    assert!(debugger
+        .call_stack(&chip8)
+        .contains("#3 20A: 120A  JP 0x20A, not a call"));
+
+    // the count is hex like every number here, 10 steps is sixteen: V0 += 1,
+    // jump back, eight times
+    chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    chip8.reset();
+    debugger.command(&mut chip8, "s 10").unwrap();
+    assert_eq!(chip8.regs.get(0), 8);
+}
+
+#[test]
+fn test_symbols() {
+    use crate::quirks::Quirks;
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // CALL 204, JP 200, V0 += 1, RET
+    chip8
+        .load_rom(&[0x22, 0x04, 0x12, 0x00, 0x70, 0x01, 0x00, 0xEE])
+        .unwrap();
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(Symbols::parse("200 main\n204 count").unwrap());
+    assert_eq!(
+        debugger.command(&mut chip8, "b count+2").unwrap(),
+        "breakpoint at 206 <count+2>"
+    );
+    assert_eq!(debugger.command(&mut chip8, "bl").unwrap(), "206 <count+2>");
+    assert_eq!(
+        debugger.command(&mut chip8, "s").unwrap(),
+        "204 <count>: 7001  ADD V0, 0x01"
+    );
+    assert_eq!(
+        debugger.command(&mut chip8, "bt").unwrap(),
+        "1 of 16 calls deep\n\
+         #1 200 <main>: 2204  CALL count, returns to 202 <main+2>"
+    );
+    assert!(debugger.command(&mut chip8, "d 206").is_ok());
+    assert!(debugger.command(&mut chip8, "b nowhere").is_err());
+}
+
+#[test]
+fn test_watches() {
+    use crate::quirks::Quirks;
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V1 = 5, V1 += VF... VF = 1, draw, wait for a key
+    chip8
+        .load_rom(&[0x61, 0x05, 0x81, 0xF4, 0x6F, 0x01, 0xD0, 0x05, 0xF2, 0x0A])
+        .unwrap();
+    let mut debugger = Debugger::new();
+    assert!(debugger.command(&mut chip8, "watch vf").is_ok());
+    assert!(debugger.command(&mut chip8, "w dxyn").is_ok());
+    assert!(debugger.command(&mut chip8, "w FX0A").is_ok());
+    assert!(debugger.command(&mut chip8, "w V10").is_err());
+    assert!(debugger.command(&mut chip8, "w DXG0").is_err());
+    assert_eq!(
+        debugger.command(&mut chip8, "bl").unwrap(),
+        "watch VF\nwatch DXYN\nwatch FX0A"
+    );
+
+    // 81F4 leaves VF at 0, 6F01 is the first to change it
+    let run = |debugger: &mut Debugger, chip8: &mut Chip8| {
+        while debugger.step(chip8).unwrap() {}
+        debugger.resume();
+        debugger.take_hit()
+    };
+    assert_eq!(run(&mut debugger, &mut chip8).as_deref(), Some("VF at 204"));
+    // the draw clears VF again
+    debugger.command(&mut chip8, "uw VF").unwrap();
+    assert!(debugger.command(&mut chip8, "unwatch vf").is_err());
+    assert_eq!(
+        run(&mut debugger, &mut chip8).as_deref(),
+        Some("DXYN at 206")
+    );
+    assert_eq!(
+        run(&mut debugger, &mut chip8).as_deref(),
+        Some("FX0A at 208")
+    );
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    use crate::quirks::Quirks;
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V3 += 1, I = 310, jump back to start
+    chip8
+        .load_rom(&[0x73, 0x01, 0xA3, 0x10, 0x12, 0x00])
+        .unwrap();
+    let mut debugger = Debugger::new();
+    let set = debugger
+        .command(&mut chip8, "b 200 if V3 == 0x1F && I > 0x300")
+        .unwrap();
+    assert_eq!(set, "breakpoint at 200 if V3 == 0x1F && I > 0x300");
+    while debugger.step(&mut chip8).unwrap() {}
+    assert_eq!((chip8.mem.pc.0, chip8.regs.get(3)), (0x200, 0x1F));
+    assert_eq!(
+        debugger.command(&mut chip8, "bl").unwrap(),
+        "200 if V3 == 0x1F && I > 0x300"
+    );
+
+    let holds = |s: &str| s.parse::<Expr>().unwrap().holds(&chip8);
+    assert!(holds("!(v3 < 1f) && (pc == 200 || dt)"));
+    assert!(holds("SP == 0 && V3 != 0 && i >= 310 && V3 <= 1F"));
+    assert!(!holds("V3 > 1F || ST"));
+    assert!(holds("V3"));
+    for bad in ["V3 ==", "V3 == 1F)", "(V3", "VG > 1", "V3 = 1", "&& V3"] {
+        assert!(bad.parse::<Expr>().is_err(), "{bad}");
+    }
+    assert!(debugger.command(&mut chip8, "b 200 when V3").is_err());
+}
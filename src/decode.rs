@@ -1,3 +1,9 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::fmt;
+
 use crate::memory::TypeAddr;
 
 pub struct RawInstruction {
@@ -88,11 +94,14 @@ fn test_bit_manip() {
     assert_eq!(RawInstruction::new(0x4CEE).nth_m_digits(2, 2), 0xCE);
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpCodes {
     // 00E0
     // turn all pixels to 0
     ClearScreen,
+    // 0230 (hi-res CHIP-8)
+    // clear the 64x64 screen, invalid outside that mode
+    TwoPageClear,
     // 1NNN
     // set PC to address NNN, "jump" to memory location
     Jump(TypeAddr),
@@ -112,6 +121,32 @@ pub enum OpCodes {
     // if pixels on screen were switched OFF: VF set to 1
     Display(u8, u8, u8),
 
+    // DXY0 (SCHIP)
+    // draw a 16x16 sprite starting at I, two bytes per row
+    DisplayLarge(u8, u8),
+
+    // 00FE (SCHIP)
+    // switch to 64x32 low resolution mode
+    LowRes,
+    // 00FF (SCHIP)
+    // switch to 128x64 high resolution mode
+    HighRes,
+    // 00FD (SCHIP)
+    // stop the interpreter
+    Exit,
+    // 0NNN
+    // call the machine code routine at NNN, only the COSMAC VIP could
+    MachineRoutine(TypeAddr),
+    // 00CN (SCHIP)
+    // scroll display N pixels down
+    ScrollDown(u8),
+    // 00FB (SCHIP)
+    // scroll display 4 pixels right
+    ScrollRight,
+    // 00FC (SCHIP)
+    // scroll display 4 pixels left
+    ScrollLeft,
+
     // 2NNN
     PushSubroutine(TypeAddr),
     // 00EE
@@ -168,14 +203,31 @@ pub enum OpCodes {
     GetKey(u8),
     // FX29
     PointChar(u8),
+    // FX30 (SCHIP)
+    // point I to the 10 byte font sprite for digit VX
+    PointBigChar(u8),
     // FX33
     ToDecimal(u8),
 
+    // F002 (XO-CHIP)
+    // copy the 16 bytes at I into the audio pattern buffer
+    LoadAudio,
+    // FX3A (XO-CHIP)
+    // set the audio playback pitch to VX
+    SetPitch(u8),
+
     // FX65
     LoadRegisterFromMemory(u8),
     // FX55
     StoreRegisterToMemory(u8),
 
+    // FX75 (SCHIP)
+    // save V0 to VX in the RPL flags, which outlast the program
+    StoreFlags(u8),
+    // FX85 (SCHIP)
+    // load V0 to VX from the RPL flags
+    LoadFlags(u8),
+
     Unimplemented,
 }
 
@@ -186,8 +238,17 @@ impl OpCodes {
         match raw.start_identifier() {
             0x0 => match ins {
                 0x00E0 => Self::ClearScreen,
+                0x0230 => Self::TwoPageClear,
                 0x00EE => Self::PopSubroutine,
-                _ => Self::Unimplemented,
+                0x00FB => Self::ScrollRight,
+                0x00FC => Self::ScrollLeft,
+                0x00FE => Self::LowRes,
+                0x00FF => Self::HighRes,
+                0x00FD => Self::Exit,
+                _ if ins & 0xFFF0 == 0x00C0 => Self::ScrollDown((ins & 0xF) as u8),
+                // more likely empty memory than a routine at 000
+                0x0000 => Self::Unimplemented,
+                _ => Self::MachineRoutine(raw.next_address()),
             },
             0x1 => Self::Jump(raw.next_address()),
             0x2 => Self::PushSubroutine(raw.next_address()),
@@ -216,7 +277,13 @@ impl OpCodes {
             0xA => Self::SetIndexRegister(raw.next_address()),
             0xB => Self::JumpWithOffset(raw.next_address()),
             0xC => Self::Random(raw.next_register(), raw.next_u8()),
-            0xD => Self::Display(raw.next_register(), raw.next_register(), raw.next_u4()),
+            0xD => {
+                let (x, y) = (raw.next_register(), raw.next_register());
+                match raw.next_u4() {
+                    0x0 => Self::DisplayLarge(x, y),
+                    n => Self::Display(x, y, n),
+                }
+            }
             0xE => {
                 let x = raw.next_register();
                 let k_type = raw.next_u8();
@@ -230,15 +297,20 @@ impl OpCodes {
                 let x = raw.next_register();
                 let f_type = raw.next_u8();
                 match f_type {
+                    0x02 if x == 0 => Self::LoadAudio,
                     0x07 => Self::CopyDelayToRegister(x),
                     0x0A => Self::GetKey(x),
                     0x15 => Self::CopyRegisterToDelay(x),
                     0x18 => Self::CopyRegisterToSound(x),
                     0x1E => Self::AddToIndex(x),
                     0x29 => Self::PointChar(x),
+                    0x30 => Self::PointBigChar(x),
                     0x33 => Self::ToDecimal(x),
+                    0x3A => Self::SetPitch(x),
                     0x55 => Self::StoreRegisterToMemory(x),
                     0x65 => Self::LoadRegisterFromMemory(x),
+                    0x75 => Self::StoreFlags(x),
+                    0x85 => Self::LoadFlags(x),
                     _ => Self::Unimplemented,
                 }
             }
@@ -246,3 +318,198 @@ impl OpCodes {
         }
     }
 }
+
+impl OpCodes {
+    // assembly-like text, address operands are rendered with `addr`
+    // so callers can substitute labels
+    pub fn mnemonic(&self, addr: impl Fn(TypeAddr) -> String) -> String {
+        match *self {
+            Self::ClearScreen | Self::TwoPageClear => "CLS".to_string(),
+            Self::Jump(nnn) => format!("JP {}", addr(nnn)),
+            Self::SetRegister(x, nn) => format!("LD V{x:X}, 0x{nn:02X}"),
+            Self::AddToRegister(x, nn) => format!("ADD V{x:X}, 0x{nn:02X}"),
+            Self::SetIndexRegister(nnn) => format!("LD I, {}", addr(nnn)),
+            Self::Display(x, y, n) => format!("DRW V{x:X}, V{y:X}, {n}"),
+            Self::DisplayLarge(x, y) => format!("DRW V{x:X}, V{y:X}, 0"),
+            Self::LowRes => "LOW".to_string(),
+            Self::HighRes => "HIGH".to_string(),
+            Self::Exit => "EXIT".to_string(),
+            Self::MachineRoutine(nnn) => format!("SYS {}", addr(nnn)),
+            Self::ScrollDown(n) => format!("SCD {n}"),
+            Self::ScrollRight => "SCR".to_string(),
+            Self::ScrollLeft => "SCL".to_string(),
+            Self::PushSubroutine(nnn) => format!("CALL {}", addr(nnn)),
+            Self::PopSubroutine => "RET".to_string(),
+            Self::SkipEqualConstant(x, nn) => format!("SE V{x:X}, 0x{nn:02X}"),
+            Self::SkipNotEqualConstant(x, nn) => format!("SNE V{x:X}, 0x{nn:02X}"),
+            Self::SkipEqualRegister(x, y) => format!("SE V{x:X}, V{y:X}"),
+            Self::SkipNotEqualRegister(x, y) => format!("SNE V{x:X}, V{y:X}"),
+            Self::CopyRegister(x, y) => format!("LD V{x:X}, V{y:X}"),
+            Self::Or(x, y) => format!("OR V{x:X}, V{y:X}"),
+            Self::And(x, y) => format!("AND V{x:X}, V{y:X}"),
+            Self::XOr(x, y) => format!("XOR V{x:X}, V{y:X}"),
+            Self::Add(x, y) => format!("ADD V{x:X}, V{y:X}"),
+            Self::SubtractForward(x, y) => format!("SUB V{x:X}, V{y:X}"),
+            Self::SubtractBackward(x, y) => format!("SUBN V{x:X}, V{y:X}"),
+            Self::LeftShift(x, y) => format!("SHL V{x:X}, V{y:X}"),
+            Self::RightShift(x, y) => format!("SHR V{x:X}, V{y:X}"),
+            Self::JumpWithOffset(nnn) => format!("JP V0, {}", addr(nnn)),
+            Self::Random(x, nn) => format!("RND V{x:X}, 0x{nn:02X}"),
+            Self::SkipIfPressed(x) => format!("SKP V{x:X}"),
+            Self::SkipIfNotPressed(x) => format!("SKNP V{x:X}"),
+            Self::CopyDelayToRegister(x) => format!("LD V{x:X}, DT"),
+            Self::CopyRegisterToDelay(x) => format!("LD DT, V{x:X}"),
+            Self::CopyRegisterToSound(x) => format!("LD ST, V{x:X}"),
+            Self::AddToIndex(x) => format!("ADD I, V{x:X}"),
+            Self::GetKey(x) => format!("LD V{x:X}, K"),
+            Self::PointChar(x) => format!("LD F, V{x:X}"),
+            Self::PointBigChar(x) => format!("LD HF, V{x:X}"),
+            Self::ToDecimal(x) => format!("LD B, V{x:X}"),
+            Self::LoadAudio => "AUDIO".to_string(),
+            Self::SetPitch(x) => format!("PITCH V{x:X}"),
+            Self::LoadRegisterFromMemory(x) => format!("LD V{x:X}, [I]"),
+            Self::StoreRegisterToMemory(x) => format!("LD [I], V{x:X}"),
+            Self::StoreFlags(x) => format!("LD R, V{x:X}"),
+            Self::LoadFlags(x) => format!("LD V{x:X}, R"),
+            Self::Unimplemented => "???".to_string(),
+        }
+    }
+}
+
+impl OpCodes {
+    // the instruction that decodes back to this, None for Unimplemented
+    // since the original word is lost
+    pub fn encode(&self) -> Option<u16> {
+        let x = |x: u8| (x as u16 & 0xF) << 8;
+        let xy = |vx: u8, vy: u8| x(vx) | (vy as u16 & 0xF) << 4;
+        let xnn = |vx: u8, nn: u8| x(vx) | nn as u16;
+        let nnn = |addr: TypeAddr| addr & 0xFFF;
+        let code = match *self {
+            Self::ClearScreen => 0x00E0,
+            Self::TwoPageClear => 0x0230,
+            Self::Jump(addr) => 0x1000 | nnn(addr),
+            Self::SetRegister(vx, nn) => 0x6000 | xnn(vx, nn),
+            Self::AddToRegister(vx, nn) => 0x7000 | xnn(vx, nn),
+            Self::SetIndexRegister(addr) => 0xA000 | nnn(addr),
+            Self::Display(vx, vy, n) => 0xD000 | xy(vx, vy) | (n as u16 & 0xF),
+            Self::DisplayLarge(vx, vy) => 0xD000 | xy(vx, vy),
+            Self::LowRes => 0x00FE,
+            Self::HighRes => 0x00FF,
+            Self::Exit => 0x00FD,
+            Self::MachineRoutine(addr) => nnn(addr),
+            Self::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::PushSubroutine(addr) => 0x2000 | nnn(addr),
+            Self::PopSubroutine => 0x00EE,
+            Self::SkipEqualConstant(vx, nn) => 0x3000 | xnn(vx, nn),
+            Self::SkipNotEqualConstant(vx, nn) => 0x4000 | xnn(vx, nn),
+            Self::SkipEqualRegister(vx, vy) => 0x5000 | xy(vx, vy),
+            Self::SkipNotEqualRegister(vx, vy) => 0x9000 | xy(vx, vy),
+            Self::CopyRegister(vx, vy) => 0x8000 | xy(vx, vy),
+            Self::Or(vx, vy) => 0x8001 | xy(vx, vy),
+            Self::And(vx, vy) => 0x8002 | xy(vx, vy),
+            Self::XOr(vx, vy) => 0x8003 | xy(vx, vy),
+            Self::Add(vx, vy) => 0x8004 | xy(vx, vy),
+            Self::SubtractForward(vx, vy) => 0x8005 | xy(vx, vy),
+            Self::RightShift(vx, vy) => 0x8006 | xy(vx, vy),
+            Self::SubtractBackward(vx, vy) => 0x8007 | xy(vx, vy),
+            Self::LeftShift(vx, vy) => 0x800E | xy(vx, vy),
+            Self::JumpWithOffset(addr) => 0xB000 | nnn(addr),
+            Self::Random(vx, nn) => 0xC000 | xnn(vx, nn),
+            Self::SkipIfPressed(vx) => 0xE09E | x(vx),
+            Self::SkipIfNotPressed(vx) => 0xE0A1 | x(vx),
+            Self::LoadAudio => 0xF002,
+            Self::CopyDelayToRegister(vx) => 0xF007 | x(vx),
+            Self::GetKey(vx) => 0xF00A | x(vx),
+            Self::CopyRegisterToDelay(vx) => 0xF015 | x(vx),
+            Self::CopyRegisterToSound(vx) => 0xF018 | x(vx),
+            Self::AddToIndex(vx) => 0xF01E | x(vx),
+            Self::PointChar(vx) => 0xF029 | x(vx),
+            Self::PointBigChar(vx) => 0xF030 | x(vx),
+            Self::ToDecimal(vx) => 0xF033 | x(vx),
+            Self::SetPitch(vx) => 0xF03A | x(vx),
+            Self::StoreRegisterToMemory(vx) => 0xF055 | x(vx),
+            Self::LoadRegisterFromMemory(vx) => 0xF065 | x(vx),
+            Self::StoreFlags(vx) => 0xF075 | x(vx),
+            Self::LoadFlags(vx) => 0xF085 | x(vx),
+            Self::Unimplemented => return None,
+        };
+        Some(code)
+    }
+}
+
+impl fmt::Display for OpCodes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.mnemonic(|addr| format!("0x{addr:03X}")))
+    }
+}
+
+#[test]
+fn test_decode_schip() {
+    assert!(matches!(OpCodes::decode_raw(0x00FF), OpCodes::HighRes));
+    assert!(matches!(
+        OpCodes::decode_raw(0x00C4),
+        OpCodes::ScrollDown(4)
+    ));
+    assert!(matches!(
+        OpCodes::decode_raw(0xD120),
+        OpCodes::DisplayLarge(1, 2)
+    ));
+    assert!(matches!(
+        OpCodes::decode_raw(0xD125),
+        OpCodes::Display(1, 2, 5)
+    ));
+    assert!(matches!(
+        OpCodes::decode_raw(0xF330),
+        OpCodes::PointBigChar(3)
+    ));
+    assert_eq!(OpCodes::decode_raw(0xF775), OpCodes::StoreFlags(7));
+    assert_eq!(OpCodes::decode_raw(0xF285), OpCodes::LoadFlags(2));
+}
+
+#[test]
+fn test_decode_machine_routine() {
+    assert_eq!(OpCodes::decode_raw(0x0123), OpCodes::MachineRoutine(0x123));
+    assert_eq!(OpCodes::decode_raw(0x0000), OpCodes::Unimplemented);
+    assert_eq!(OpCodes::MachineRoutine(0x123).to_string(), "SYS 0x123");
+}
+
+#[test]
+fn test_decode_xochip_audio() {
+    assert!(matches!(OpCodes::decode_raw(0xF002), OpCodes::LoadAudio));
+    assert!(matches!(
+        OpCodes::decode_raw(0xF102),
+        OpCodes::Unimplemented
+    ));
+    assert!(matches!(OpCodes::decode_raw(0xF43A), OpCodes::SetPitch(4)));
+    assert_eq!(OpCodes::SetPitch(4).to_string(), "PITCH V4");
+}
+
+#[test]
+fn test_decode_every_opcode() {
+    for ins in 0..=u16::MAX {
+        let op = OpCodes::decode_raw(ins);
+        if let Some(code) = op.encode() {
+            assert_eq!(
+                OpCodes::decode_raw(code),
+                op,
+                "{ins:04X} encodes as {code:04X}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_encode_round_trip(ins: u16) {
+        let op = OpCodes::decode_raw(ins);
+        proptest::prop_assert_eq!(op.encode().is_none(), op == OpCodes::Unimplemented);
+        if let Some(code) = op.encode() {
+            proptest::prop_assert_eq!(OpCodes::decode_raw(code), op);
+            // only the bits decoding ignores may differ
+            proptest::prop_assert_eq!(code >> 12, ins >> 12);
+        }
+    }
+}
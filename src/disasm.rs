@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{decode::OpCodes, memory::TypeAddr, symbols::Symbols};
+
+pub struct Line {
+    pub addr: TypeAddr,
+    pub code: u16,
+    pub op: OpCodes,
+}
+
+// Linear listing of a rom loaded at `start`, two bytes per line
+pub fn decode_rom(rom: &[u8], start: TypeAddr) -> Vec<Line> {
+    rom.chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let code = match pair {
+                [l, r] => ((*l as u16) << 8) | *r as u16,
+                [l] => (*l as u16) << 8,
+                _ => unreachable!(),
+            };
+            Line {
+                addr: start + (i * 2) as TypeAddr,
+                code,
+                op: OpCodes::decode_raw(code),
+            }
+        })
+        .collect()
+}
+
+// label names for every call and jump destination
+pub fn labels(lines: &[Line]) -> BTreeMap<TypeAddr, String> {
+    let mut labels = BTreeMap::new();
+    for line in lines {
+        match line.op {
+            OpCodes::PushSubroutine(addr) => {
+                labels.insert(addr, format!("sub_{addr:03X}"));
+            }
+            OpCodes::Jump(addr) => {
+                labels
+                    .entry(addr)
+                    .or_insert_with(|| format!("lbl_{addr:03X}"));
+            }
+            _ => {}
+        }
+    }
+    labels
+}
+
+// the two bytes at an address of a rom loaded at `start`
+pub fn word_at(rom: &[u8], start: TypeAddr, addr: TypeAddr) -> Option<u16> {
+    let at = addr.checked_sub(start)? as usize;
+    match rom.get(at..at + 2)? {
+        [l, r] => Some(u16::from_be_bytes([*l, *r])),
+        _ => None,
+    }
+}
+
+// Addresses of the instructions reachable from `start` by following jumps,
+// calls, returns and skips. A BNNN is taken to jump into a table of jumps at
+// NNN, as far as there are jumps there, what else it reaches isn't known
+pub fn reachable(rom: &[u8], start: TypeAddr) -> BTreeSet<TypeAddr> {
+    flow(rom, start, start, true)
+}
+
+// the instructions reachable from `entry`, without going into the
+// subroutines called unless `into_calls`, a call then only goes on past it
+pub fn flow(rom: &[u8], start: TypeAddr, entry: TypeAddr, into_calls: bool) -> BTreeSet<TypeAddr> {
+    let op_at = |addr| word_at(rom, start, addr).map(OpCodes::decode_raw);
+    let mut code = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(addr) = pending.pop() {
+        let Some(op) = op_at(addr) else {
+            continue;
+        };
+        if !code.insert(addr) {
+            continue;
+        }
+        let next = addr + 2;
+        match op {
+            OpCodes::Jump(target) => pending.push(target),
+            OpCodes::PushSubroutine(target) if into_calls => pending.extend([target, next]),
+            OpCodes::JumpWithOffset(table) => {
+                let mut entry = table;
+                while matches!(op_at(entry), Some(OpCodes::Jump(_))) {
+                    pending.push(entry);
+                    entry += 2;
+                }
+            }
+            OpCodes::SkipEqualConstant(..)
+            | OpCodes::SkipNotEqualConstant(..)
+            | OpCodes::SkipEqualRegister(..)
+            | OpCodes::SkipNotEqualRegister(..)
+            | OpCodes::SkipIfPressed(_)
+            | OpCodes::SkipIfNotPressed(_) => pending.extend([next, next + 2]),
+            OpCodes::PopSubroutine | OpCodes::Exit | OpCodes::Unimplemented => {}
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+// Listing of the instructions reachable from the start of the rom, with
+// labels at branch targets and where I is pointed into data. The bytes no
+// instruction reaches, sprites mostly, are listed as data one byte a line
+// with their pixels. Names from symbols win over the made up ones
+pub fn disassemble(rom: &[u8], start: TypeAddr, symbols: &Symbols) -> String {
+    let code = reachable(rom, start);
+    let lines: Vec<Line> = code
+        .iter()
+        .filter_map(|&addr| {
+            let code = word_at(rom, start, addr)?;
+            Some(Line {
+                addr,
+                code,
+                op: OpCodes::decode_raw(code),
+            })
+        })
+        .collect();
+    let end = start as usize + rom.len();
+    let mut labels = labels(&lines);
+    for line in &lines {
+        if let OpCodes::SetIndexRegister(addr) = line.op {
+            if (start as usize..end).contains(&(addr as usize)) && !code.contains(&addr) {
+                labels
+                    .entry(addr)
+                    .or_insert_with(|| format!("data_{addr:03X}"));
+            }
+        }
+    }
+    for addr in start..end as TypeAddr {
+        if let Some(name) = symbols.name(addr) {
+            labels.insert(addr, name.to_string());
+        }
+    }
+    let addr_name = |addr: TypeAddr| match symbols.name(addr) {
+        Some(name) => name.to_string(),
+        None => labels
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{addr:03X}")),
+    };
+
+    let mut out = String::new();
+    let mut addr = start;
+    while (addr as usize) < end {
+        if let Some(label) = labels.get(&addr) {
+            out.push_str(&format!("{label}:\n"));
+        }
+        match word_at(rom, start, addr).filter(|_| code.contains(&addr)) {
+            Some(word) => {
+                let op = OpCodes::decode_raw(word);
+                out.push_str(&format!(
+                    "{addr:03X}: {word:04X}  {}\n",
+                    op.mnemonic(addr_name)
+                ));
+                addr += 2;
+            }
+            None => {
+                let byte = rom[(addr - start) as usize];
+                let pixels: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                out.push_str(&format!(
+                    "{addr:03X}: {byte:02X}    DB 0x{byte:02X}  {pixels}\n"
+                ));
+                addr += 1;
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_disassemble_labels() {
+    // CALL 208, LD I 20C, DRW, JP 206, CLS, RET and a sprite
+    let rom = [
+        0x22, 0x08, 0xA2, 0x0C, 0xD0, 0x12, 0x12, 0x06, 0x00, 0xE0, 0x00, 0xEE, 0x80, 0x3C,
+    ];
+    let listing = disassemble(&rom, 0x200, &Symbols::new());
+    assert_eq!(
+        listing,
+        "200: 2208  CALL sub_208\n\
+         202: A20C  LD I, data_20C\n\
+         204: D012  DRW V0, V1, 2\n\
+         lbl_206:\n\
+         206: 1206  JP lbl_206\n\
+         sub_208:\n\
+         208: 00E0  CLS\n\
+         20A: 00EE  RET\n\
+         data_20C:\n\
+         20C: 80    DB 0x80  #.......\n\
+         20D: 3C    DB 0x3C  ..####..\n"
+    );
+}
+
+#[test]
+fn test_reachable() {
+    // SE V0 0, JP 208, then JP V0 into the table of jumps at 208, which go
+    // to 20E and back to 208, the rest is data apart from the EXIT at 20E
+    let rom = [
+        0x30, 0x00, 0x12, 0x08, 0xB2, 0x08, 0xFF, 0xFF, 0x12, 0x0E, 0x12, 0x08, 0x00, 0x00, 0x00,
+        0xFD,
+    ];
+    let code: Vec<TypeAddr> = reachable(&rom, 0x200).into_iter().collect();
+    assert_eq!(code, [0x200, 0x202, 0x204, 0x208, 0x20A, 0x20E]);
+}
+
+#[test]
+fn test_disassemble_symbols() {
+    // CALL 206, LD I 208, CLS, RET, a sprite byte
+    let rom = [0x22, 0x06, 0xA2, 0x08, 0x00, 0xE0, 0x00, 0xEE, 0x80];
+    let symbols = Symbols::parse("200 main\n206 draw\n208 dot").unwrap();
+    let listing = disassemble(&rom, 0x200, &symbols);
+    assert_eq!(
+        listing,
+        "main:\n\
+         200: 2206  CALL draw\n\
+         202: A208  LD I, dot\n\
+         204: 00E0  CLS\n\
+         draw:\n\
+         206: 00EE  RET\n\
+         dot:\n\
+         208: 80    DB 0x80  #.......\n"
+    );
+}
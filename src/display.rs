@@ -1,120 +1,336 @@
-use minifb::{Key, Scale, Window, WindowOptions};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, ops::Range};
 
-use crate::keyboard::Keyboard;
+use serde::{Deserialize, Serialize};
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+use crate::database;
 
-const BLACK: (u16, u16, u16) = (34, 34, 51);
-const WHITE: (u16, u16, u16) = (170, 204, 255);
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
 
+// SCHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedScreen")]
 pub struct FrameBuffer {
-    bit_buffer: Vec<u32>,
-    pixel_buffer: Vec<u32>,
-    pub window: Window,
-    should_update: bool,
-    pub keyboard: Keyboard,
+    // one per row of the current resolution, pixel x is bit 127 - x so a
+    // sprite row is drawn with a shift and an XOR
+    rows: Vec<u128>,
+    // rows changed since they were last taken, None when nothing did
+    #[serde(skip)]
+    dirty: Option<Range<usize>>,
+    hires: bool,
+    // 64x64 hi-res CHIP-8, twice the rows of the low resolution screen
+    two_page: bool,
+}
+
+// A screen as read from a savestate, before its rows are checked against the
+// resolution it was saved in
+#[derive(Deserialize)]
+struct SavedScreen {
+    rows: Vec<u128>,
+    hires: bool,
+    two_page: bool,
+}
+
+impl TryFrom<SavedScreen> for FrameBuffer {
+    type Error = String;
+
+    fn try_from(saved: SavedScreen) -> Result<Self, Self::Error> {
+        let fb = Self {
+            rows: saved.rows,
+            dirty: None,
+            hires: saved.hires,
+            two_page: saved.two_page,
+        };
+        if fb.rows.len() != fb.height() {
+            return Err(format!(
+                "a {}x{} screen with {} rows",
+                fb.width(),
+                fb.height(),
+                fb.rows.len()
+            ));
+        }
+        Ok(fb)
+    }
 }
 
 impl FrameBuffer {
     pub fn new() -> Self {
-        let mut window = Window::new(
-            "emuchip - ESC to exit",
-            WIDTH,
-            HEIGHT,
-            WindowOptions {
-                scale: Scale::X16,
-                ..WindowOptions::default()
-            },
-        )
-        .unwrap();
-        window.set_position(500, 300);
-        // Limit to max ~60 fps update rate
-        window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
-        let black_color = Self::from_u16_rgb(BLACK);
         Self {
-            bit_buffer: vec![0; WIDTH * HEIGHT],
-            pixel_buffer: vec![black_color; WIDTH * HEIGHT],
-            window,
-            should_update: false,
-            keyboard: Keyboard::new(),
+            rows: vec![0; HEIGHT],
+            dirty: None,
+            hires: false,
+            two_page: false,
         }
     }
 
-    pub fn clear_buffer(&mut self) {
-        let black_color = Self::from_u16_rgb(BLACK);
-        self.bit_buffer = vec![0; WIDTH * HEIGHT];
-        self.pixel_buffer = vec![black_color; WIDTH * HEIGHT];
-        self.should_update = true;
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
     }
 
-    pub fn sync(&mut self) {
-        if self.should_update {
-            self.window
-                .update_with_buffer(&self.pixel_buffer, WIDTH, HEIGHT)
-                .unwrap();
+    pub fn height(&self) -> usize {
+        if self.hires || self.two_page {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
         }
     }
 
-    fn from_u16_rgb((r, g, b): (u16, u16, u16)) -> u32 {
-        let (r, g, b) = (r as u32, g as u32, b as u32);
-        (r << 16) | (g << 8) | b
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn is_two_page(&self) -> bool {
+        self.two_page
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        (self.rows[y] >> (127 - x)) & 1 != 0
+    }
+
+    // the packed rows, for comparing screens a row at a time
+    pub fn rows(&self) -> &[u128] {
+        &self.rows
+    }
+
+    // row-major pixel states for the current resolution, true is on, for
+    // frontends drawing pixel by pixel
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        let width = self.width();
+        self.rows
+            .iter()
+            .flat_map(move |row| (0..width).map(move |x| (row >> (127 - x)) & 1 != 0))
+    }
+
+    pub fn lit(&self) -> usize {
+        self.rows.iter().map(|row| row.count_ones() as usize).sum()
+    }
+
+    // the bits of a row that are on screen
+    fn mask(&self) -> u128 {
+        u128::MAX << (128 - self.width())
+    }
+
+    // the SHA-1 of the screen as printed, to check test roms against
+    pub fn sha1(&self) -> String {
+        database::sha1(self.to_string().as_bytes())
+    }
+
+    // whether anything changed since the last call
+    pub fn take_update(&mut self) -> bool {
+        self.take_dirty_rows().is_some()
+    }
+
+    // the rows that changed since the last call, for frontends that only
+    // redraw those
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+
+    fn mark_rows(&mut self, rows: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(rows.start)..dirty.end.max(rows.end),
+            None => rows,
+        });
+    }
+
+    // force a redraw, e.g. after swapping in a saved screen
+    pub fn mark_updated(&mut self) {
+        self.mark_rows(0..self.height());
+    }
+
+    pub fn clear_buffer(&mut self) {
+        self.rows = vec![0; self.height()];
+        self.mark_updated();
+    }
+
+    // switching resolution also clears the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.clear_buffer();
     }
 
-    pub fn paint(&mut self, x: u8, y: u8, sprite: Vec<u8>) -> bool {
-        let (x, y) = (x as usize % (WIDTH), y as usize % (HEIGHT));
+    pub fn set_two_page(&mut self, two_page: bool) {
+        self.two_page = two_page;
+        self.clear_buffer();
+    }
+
+    pub fn paint(&mut self, x: u8, y: u8, sprite: Vec<u8>, wrap: bool) -> bool {
+        let rows: Vec<u16> = sprite.iter().map(|row| *row as u16).collect();
+        self.paint_rows(x, y, &rows, 8, wrap)
+    }
+
+    // 16x16 SCHIP sprite, each row is two bytes
+    pub fn paint_large(&mut self, x: u8, y: u8, sprite: Vec<u8>, wrap: bool) -> bool {
+        let rows: Vec<u16> = sprite
+            .chunks(2)
+            .map(|pair| ((pair[0] as u16) << 8) | pair[1] as u16)
+            .collect();
+        self.paint_rows(x, y, &rows, 16, wrap)
+    }
+
+    fn paint_rows(&mut self, x: u8, y: u8, rows: &[u16], sprite_width: usize, wrap: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let (x, y) = (x as usize % width, y as usize % height);
+        let mask = self.mask();
         let mut vf = false;
-        for (i, row) in sprite.iter().enumerate() {
-            for j in 0..8 {
-                let (nx, ny) = (x as usize + j, y as usize + i);
-                let index = (ny * WIDTH) + nx;
-                let bit = (row >> (7 - j)) & 1;
-                if index >= self.bit_buffer.len() {
-                    continue; // should not wrap, cut-off instead
-                }
-                let previous = self.bit_buffer[index];
-                self.bit_buffer[index] ^= bit as u32;
-                if previous != self.bit_buffer[index] && self.bit_buffer[index] == 0 {
-                    vf = true;
-                }
-
-                match self.bit_buffer[index] {
-                    0 => {
-                        self.pixel_buffer[index] = Self::from_u16_rgb(BLACK);
-                    }
-                    1 => {
-                        self.pixel_buffer[index] = Self::from_u16_rgb(WHITE);
-                    }
-                    _ => {}
-                }
+        // with wrapping the rows drawn can be anywhere
+        let mut drawn = height..0;
+        for (i, row) in rows.iter().enumerate() {
+            let mut ny = y + i;
+            if wrap {
+                ny %= height;
+            }
+            if ny >= height {
+                continue; // should not wrap, cut-off instead
             }
+            drawn = drawn.start.min(ny)..drawn.end.max(ny + 1);
+            let left = (*row as u128) << (128 - sprite_width);
+            let mut bits = left >> x;
+            if wrap {
+                // what goes past the right edge comes back on the left
+                bits |= left.checked_shl((width - x) as u32).unwrap_or(0);
+            }
+            bits &= mask;
+            // a pixel turned off is a collision
+            vf |= self.rows[ny] & bits != 0;
+            self.rows[ny] ^= bits;
+        }
+        if !drawn.is_empty() {
+            self.mark_rows(drawn);
         }
-        self.should_update = true;
         vf
     }
 
-    pub fn check_for_keys(&mut self) {
-        self.keyboard.reset();
-        self.window
-            .get_keys()
-            .iter()
-            .for_each(|key| self.keyboard.update_key(key));
+    pub fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.rows.len());
+        self.rows.rotate_right(n);
+        self.rows[..n].fill(0);
+        self.mark_updated();
     }
 
-    pub fn wait_for_key(&mut self) -> u8 {
-        self.sync();
-        let mut key: Result<u8, ()> = Err(());
-        let mut keys = self.window.get_keys();
-        while key == Err(()) {
-            while keys.is_empty() {
-                self.sync();
-                keys = self.window.get_keys();
-            }
-            key = self.keyboard.key_to_num(keys[0]);
-            self.sync();
+    pub fn scroll_right(&mut self, n: usize) {
+        let mask = self.mask();
+        for row in &mut self.rows {
+            *row = (*row >> n) & mask;
+        }
+        self.mark_updated();
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        for row in &mut self.rows {
+            *row <<= n;
+        }
+        self.mark_updated();
+    }
+}
 
-            keys = self.window.get_keys();
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ascii art of the screen, one line per row
+impl fmt::Display for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                write!(f, "{}", if self.get(x, y) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
         }
-        key.unwrap()
+        Ok(())
     }
 }
+
+#[test]
+fn test_paint_clips_at_edges() {
+    let mut fb = FrameBuffer::new();
+    // a full row two pixels from the right edge, and a column off the bottom
+    fb.paint(62, 10, vec![0xFF], false);
+    fb.paint(0, 31, vec![0x80, 0x80], false);
+    assert!(fb.get(62, 10) && fb.get(63, 10) && fb.get(0, 31));
+    // nothing bleeds onto the next row or back to the top
+    assert!(!fb.get(0, 11) && !fb.get(0, 0));
+    assert_eq!(fb.lit(), 3);
+}
+
+#[test]
+fn test_paint_wraps_around() {
+    let mut fb = FrameBuffer::new();
+    fb.paint(62, 31, vec![0xE0, 0x80], true);
+    assert!(fb.get(62, 31) && fb.get(63, 31) && fb.get(0, 31));
+    assert!(fb.get(62, 0));
+    assert_eq!(fb.lit(), 4);
+}
+
+#[test]
+fn test_dirty_rows() {
+    let mut fb = FrameBuffer::new();
+    assert_eq!(fb.take_dirty_rows(), None);
+    fb.paint(0, 10, vec![0xFF, 0xFF], false);
+    fb.paint(0, 4, vec![0xFF], false);
+    assert_eq!(fb.take_dirty_rows(), Some(4..12));
+    assert!(!fb.take_update());
+
+    // wrapped from the bottom to the top, then clipped at the bottom
+    fb.paint(0, 31, vec![0xFF, 0xFF], true);
+    assert_eq!(fb.take_dirty_rows(), Some(0..32));
+    fb.paint(0, 31, vec![0xFF, 0xFF], false);
+    assert_eq!(fb.take_dirty_rows(), Some(31..32));
+    fb.scroll_down(1);
+    assert_eq!(fb.take_dirty_rows(), Some(0..32));
+}
+
+#[test]
+fn test_hires_rows() {
+    let mut fb = FrameBuffer::new();
+    fb.set_hires(true);
+    // across the right edge of the full 128 pixels and back on the left
+    assert!(!fb.paint(124, 63, vec![0xFF], true));
+    assert!(fb.get(127, 63) && fb.get(0, 63) && fb.get(3, 63) && !fb.get(4, 63));
+    assert_eq!(fb.rows()[63], 0xF << 124 | 0xF);
+    assert!(fb.paint(0, 63, vec![0x80], false));
+
+    // scrolling drops what goes past either edge
+    fb.scroll_left(4);
+    assert_eq!(fb.lit(), 4);
+    assert!(fb.get(120, 63) && fb.get(123, 63) && !fb.get(124, 63));
+    fb.scroll_right(6);
+    assert_eq!(fb.rows()[63], 0b11);
+    fb.scroll_down(1);
+    assert_eq!(fb.lit(), 0);
+    assert_eq!(fb.pixels().count(), HIRES_WIDTH * HIRES_HEIGHT);
+}
+
+#[test]
+fn test_saved_screen_size() {
+    let mut fb = FrameBuffer::new();
+    fb.set_two_page(true);
+    let saved = serde_json::to_string(&fb).unwrap();
+    let loaded: FrameBuffer = serde_json::from_str(&saved).unwrap();
+    assert_eq!(loaded.rows().len(), HIRES_HEIGHT);
+
+    // the rows of a low resolution screen, saved as one and as hi-res
+    let screen = |hires: bool| {
+        let rows = vec!["0"; HEIGHT].join(",");
+        let json = format!(r#"{{"rows":[{rows}],"hires":{hires},"two_page":false}}"#);
+        serde_json::from_str::<FrameBuffer>(&json)
+    };
+    assert!(screen(false).is_ok());
+    let err = screen(true).err().unwrap().to_string();
+    assert!(err.starts_with("a 128x64 screen with 32 rows"), "{err}");
+}
@@ -0,0 +1,102 @@
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::PixelColor,
+    primitives::Rectangle,
+    Drawable,
+};
+
+use crate::display::FrameBuffer;
+
+// The screen as an embedded-graphics drawable, e.g. for an SSD1306:
+//   Screen::new(chip8.framebuffer(), BinaryColor::On, BinaryColor::Off).draw(&mut display)
+// Pixels are scaled up as far as the display fits them, and centered
+pub struct Screen<'a, C> {
+    fb: &'a FrameBuffer,
+    on: C,
+    off: C,
+}
+
+impl<'a, C: PixelColor> Screen<'a, C> {
+    pub fn new(fb: &'a FrameBuffer, on: C, off: C) -> Self {
+        Self { fb, on, off }
+    }
+
+    // where the screen ends up on a display with these bounds
+    pub fn area(&self, bounds: Rectangle) -> Rectangle {
+        let (width, height) = (self.fb.width() as u32, self.fb.height() as u32);
+        let scale = (bounds.size.width / width)
+            .min(bounds.size.height / height)
+            .max(1);
+        let size = Size::new(width * scale, height * scale);
+        let margin = Point::new(
+            (bounds.size.width.saturating_sub(size.width) / 2) as i32,
+            (bounds.size.height.saturating_sub(size.height) / 2) as i32,
+        );
+        Rectangle::new(bounds.top_left + margin, size)
+    }
+}
+
+impl<C: PixelColor> Drawable for Screen<'_, C> {
+    type Color = C;
+    type Output = ();
+
+    // one fill of the whole area, which suits displays written to over SPI
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let area = self.area(target.bounding_box());
+        let scale = area.size.width as usize / self.fb.width();
+        let colors = (0..area.size.height as usize).flat_map(|y| {
+            (0..area.size.width as usize).map(move |x| {
+                if self.fb.get(x / scale, y / scale) {
+                    self.on
+                } else {
+                    self.off
+                }
+            })
+        });
+        target.fill_contiguous(&area, colors)
+    }
+}
+
+#[test]
+fn test_draw_to_display() {
+    use core::convert::Infallible;
+    use embedded_graphics_core::{geometry::OriginDimensions, pixelcolor::BinaryColor, Pixel};
+
+    // 132x70, room for the low resolution screen at twice the size
+    struct Display(Vec<BinaryColor>);
+    impl OriginDimensions for Display {
+        fn size(&self) -> Size {
+            Size::new(132, 70)
+        }
+    }
+    impl DrawTarget for Display {
+        type Color = BinaryColor;
+        type Error = Infallible;
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = Pixel<BinaryColor>>,
+        {
+            for Pixel(point, color) in pixels {
+                self.0[point.y as usize * 132 + point.x as usize] = color;
+            }
+            Ok(())
+        }
+    }
+
+    let mut fb = FrameBuffer::new();
+    fb.paint(0, 0, vec![0x80], false);
+    let mut display = Display(vec![BinaryColor::Off; 132 * 70]);
+    Screen::new(&fb, BinaryColor::On, BinaryColor::Off)
+        .draw(&mut display)
+        .unwrap();
+    let lit: Vec<(usize, usize)> = (0..132 * 70)
+        .filter(|i| display.0[*i] == BinaryColor::On)
+        .map(|i| (i % 132, i / 132))
+        .collect();
+    // the top left pixel, doubled and moved in by the margins
+    assert_eq!(lit, [(2, 3), (3, 3), (2, 4), (3, 4)]);
+}
@@ -1,50 +1,299 @@
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::ops::Range;
+
+#[cfg(feature = "std")]
+use crate::trace::Tracer;
 use crate::{
-    decode::OpCodes, display::FrameBuffer, memory::Memory, registers::Registers, sound::Sound,
+    audio::{AudioPattern, DEFAULT_PITCH},
+    bus::Bus,
+    coverage::Coverage,
+    database,
+    decode::OpCodes,
+    display::FrameBuffer,
+    error::EmulatorError,
+    keyboard::Keyboard,
+    memory::{Memory, TypeAddr, BIG_FONT_START, FONT_START, MEMORY_SIZE, PROGRAM_START},
+    profile::Profiler,
+    quirks::{MachineRoutines, OutOfBounds, Quirks},
+    registers::Registers,
+    snapshot::Snapshot,
+    timer::Timers,
 };
-use minifb::{Key, KeyRepeat};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// what a single instruction did, for tools watching the machine run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepOutcome {
+    pub opcode: u16,
+    pub operation: OpCodes,
+    pub pc_before: TypeAddr,
+    pub pc_after: TypeAddr,
+    // bit n is set when Vn holds a different value afterwards
+    pub written: u16,
+    pub drew: bool,
+    pub sound_started: bool,
+}
+
+impl StepOutcome {
+    pub fn wrote(&self, reg: u8) -> bool {
+        self.written & (1 << reg) != 0
+    }
+}
+
+// called after every instruction that ran without an error
+pub type Observer = Box<dyn FnMut(&StepOutcome) + Send>;
 
-pub struct Emulator {
+// runs the machine code routine at the address for 0NNN, false when it isn't
+// one it knows
+pub type RoutineHandler = Box<dyn FnMut(TypeAddr, &mut Registers, &mut Memory) -> bool + Send>;
+
+pub struct Chip8 {
     fb: FrameBuffer,
     pub regs: Registers,
     pub mem: Memory,
-    pub delay_timer: u8,
-    pub sound_timer: u8,
-    pub sound: Sound,
+    pub timers: Timers,
+    pub keyboard: Keyboard,
+    pub quirks: Quirks,
+    // for the 0NNN calls the handler doesn't take
+    pub machine_routines: MachineRoutines,
+    // a jump to itself, how most roms stop for good, ends the program rather
+    // than spinning until the frontend closes
+    pub exit_on_halt: bool,
+    routine_handler: Option<RoutineHandler>,
+    // same seed and same input gives the same run
+    rng: ChaCha8Rng,
+    seed: u64,
+    #[cfg(feature = "std")]
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    observers: Vec<Observer>,
+    // claimed address ranges, in the order they were mapped
+    peripherals: Vec<(Range<usize>, Box<dyn Bus>)>,
+    // kept for reset, memory may have been written to since
+    rom: Vec<u8>,
+    // identifies the rom in the database
+    rom_sha1: String,
+    // set on every display refresh, cleared by a draw when display_wait is on
+    vblank: bool,
+    // XO-CHIP audio, None until the rom loads a pattern
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+    // address of the instruction being executed, for errors
+    current: TypeAddr,
+    // SCHIP RPL flags, 8 on the HP-48 and 16 on XO-CHIP, kept across resets
+    // like the calculator kept them between programs
+    flags: [u8; 16],
 }
 
-impl Emulator {
-    pub fn init() -> Self {
-        let mut mem = Memory::new();
+impl Chip8 {
+    // seeded from the OS
+    #[cfg(feature = "std")]
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_seed(quirks, rand::thread_rng().gen())
+    }
+
+    // without std there's nothing to pick a random seed from
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Self {
+        Self {
+            regs: Registers::new(),
+            mem: Memory::new(),
+            fb: FrameBuffer::new(),
+            timers: Timers::new(),
+            keyboard: Keyboard::new(),
+            quirks,
+            machine_routines: MachineRoutines::default(),
+            exit_on_halt: false,
+            routine_handler: None,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+            #[cfg(feature = "std")]
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            observers: vec![],
+            peripherals: vec![],
+            rom: vec![],
+            rom_sha1: database::sha1(&[]),
+            vblank: false,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            current: PROGRAM_START,
+            flags: [0; 16],
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    pub fn load_rom(&mut self, bytes: &[u8]) -> Result<(), EmulatorError> {
+        if bytes.is_empty() {
+            return Err(EmulatorError::EmptyRom);
+        }
+        let max = MEMORY_SIZE.saturating_sub(self.quirks.load_addr as usize);
+        if bytes.len() > max {
+            return Err(EmulatorError::RomTooLarge {
+                size: bytes.len(),
+                max,
+            });
+        }
+        self.rom = bytes.to_vec();
+        self.rom_sha1 = database::sha1(bytes);
+        self.mem.load_rom(bytes, self.quirks.load_addr);
+        self.start_two_page();
+        Ok(())
+    }
+
+    // Hi-res CHIP-8 roms start with 1260, a jump into the patch the original
+    // interpreter needed for its 64x64 mode. The program proper is at 2C0,
+    // so that's where the jump goes instead
+    fn start_two_page(&mut self) {
+        let two_page = self.rom.starts_with(&[0x12, 0x60]);
+        if two_page != self.fb.is_two_page() {
+            self.fb.set_two_page(two_page);
+        }
+        if two_page {
+            self.mem.write(PROGRAM_START as usize + 1, 0xC0);
+        }
+    }
+
+    // for frontends to keep between runs
+    pub fn flags(&self) -> [u8; 16] {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: [u8; 16]) {
+        self.flags = flags;
+    }
+
+    pub fn rom_sha1(&self) -> &str {
+        &self.rom_sha1
+    }
+
+    // where the rom is loaded, with the quirks it was loaded with
+    pub fn rom_range(&self) -> Range<TypeAddr> {
+        let start = self.quirks.load_addr;
+        start..start + self.rom.len() as TypeAddr
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_rom_by_file(&mut self, path: &str) -> Result<(), EmulatorError> {
+        let program = std::fs::read(path).map_err(|err| EmulatorError::RomUnreadable {
+            path: path.to_string(),
+            reason: err.to_string(),
+        })?;
+        self.load_rom(&program)
+    }
+
+    // power cycle with the same rom, quirks and seed
+    pub fn reset(&mut self) {
+        self.regs = Registers::new();
+        self.mem = Memory::new();
+        self.mem.load_rom(&self.rom, self.quirks.load_addr);
+        self.fb = FrameBuffer::new();
+        self.fb.mark_updated();
+        self.start_two_page();
+        self.timers = Timers::new();
+        self.keyboard.reset();
+        self.set_seed(self.seed);
+        self.vblank = false;
+        self.pattern = None;
+        self.pitch = DEFAULT_PITCH;
+    }
+
+    pub fn fetch_decode(&mut self) -> Result<OpCodes, EmulatorError> {
+        self.fetch().map(|(_, op)| op)
+    }
 
-        if let Some(rom) = std::env::args().nth(1) {
-            mem.load_rom_by_file(&rom);
+    // the opcode along with the instruction, for tracing
+    fn fetch(&mut self) -> Result<(u16, OpCodes), EmulatorError> {
+        let pc = self.mem.pc.0;
+        self.current = pc;
+        let decoded = self.mem.op_at(pc).ok_or(EmulatorError::OutOfBoundsAccess {
+            addr: pc,
+            target: pc as usize + 1,
+        })?;
+        self.advance()?;
+        Ok(decoded)
+    }
+
+    // moves on to the next instruction, which has to be within the rom
+    fn advance(&mut self) -> Result<(), EmulatorError> {
+        if self.mem.increment_pc() {
+            Ok(())
         } else {
-            panic!("supply a rom file")
+            Err(EmulatorError::ProgramEnded { addr: self.current })
         }
+    }
 
-        let regs = Registers::new();
-        let fb = FrameBuffer::new();
-        let sound = Sound::new();
+    // where an access to `target` lands, None when the quirk makes it an error
+    fn resolve(&self, target: usize) -> Option<usize> {
+        if target < MEMORY_SIZE {
+            return Some(target);
+        }
+        match self.quirks.out_of_bounds {
+            OutOfBounds::Wrap => Some(target % MEMORY_SIZE),
+            OutOfBounds::Clamp => Some(MEMORY_SIZE - 1),
+            OutOfBounds::Error => None,
+        }
+    }
 
-        Self {
-            regs,
-            mem,
-            fb,
-            delay_timer: 0,
-            sound_timer: 0,
-            sound,
+    // the latest peripheral mapped over addr or else memory, with addr made
+    // relative to the start of the peripheral
+    fn bus(&mut self, addr: usize) -> (&mut dyn Bus, usize) {
+        let mapped = self.peripherals.iter_mut().rev();
+        match mapped.into_iter().find(|(range, _)| range.contains(&addr)) {
+            Some((range, peripheral)) => (peripheral.as_mut(), addr - range.start),
+            None => (&mut self.mem, addr),
         }
     }
 
-    pub fn fetch_decode(&mut self) -> OpCodes {
-        let ins = self.mem.next_instruction();
-        OpCodes::decode_raw(ins)
+    fn read(&mut self, target: usize) -> Result<u8, EmulatorError> {
+        self.resolve(target)
+            .and_then(|addr| {
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.read(addr);
+                }
+                let (bus, addr) = self.bus(addr);
+                bus.read(addr)
+            })
+            .ok_or(EmulatorError::OutOfBoundsAccess {
+                addr: self.current,
+                target,
+            })
+    }
+
+    fn write(&mut self, target: usize, val: u8) -> Result<(), EmulatorError> {
+        let written = self.resolve(target).is_some_and(|addr| {
+            if let Some(coverage) = &mut self.coverage {
+                coverage.written(addr);
+            }
+            let (bus, addr) = self.bus(addr);
+            bus.write(addr, val)
+        });
+        if written {
+            Ok(())
+        } else {
+            Err(EmulatorError::OutOfBoundsAccess {
+                addr: self.current,
+                target,
+            })
+        }
     }
 
-    pub fn execute_ins(&mut self, ins: OpCodes) {
+    pub fn execute_ins(&mut self, ins: OpCodes) -> Result<(), EmulatorError> {
         match ins {
             OpCodes::Jump(addr) => {
+                if self.exit_on_halt && addr == self.current {
+                    return Err(EmulatorError::Halted { addr });
+                }
                 self.mem.set_pc(addr);
             }
             OpCodes::SetRegister(vx, nn) => {
@@ -57,25 +306,78 @@ impl Emulator {
             OpCodes::ClearScreen => {
                 self.fb.clear_buffer();
             }
+            OpCodes::TwoPageClear if self.fb.is_two_page() => self.fb.clear_buffer(),
+            OpCodes::Display(..) | OpCodes::DisplayLarge(..)
+                if self.quirks.display_wait && !self.vblank =>
+            {
+                // retry the draw after the next display refresh
+                self.mem.decrement_pc();
+            }
             OpCodes::Display(reg_x, reg_y, height) => {
+                self.vblank = false;
+
                 let (x, y) = (self.regs.get(reg_x), self.regs.get(reg_y));
                 // From I to I + N, plot I at VX, VY
                 // Simply XOR with existing fb data
                 let mut sprite: Vec<u8> = vec![];
-                for addr in self.mem.index.0..self.mem.index.0 + height as u16 {
-                    let row = self.mem.get(addr); // 8 pixels wide because u8
+                let index = self.mem.index.0 as usize;
+                for addr in index..index + height as usize {
+                    let row = self.read(addr)?; // 8 pixels wide because u8
                     sprite.push(row);
                 }
 
-                let vf = self.fb.paint(x, y, sprite) as u8;
+                let vf = self.fb.paint(x, y, sprite, self.quirks.wrap_sprites) as u8;
                 self.regs.set_register(0xF, vf);
             }
+            OpCodes::DisplayLarge(reg_x, reg_y) => {
+                self.vblank = false;
+                let (x, y) = (self.regs.get(reg_x), self.regs.get(reg_y));
+                // 16 rows of 2 bytes each
+                let mut sprite: Vec<u8> = vec![];
+                let index = self.mem.index.0 as usize;
+                for addr in index..index + 32 {
+                    sprite.push(self.read(addr)?);
+                }
+
+                let vf = self.fb.paint_large(x, y, sprite, self.quirks.wrap_sprites) as u8;
+                self.regs.set_register(0xF, vf);
+            }
+            OpCodes::LowRes => self.fb.set_hires(false),
+            OpCodes::HighRes => self.fb.set_hires(true),
+            // the program counter stays on it, so it keeps exiting
+            OpCodes::Exit => return Err(EmulatorError::Exited { addr: self.current }),
+            OpCodes::MachineRoutine(routine) => {
+                let handled = match &mut self.routine_handler {
+                    Some(handler) => handler(routine, &mut self.regs, &mut self.mem),
+                    None => false,
+                };
+                if !handled && self.machine_routines == MachineRoutines::Halt {
+                    return Err(EmulatorError::MachineRoutine {
+                        addr: self.current,
+                        routine,
+                    });
+                }
+            }
+            OpCodes::ScrollDown(n) => self.fb.scroll_down(n as usize),
+            OpCodes::ScrollRight => self.fb.scroll_right(4),
+            OpCodes::ScrollLeft => self.fb.scroll_left(4),
             OpCodes::PushSubroutine(addr) => {
+                let depth = self.quirks.stack_depth;
+                if self.mem.stack.addresses().len() >= depth as usize {
+                    return Err(EmulatorError::StackOverflow {
+                        addr: self.current,
+                        depth,
+                    });
+                }
                 self.mem.stack.push(self.mem.pc.0); // store current instruction to return back
                 self.mem.set_pc(addr);
             }
             OpCodes::PopSubroutine => {
-                let addr = self.mem.stack.pop().unwrap();
+                let addr = self
+                    .mem
+                    .stack
+                    .pop()
+                    .ok_or(EmulatorError::StackUnderflow { addr: self.current })?;
                 self.mem.set_pc(addr);
             }
             OpCodes::CopyRegister(vx, vy) => {
@@ -84,14 +386,23 @@ impl Emulator {
             OpCodes::Or(vx, vy) => {
                 self.regs
                     .set_register(vx, self.regs.get(vy) | self.regs.get(vx));
+                if self.quirks.vf_reset {
+                    self.regs.set_register(0xf, 0);
+                }
             }
             OpCodes::And(vx, vy) => {
                 self.regs
                     .set_register(vx, self.regs.get(vy) & self.regs.get(vx));
+                if self.quirks.vf_reset {
+                    self.regs.set_register(0xf, 0);
+                }
             }
             OpCodes::XOr(vx, vy) => {
                 self.regs
                     .set_register(vx, self.regs.get(vy) ^ self.regs.get(vx));
+                if self.quirks.vf_reset {
+                    self.regs.set_register(0xf, 0);
+                }
             }
             OpCodes::Add(vx, vy) => {
                 let (x, y) = (self.regs.get(vy), self.regs.get(vx));
@@ -127,8 +438,12 @@ impl Emulator {
                     self.regs.set_register(0xf, 0); // borrow
                 }
             }
-            OpCodes::LeftShift(vx, _) => {
-                let vx_value = self.regs.get(vx);
+            OpCodes::LeftShift(vx, vy) => {
+                let vx_value = if self.quirks.shift_uses_vy {
+                    self.regs.get(vy)
+                } else {
+                    self.regs.get(vx)
+                };
 
                 let vf = (vx_value >> 7) & 1;
                 let vx_value = vx_value << 1;
@@ -136,8 +451,12 @@ impl Emulator {
                 self.regs.set_register(vx, vx_value);
                 self.regs.set_register(0xf, vf);
             }
-            OpCodes::RightShift(vx, _) => {
-                let vx_value = self.regs.get(vx);
+            OpCodes::RightShift(vx, vy) => {
+                let vx_value = if self.quirks.shift_uses_vy {
+                    self.regs.get(vy)
+                } else {
+                    self.regs.get(vx)
+                };
 
                 let vf = vx_value & 1;
                 let vx_value = vx_value >> 1;
@@ -146,41 +465,69 @@ impl Emulator {
                 self.regs.set_register(0xf, vf);
             }
             OpCodes::Random(vx, nn) => {
-                let mut rng = rand::thread_rng();
-                let ransuu = rng.gen_range(0..=255);
+                let ransuu: u8 = self.rng.gen();
                 self.regs.set_register(vx, nn & ransuu);
             }
             OpCodes::JumpWithOffset(addr) => {
-                self.mem.set_pc(addr + self.regs.get(0) as u16);
+                let offset_reg = if self.quirks.jump_uses_vx {
+                    (addr >> 8) as u8
+                } else {
+                    0
+                };
+                self.mem.set_pc(addr + self.regs.get(offset_reg) as u16);
             }
             OpCodes::AddToIndex(vx) => {
-                self.mem
-                    .set_index(self.mem.index.0 + self.regs.get(vx) as u16);
+                let index = self.mem.index.0.wrapping_add(self.regs.get(vx) as u16);
+                self.mem.set_index(index);
+                if self.quirks.index_overflow {
+                    self.regs.set_register(0xF, (index > 0xFFF) as u8);
+                }
             }
             OpCodes::SkipEqualConstant(vx, nn) => {
                 if self.regs.get(vx) == nn {
-                    self.mem.increment_pc();
+                    self.advance()?;
                 }
             }
             OpCodes::SkipNotEqualConstant(vx, nn) => {
                 if self.regs.get(vx) != nn {
-                    self.mem.increment_pc();
+                    self.advance()?;
                 }
             }
             OpCodes::SkipEqualRegister(vx, vy) => {
                 if self.regs.get(vx) == self.regs.get(vy) {
-                    self.mem.increment_pc();
+                    self.advance()?;
                 }
             }
             OpCodes::SkipNotEqualRegister(vx, vy) => {
                 if self.regs.get(vx) != self.regs.get(vy) {
-                    self.mem.increment_pc();
+                    self.advance()?;
                 }
             }
             OpCodes::PointChar(vx) => {
-                let char = self.regs.get(vx);
-                let addr = 0x50 + char * 5;
-                self.mem.set_index(addr as u16);
+                let char = self.regs.get(vx) as u16;
+                self.mem.set_index(FONT_START + char * 5);
+            }
+            OpCodes::PointBigChar(vx) => {
+                let char = self.regs.get(vx) as u16;
+                self.mem.set_index(BIG_FONT_START + char * 10);
+            }
+            OpCodes::LoadAudio => {
+                let mut pattern = [0; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read(self.mem.index.0 as usize + offset)?;
+                }
+                self.pattern = Some(pattern);
+            }
+            OpCodes::SetPitch(vx) => self.pitch = self.regs.get(vx),
+            OpCodes::StoreFlags(vx) => {
+                for reg in 0..=vx {
+                    self.flags[reg as usize] = self.regs.get(reg);
+                }
+            }
+            OpCodes::LoadFlags(vx) => {
+                for reg in 0..=vx {
+                    self.regs.set_register(reg, self.flags[reg as usize]);
+                }
             }
             OpCodes::ToDecimal(vx) => {
                 let mut in_decimal = self.regs.get(vx);
@@ -195,69 +542,724 @@ impl Emulator {
                 }
                 digits.reverse();
                 for (i, digit) in digits.iter().enumerate() {
-                    self.mem.set(self.mem.index.0 + i as u16, *(digit));
+                    self.write(self.mem.index.0 as usize + i, *(digit))?;
                 }
             }
             OpCodes::SkipIfPressed(vx) => {
-                self.fb.check_for_keys();
-                if self.fb.keyboard.get_key_status_from_num(self.regs.get(vx)) {
-                    self.mem.pc.increment();
+                if self.keyboard.get_key_status_from_num(self.regs.get(vx)) {
+                    self.advance()?;
                 }
             }
             OpCodes::SkipIfNotPressed(vx) => {
-                self.fb.check_for_keys();
-                if !self.fb.keyboard.get_key_status_from_num(self.regs.get(vx)) {
-                    self.mem.pc.increment();
+                if !self.keyboard.get_key_status_from_num(self.regs.get(vx)) {
+                    self.advance()?;
                 }
             }
-            OpCodes::CopyDelayToRegister(vx) => self.regs.set_register(vx, self.delay_timer),
-            OpCodes::CopyRegisterToDelay(vx) => self.delay_timer = self.regs.get(vx),
-            OpCodes::CopyRegisterToSound(vx) => self.sound_timer = self.regs.get(vx),
+            OpCodes::CopyDelayToRegister(vx) => self.regs.set_register(vx, self.timers.delay),
+            OpCodes::CopyRegisterToDelay(vx) => self.timers.delay = self.regs.get(vx),
+            OpCodes::CopyRegisterToSound(vx) => self.timers.sound = self.regs.get(vx),
             OpCodes::GetKey(vx) => {
-                let key_pressed = self.fb.wait_for_key();
-                self.regs.set_register(vx, key_pressed);
+                if let Some(key) = self.keyboard.wait_key(self.quirks.key_release) {
+                    self.regs.set_register(vx, key);
+                } else {
+                    // block by running this instruction again until a key comes in
+                    self.mem.decrement_pc();
+                }
             }
             OpCodes::LoadRegisterFromMemory(vx) => {
                 for reg in 0..=vx {
-                    let reg_val = self.mem.get(self.mem.index.0 + reg as u16);
+                    let reg_val = self.read(self.mem.index.0 as usize + reg as usize)?;
                     self.regs.set_register(reg, reg_val);
                 }
+                if self.quirks.memory_increments_i {
+                    self.mem
+                        .set_index(self.mem.index.0.wrapping_add(vx as u16 + 1));
+                }
             }
             OpCodes::StoreRegisterToMemory(vx) => {
                 for reg in 0..=vx {
                     let reg_val = self.regs.get(reg);
-                    self.mem.set(self.mem.index.0 + reg as u16, reg_val);
+                    self.write(self.mem.index.0 as usize + reg as usize, reg_val)?;
+                }
+                if self.quirks.memory_increments_i {
+                    self.mem
+                        .set_index(self.mem.index.0.wrapping_add(vx as u16 + 1));
                 }
             }
-            OpCodes::Unimplemented => {}
+            OpCodes::TwoPageClear | OpCodes::Unimplemented => {
+                return Err(EmulatorError::InvalidOpcode {
+                    addr: self.current,
+                    opcode: self.mem.instruction_at(self.current).unwrap_or_default(),
+                })
+            }
         }
+        Ok(())
     }
 
-    pub fn is_running(&self) -> bool {
-        self.fb.window.is_open() && !self.fb.window.is_key_pressed(Key::Escape, KeyRepeat::Yes)
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.fb
     }
 
-    pub fn sync_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-        }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-            self.sound.beep();
+    pub fn framebuffer_mut(&mut self) -> &mut FrameBuffer {
+        &mut self.fb
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keyboard.set_key(key, pressed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            regs: self.regs.clone(),
+            mem: self.mem.clone(),
+            timers: self.timers.clone(),
+            fb: self.fb.clone(),
+            vblank: self.vblank,
+            pattern: self.pattern,
+            pitch: self.pitch,
+            rng: (&self.rng).into(),
         }
     }
 
-    pub fn sync_display(&mut self) {
-        self.fb.sync();
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.regs = snapshot.regs;
+        self.mem = snapshot.mem;
+        self.timers = snapshot.timers;
+        self.fb = snapshot.fb;
+        self.vblank = snapshot.vblank;
+        self.pattern = snapshot.pattern;
+        self.pitch = snapshot.pitch;
+        self.rng = (&snapshot.rng).into();
+        self.fb.mark_updated();
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.timers.is_beeping()
+    }
+
+    // what to play instead of the plain buzzer, once the rom has set a pattern
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        self.pattern.map(|bits| AudioPattern {
+            bits,
+            pitch: self.pitch,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // hands the accesses to a range of addresses to a peripheral instead of
+    // memory, where ranges overlap the one mapped last wins. Peripherals stay
+    // mapped across resets and aren't part of a snapshot
+    pub fn map(&mut self, range: Range<usize>, peripheral: impl Bus + 'static) {
+        self.peripherals.push((range, Box::new(peripheral)));
+    }
+
+    pub fn set_routine_handler(&mut self, handler: Option<RoutineHandler>) {
+        self.routine_handler = handler;
+    }
+
+    pub fn add_observer(&mut self, observer: impl FnMut(&StepOutcome) + Send + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
     }
 
-    pub fn tick(&mut self) {
-        let operation = self.fetch_decode();
-        self.execute_ins(operation);
+    // on an error the program counter is left on the failing instruction
+    pub fn step(&mut self) -> Result<StepOutcome, EmulatorError> {
+        let pc = self.mem.pc.0;
+        let before: [u8; 16] = core::array::from_fn(|reg| self.regs.get(reg as u8));
+        let beeping = self.is_beeping();
+        let result = self.fetch().and_then(|(opcode, operation)| {
+            #[cfg(feature = "std")]
+            if let Some(tracer) = &mut self.tracer {
+                tracer.trace(pc, opcode, operation, &self.regs, &self.mem);
+            }
+            self.execute_ins(operation)?;
+            Ok((opcode, operation))
+        });
+        let (opcode, operation) = result.inspect_err(|_| self.mem.set_pc(pc))?;
+        let pc_after = self.mem.pc.0;
+        let outcome = StepOutcome {
+            opcode,
+            operation,
+            pc_before: pc,
+            pc_after,
+            written: (0..16)
+                .filter(|&reg| self.regs.get(reg) != before[reg as usize])
+                .fold(0, |written, reg| written | 1 << reg),
+            // a draw held back for the display refresh runs again from the same place
+            drew: pc_after != pc
+                && matches!(
+                    operation,
+                    OpCodes::ClearScreen
+                        | OpCodes::TwoPageClear
+                        | OpCodes::Display(..)
+                        | OpCodes::DisplayLarge(..)
+                        | OpCodes::ScrollDown(_)
+                        | OpCodes::ScrollRight
+                        | OpCodes::ScrollLeft
+                        | OpCodes::LowRes
+                        | OpCodes::HighRes
+                ),
+            sound_started: !beeping && self.is_beeping(),
+        };
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, operation, pc_after);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.executed(pc);
+        }
+        for observer in &mut self.observers {
+            observer(&outcome);
+        }
+        Ok(outcome)
     }
 
+    // call once per display refresh (60 times / s)
     pub fn sync(&mut self) {
-        self.sync_timers();
-        self.sync_display();
+        self.timers.tick();
+        self.vblank = true;
+    }
+
+    // one display refresh worth of emulation, timers included
+    pub fn run_frame(&mut self, cycles: u32) -> Result<(), EmulatorError> {
+        for _ in 0..cycles {
+            self.step()?;
+        }
+        self.sync();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_headless_draw() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 0, I = font sprite for 0, draw it at (V0, V0), loop forever
+    chip8
+        .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06])
+        .unwrap();
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+
+    let fb = chip8.framebuffer();
+    assert!(fb.get(0, 0) && fb.get(3, 0) && !fb.get(4, 0));
+    assert!(fb.get(0, 1) && !fb.get(1, 1) && fb.get(3, 1));
+    assert_eq!(chip8.regs.get(0xF), 0);
+}
+
+#[test]
+fn test_reset_reloads_rom() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 5, I = 0x200, store V0 over the first instruction
+    let rom = [0x60, 0x05, 0xA2, 0x00, 0xF0, 0x55];
+    chip8.load_rom(&rom).unwrap();
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.mem.read(0x200), Some(0x05));
+
+    chip8.reset();
+    assert_eq!(chip8.mem.pc.0, 0x200);
+    assert_eq!(chip8.regs.get(0), 0);
+    assert_eq!(chip8.mem.read(0x200), Some(0x60));
+}
+
+#[test]
+fn test_index_overflow_quirk() {
+    // I = 0xFFE, V0 = 2, I += V0
+    let rom = [0xAF, 0xFE, 0x60, 0x02, 0xF0, 0x1E];
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    chip8.regs.set_register(0xF, 7);
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.mem.index.0, 0x1000);
+    assert_eq!(chip8.regs.get(0xF), 7);
+
+    let mut quirks = Quirks::default();
+    quirks.set("index-overflow", true).unwrap();
+    let mut chip8 = Chip8::new(quirks);
+    chip8.load_rom(&rom).unwrap();
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.regs.get(0xF), 1);
+}
+
+#[test]
+fn test_shift_quirk() {
+    use crate::quirks::Platform;
+
+    // V0 = 1, V1 = 0x81, V0 <<= V1
+    let rom = [0x60, 0x01, 0x61, 0x81, 0x80, 0x1E];
+    let run = |quirks: Quirks| {
+        let mut chip8 = Chip8::new(quirks);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        (chip8.regs.get(0), chip8.regs.get(0xF))
+    };
+    // CHIP-48 shifts VX in place, COSMAC shifts a copy of VY
+    assert_eq!(run(Quirks::from_platform(Platform::Chip48)), (0x02, 0));
+    assert_eq!(run(Quirks::from_platform(Platform::Cosmac)), (0x02, 1));
+}
+
+#[test]
+fn test_jump_quirk() {
+    use crate::quirks::Platform;
+
+    // V0 = 0x10, V3 = 0x20, jump to 0x300 plus an offset
+    let rom = [0x60, 0x10, 0x63, 0x20, 0xB3, 0x00];
+    let run = |quirks: Quirks| {
+        let mut chip8 = Chip8::new(quirks);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        chip8.mem.pc.0
+    };
+    // COSMAC adds V0, CHIP-48 and SCHIP add VX where X is the top digit of the address
+    assert_eq!(run(Quirks::from_platform(Platform::Cosmac)), 0x310);
+    assert_eq!(run(Quirks::from_platform(Platform::Schip)), 0x320);
+}
+
+#[test]
+fn test_display_wait_quirk() {
+    let mut quirks = Quirks::default();
+    quirks.set("display-wait", true).unwrap();
+    let mut chip8 = Chip8::new(quirks);
+    // V0 = 0, I = font sprite for 0, draw it twice at (V0, V0)
+    chip8
+        .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05])
+        .unwrap();
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    // nothing is drawn until the display refreshes
+    assert!(!chip8.framebuffer().get(0, 0));
+    assert_eq!(chip8.mem.pc.0, 0x204);
+
+    // then only one draw per refresh
+    chip8.sync();
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    assert!(chip8.framebuffer().get(0, 0));
+    assert_eq!(chip8.mem.pc.0, 0x206);
+
+    chip8.sync();
+    chip8.step().unwrap();
+    assert!(!chip8.framebuffer().get(0, 0));
+
+    // 16x16 sprites wait the same
+    chip8.load_rom(&[0xD0, 0x00]).unwrap();
+    chip8.reset();
+    chip8.step().unwrap();
+    assert_eq!(chip8.mem.pc.0, 0x200);
+    chip8.sync();
+    chip8.step().unwrap();
+    assert_eq!(chip8.mem.pc.0, 0x202);
+}
+
+#[test]
+fn test_vf_reset_quirk() {
+    // VF = 5, V0 = 0x0F, V0 |= V1
+    let rom = [0x6F, 0x05, 0x60, 0x0F, 0x80, 0x11];
+    let run = |quirks: Quirks| {
+        let mut chip8 = Chip8::new(quirks);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        chip8.regs.get(0xF)
+    };
+    assert_eq!(run(Quirks::default()), 5);
+    let mut quirks = Quirks::default();
+    quirks.set("vf-reset", true).unwrap();
+    assert_eq!(run(quirks), 0);
+}
+
+#[test]
+fn test_skip_on_key_state() {
+    // V0 = 5, skip if key V0 is down, skip if it's up, CLS to skip to
+    let rom = [0x60, 0x05, 0xE0, 0x9E, 0x00, 0xE0, 0xE0, 0xA1, 0x00, 0xE0];
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    chip8.set_key(5, true);
+    for _ in 0..2 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.mem.pc.0, 0x206);
+
+    chip8.set_key(5, false);
+    chip8.step().unwrap();
+    assert_eq!(chip8.mem.pc.0, 0x20A);
+}
+
+#[test]
+fn test_xochip_audio() {
+    // I = 0x20A, load the pattern there, V0 = 112, pitch V0
+    let mut rom = vec![0xA2, 0x0A, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A, 0x12, 0x08];
+    rom.extend([0xAA; 16]);
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    assert_eq!(chip8.audio_pattern(), None);
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    let pattern = chip8.audio_pattern().unwrap();
+    assert_eq!((pattern.bits, pattern.pitch), ([0xAA; 16], 112));
+
+    chip8.reset();
+    assert_eq!(chip8.audio_pattern(), None);
+}
+
+#[test]
+fn test_stack_limits() {
+    // return with nothing on the stack
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&[0x00, 0xEE]).unwrap();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::StackUnderflow { addr: 0x200 })
+    );
+    assert_eq!(chip8.mem.pc.0, 0x200);
+
+    // call itself forever
+    let mut chip8 = Chip8::new(Quirks {
+        stack_depth: 12,
+        ..Quirks::default()
+    });
+    chip8.load_rom(&[0x22, 0x00]).unwrap();
+    for _ in 0..12 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::StackOverflow {
+            addr: 0x200,
+            depth: 12
+        })
+    );
+}
+
+#[test]
+fn test_emulator_errors() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    assert_eq!(
+        chip8.load_rom(&[0; 4000]),
+        Err(EmulatorError::RomTooLarge {
+            size: 4000,
+            max: 3584
+        })
+    );
+    assert_eq!(chip8.load_rom(&[]), Err(EmulatorError::EmptyRom));
+    let missing = chip8.load_rom_by_file("/nonexistent/pong.ch8").unwrap_err();
+    assert!(missing
+        .to_string()
+        .starts_with("could not read /nonexistent/pong.ch8: "));
+
+    // an unknown instruction, then one that runs off the end of the rom
+    chip8.load_rom(&[0x80, 0x0F, 0x60, 0x01]).unwrap();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::InvalidOpcode {
+            addr: 0x200,
+            opcode: 0x800F
+        })
+    );
+    chip8.mem.set_pc(0x202);
+    chip8.step().unwrap();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::ProgramEnded { addr: 0x204 })
+    );
+    // and a key skip past the end, V0's key isn't down
+    chip8.load_rom(&[0xE0, 0xA1]).unwrap();
+    chip8.reset();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::ProgramEnded { addr: 0x200 })
+    );
+
+    // 00FD stops the program for good, which isn't a crash
+    chip8.load_rom(&[0x00, 0xFD]).unwrap();
+    chip8.reset();
+    for _ in 0..2 {
+        assert_eq!(chip8.step(), Err(EmulatorError::Exited { addr: 0x200 }));
+    }
+    assert!(chip8.step().unwrap_err().is_exit());
+
+    // I = FFE, store V0-V3 there
+    chip8.load_rom(&[0xAF, 0xFE, 0xF3, 0x55]).unwrap();
+    chip8.reset();
+    chip8.step().unwrap();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::OutOfBoundsAccess {
+            addr: 0x202,
+            target: 0x1000
+        })
+    );
+    assert_eq!(chip8.mem.pc.0, 0x202);
+}
+
+#[test]
+fn test_out_of_bounds_quirk() {
+    // I = FFF, V0 = 2, I += V0, V0 = [I]
+    let rom = [0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E, 0xF0, 0x65];
+    let run = |out_of_bounds| {
+        let mut chip8 = Chip8::new(Quirks {
+            out_of_bounds,
+            ..Quirks::default()
+        });
+        chip8.load_rom(&rom).unwrap();
+        chip8.mem.write(0x001, 0x11);
+        chip8.mem.write(0xFFF, 0x22);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        chip8.step().map(|_| chip8.regs.get(0))
+    };
+    assert_eq!(run(OutOfBounds::Wrap), Ok(0x11));
+    assert_eq!(run(OutOfBounds::Clamp), Ok(0x22));
+    assert_eq!(
+        run(OutOfBounds::Error),
+        Err(EmulatorError::OutOfBoundsAccess {
+            addr: 0x206,
+            target: 0x1001
+        })
+    );
+}
+
+#[test]
+fn test_random_is_seeded() {
+    // V0-V3 = random & 0F
+    let rom = [0xC0, 0x0F, 0xC1, 0x0F, 0xC2, 0x0F, 0xC3, 0x0F];
+    let run = |seed| {
+        let mut chip8 = Chip8::with_seed(Quirks::default(), seed);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.step().unwrap();
+        }
+        (0..4).map(|reg| chip8.regs.get(reg)).collect::<Vec<_>>()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+    assert!(run(7).iter().all(|value| *value <= 0x0F));
+}
+
+#[test]
+fn test_two_page_mode() {
+    let mut rom = vec![0x12, 0x60];
+    rom.resize(0xC0, 0);
+    // draw the 0 at the bottom left, clear, then draw it again
+    rom.extend([
+        0x60, 0x00, 0x61, 0x3B, 0xF0, 0x29, 0xD0, 0x15, 0x02, 0x30, 0xD0, 0x15,
+    ]);
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    assert_eq!(
+        (chip8.framebuffer().width(), chip8.framebuffer().height()),
+        (64, 64)
+    );
+    for _ in 0..5 {
+        chip8.step().unwrap();
     }
+    assert!(chip8.framebuffer().get(0, 63));
+    chip8.step().unwrap();
+    assert_eq!(chip8.framebuffer().lit(), 0);
+    chip8.step().unwrap();
+    assert!(chip8.framebuffer().get(0, 63));
+
+    // still in the mode after a reset, and 0230 is only valid in it
+    chip8.reset();
+    assert!(chip8.framebuffer().is_two_page());
+    chip8.load_rom(&[0x02, 0x30]).unwrap();
+    assert!(!chip8.framebuffer().is_two_page());
+    assert!(chip8.step().is_err());
+}
+
+#[test]
+fn test_load_addr() {
+    use crate::quirks::Platform;
+
+    let mut chip8 = Chip8::new(Quirks::from_platform(Platform::Eti660));
+    // V0 = 0x2A, jump back to the start
+    chip8.load_rom(&[0x60, 0x2A, 0x16, 0x00]).unwrap();
+    assert_eq!(chip8.mem.pc.0, 0x600);
+    assert_eq!(chip8.mem.read(0x200), Some(0));
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert_eq!((chip8.regs.get(0), chip8.mem.pc.0), (0x2A, 0x600));
+
+    chip8.reset();
+    assert_eq!(chip8.mem.read(0x600), Some(0x60));
+    assert!(chip8.load_rom(&[0; 0xA02]).is_err());
+}
+
+#[test]
+fn test_rpl_flags() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 1, V1 = 2, save V0-V1, clear them, load V0 back
+    let rom = [
+        0x60, 0x01, 0x61, 0x02, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF0, 0x85,
+    ];
+    chip8.load_rom(&rom).unwrap();
+    for _ in 0..6 {
+        chip8.step().unwrap();
+    }
+    assert_eq!((chip8.regs.get(0), chip8.regs.get(1)), (1, 0));
+    assert_eq!(chip8.flags()[..3], [1, 2, 0]);
+
+    // they outlast a reset
+    chip8.reset();
+    assert_eq!(chip8.flags()[..2], [1, 2]);
+}
+
+#[test]
+fn test_step_outcome() {
+    use std::sync::{Arc, Mutex};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 5, sound timer = V0, clear the screen, V1 = V1
+    chip8
+        .load_rom(&[0x60, 0x05, 0xF0, 0x18, 0x00, 0xE0, 0x81, 0x10])
+        .unwrap();
+    let seen = Arc::new(Mutex::new(vec![]));
+    let log = seen.clone();
+    chip8.add_observer(move |outcome| log.lock().unwrap().push(outcome.pc_before));
+
+    let outcome = chip8.step().unwrap();
+    assert_eq!(
+        (outcome.opcode, outcome.pc_before, outcome.pc_after),
+        (0x6005, 0x200, 0x202)
+    );
+    assert_eq!(outcome.operation, OpCodes::SetRegister(0, 5));
+    assert!(outcome.wrote(0) && outcome.written == 1);
+    assert!(!outcome.drew && !outcome.sound_started);
+    let outcome = chip8.step().unwrap();
+    assert!(outcome.sound_started && outcome.written == 0);
+    assert!(chip8.step().unwrap().drew);
+    // written only counts registers that changed
+    assert_eq!(chip8.step().unwrap().written, 0);
+    assert_eq!(*seen.lock().unwrap(), [0x200, 0x202, 0x204, 0x206]);
+
+    // nothing is seen of a failing step
+    assert!(chip8.step().is_err());
+    assert_eq!(seen.lock().unwrap().len(), 4);
+    chip8.clear_observers();
+    chip8.reset();
+    chip8.step().unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_machine_routines() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // call routines at 123 and 456, V1 = 1
+    chip8
+        .load_rom(&[0x01, 0x23, 0x04, 0x56, 0x61, 0x01])
+        .unwrap();
+    assert_eq!(
+        chip8.step(),
+        Err(EmulatorError::MachineRoutine {
+            addr: 0x200,
+            routine: 0x123
+        })
+    );
+
+    // the handler takes 123, skipping leaves 456 alone
+    chip8.machine_routines = MachineRoutines::Skip;
+    chip8.set_routine_handler(Some(Box::new(|routine, regs, _| {
+        regs.set_register(0, 7);
+        routine == 0x123
+    })));
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    assert_eq!((chip8.regs.get(0), chip8.regs.get(1)), (7, 1));
+    chip8.reset();
+    chip8.step().unwrap();
+    chip8.machine_routines = MachineRoutines::Halt;
+    assert!(chip8.step().is_err());
+}
+
+#[test]
+fn test_exit_on_halt() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 1, jump over the next jump, jump to itself
+    chip8
+        .load_rom(&[0x60, 0x01, 0x12, 0x06, 0x12, 0x04, 0x12, 0x06])
+        .unwrap();
+    chip8.exit_on_halt = true;
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    let err = chip8.step().unwrap_err();
+    assert_eq!(err, EmulatorError::Halted { addr: 0x206 });
+    assert!(err.is_exit());
+    assert_eq!(chip8.mem.pc.0, 0x206);
+
+    // spins forever without it
+    chip8.exit_on_halt = false;
+    for _ in 0..10 {
+        chip8.step().unwrap();
+    }
+}
+
+#[test]
+fn test_self_modifying_code() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // VA = 5, then write V0 = 7 over it and run it
+    chip8
+        .load_rom(&[
+            0x6A, 0x05, 0x60, 0x60, 0x61, 0x07, 0xA2, 0x00, 0xF1, 0x55, 0x12, 0x00,
+        ])
+        .unwrap();
+    for _ in 0..7 {
+        chip8.step().unwrap();
+    }
+    assert_eq!((chip8.regs.get(0xA), chip8.regs.get(0)), (5, 7));
+
+    // the second byte alone, from outside, and on a restored snapshot
+    let snapshot = chip8.snapshot();
+    chip8.mem.write(0x201, 0x09);
+    assert_eq!(
+        chip8.mem.op_at(0x200),
+        Some((0x6009, OpCodes::SetRegister(0, 9)))
+    );
+    chip8.restore(snapshot);
+    assert_eq!(
+        chip8.mem.op_at(0x200),
+        Some((0x6007, OpCodes::SetRegister(0, 7)))
+    );
 }
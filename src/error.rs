@@ -0,0 +1,85 @@
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use crate::memory::TypeAddr;
+
+// Everything a rom can do to stop the emulator. The addresses are of the
+// instruction that failed, the program counter is left pointing at it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmulatorError {
+    InvalidOpcode { addr: TypeAddr, opcode: u16 },
+    // 0NNN with nothing to run it
+    MachineRoutine { addr: TypeAddr, routine: TypeAddr },
+    StackOverflow { addr: TypeAddr, depth: u8 },
+    StackUnderflow { addr: TypeAddr },
+    // `target` is the memory address the instruction tried to use
+    OutOfBoundsAccess { addr: TypeAddr, target: usize },
+    // ran off the end of the rom
+    ProgramEnded { addr: TypeAddr },
+    // stopped itself with 00FD
+    Exited { addr: TypeAddr },
+    // jumped to itself with Chip8::exit_on_halt set
+    Halted { addr: TypeAddr },
+    RomTooLarge { size: usize, max: usize },
+    EmptyRom,
+    // a file that couldn't be read, or Octo source that didn't assemble
+    RomUnreadable { path: String, reason: String },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidOpcode { addr, opcode } => {
+                write!(f, "unknown instruction {opcode:04X} at {addr:03X}")
+            }
+            Self::MachineRoutine { addr, routine } => {
+                write!(
+                    f,
+                    "machine code routine {routine:03X} called at {addr:03X}, only the COSMAC VIP could run it"
+                )
+            }
+            Self::StackOverflow { addr, depth } => {
+                write!(
+                    f,
+                    "stack overflow at {addr:03X}, more than {depth} nested calls"
+                )
+            }
+            Self::StackUnderflow { addr } => {
+                write!(f, "stack underflow at {addr:03X}, return without a call")
+            }
+            Self::OutOfBoundsAccess { addr, target } => {
+                write!(
+                    f,
+                    "memory access out of bounds at {addr:03X}, address {target:04X}"
+                )
+            }
+            Self::ProgramEnded { addr } => write!(f, "program ran past its end at {addr:03X}"),
+            Self::Exited { addr } => write!(f, "program exited at {addr:03X}"),
+            Self::Halted { addr } => write!(f, "program halted at {addr:03X}, jumping to itself"),
+            Self::RomTooLarge { size, max } => {
+                write!(f, "rom is {size} bytes, only {max} fit in memory")
+            }
+            Self::EmptyRom => write!(f, "rom is empty"),
+            Self::RomUnreadable { path, reason } => write!(f, "could not read {path}: {reason}"),
+        }
+    }
+}
+
+impl EmulatorError {
+    // the program is done rather than broken, frontends shut down cleanly
+    pub fn is_exit(&self) -> bool {
+        matches!(
+            self,
+            Self::ProgramEnded { .. } | Self::Exited { .. } | Self::Halted { .. }
+        )
+    }
+}
+
+impl core::error::Error for EmulatorError {}
+
+// the frontends report errors as strings
+impl From<EmulatorError> for String {
+    fn from(err: EmulatorError) -> Self {
+        err.to_string()
+    }
+}
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+// foreground and background as 0xRRGGBB
+pub const DEFAULT_PALETTE: (u32, u32) = (0xAACCFF, 0x222233);
+
+// what the pause menu goes through, by name
+pub const PALETTES: [(&str, (u32, u32)); 5] = [
+    ("default", DEFAULT_PALETTE),
+    ("green", (0x33FF66, 0x0A1A0F)),
+    ("amber", (0xFFB000, 0x1A1000)),
+    ("paper", (0x222222, 0xEEEEDD)),
+    ("lcd", (0x0F380F, 0x9BBC0F)),
+];
+
+// A key on the host keyboard, each backend translates these to its own key codes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostKey {
+    // A to Z as 0 to 25
+    Letter(u8),
+    Digit(u8),
+    Space,
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+    // where Dvorak has its left hand keys
+    Comma,
+    Period,
+    Apostrophe,
+    Semicolon,
+}
+
+impl FromStr for HostKey {
+    type Err = String;
+
+    // A-Z, 0-9, Space, Enter, Up, Down, Left, Right, Comma, Period, Apostrophe,
+    // Semicolon or the punctuation itself; minifb style Key1 works too
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix("Key").unwrap_or(s);
+        match name.as_bytes() {
+            [c @ b'0'..=b'9'] => return Ok(HostKey::Digit(c - b'0')),
+            [c] if c.is_ascii_alphabetic() => {
+                return Ok(HostKey::Letter(c.to_ascii_uppercase() - b'A'))
+            }
+            _ => {}
+        }
+        match name.to_ascii_lowercase().as_str() {
+            "space" => Ok(HostKey::Space),
+            "enter" | "return" => Ok(HostKey::Enter),
+            "up" => Ok(HostKey::Up),
+            "down" => Ok(HostKey::Down),
+            "left" => Ok(HostKey::Left),
+            "right" => Ok(HostKey::Right),
+            "comma" | "," => Ok(HostKey::Comma),
+            "period" | "." => Ok(HostKey::Period),
+            "apostrophe" | "'" => Ok(HostKey::Apostrophe),
+            "semicolon" | ";" => Ok(HostKey::Semicolon),
+            _ => Err(format!("unknown key '{s}'")),
+        }
+    }
+}
+
+// Keyboard layouts with a preset for the keypad, so it's always the block
+// of keys below 1 to 4 whatever's printed on them
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Dvorak,
+    Colemak,
+}
+
+impl Layout {
+    // the keys of the block row by row, in keypad order 123C 456D 789E A0BF
+    fn grid(self) -> [&'static str; 4] {
+        match self {
+            Layout::Qwerty => ["1234", "QWER", "ASDF", "ZXCV"],
+            Layout::Azerty => ["1234", "AZER", "QSDF", "WXCV"],
+            Layout::Dvorak => ["1234", "',.P", "AOEU", ";QJK"],
+            Layout::Colemak => ["1234", "QWFP", "ARST", "ZXCV"],
+        }
+    }
+}
+
+// Host key for every keypad key, indexed by keypad value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    keys: [HostKey; 16],
+}
+
+impl Keymap {
+    // the hex keypad on the left of the keyboard, e.g. for QWERTY
+    //
+    // 1 2 3 4      1 2 3 C
+    // Q W E R  ->  4 5 6 D
+    // A S D F      7 8 9 E
+    // Z X C V      A 0 B F
+    pub fn new(layout: Layout) -> Self {
+        const KEYPAD: [[u8; 4]; 4] = [
+            [0x1, 0x2, 0x3, 0xC],
+            [0x4, 0x5, 0x6, 0xD],
+            [0x7, 0x8, 0x9, 0xE],
+            [0xA, 0x0, 0xB, 0xF],
+        ];
+        let mut keys = [HostKey::Space; 16];
+        for (row, keypad) in layout.grid().iter().zip(KEYPAD) {
+            for (c, n) in row.chars().zip(keypad) {
+                keys[n as usize] = c.to_string().parse().unwrap();
+            }
+        }
+        Self { keys }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = (HostKey, u8)> + '_ {
+        self.keys.iter().enumerate().map(|(n, key)| (*key, n as u8))
+    }
+
+    // keypad=host pairs on top, e.g. 5=Up,8=Down
+    pub fn with_overrides(mut self, s: &str) -> Result<Self, String> {
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let Some((keypad, host)) = pair.split_once('=') else {
+                return Err(format!("'{pair}' should look like 5=Up"));
+            };
+            let keypad = match u8::from_str_radix(keypad, 16) {
+                Ok(n) if n < 16 => n,
+                _ => return Err(format!("'{keypad}' is not a keypad key, use 0-F")),
+            };
+            self.keys[keypad as usize] = host.parse()?;
+        }
+        Ok(self)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(Layout::Qwerty)
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    // overrides of the QWERTY layout
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::default().with_overrides(s)
+    }
+}
+
+// the quirks Shift + 1-4 flip, in that order: shifts, loads and stores,
+// clipping and the display wait
+pub const QUIRK_KEYS: [&str; 4] = ["shift", "memory", "wrap", "display-wait"];
+
+// until the main loop sets its own
+pub const TITLE: &str = "emuchip - Esc for the menu";
+
+#[test]
+fn test_keymap_overrides() {
+    let keymap: Keymap = "5=Up,a=Key1,F=space".parse().unwrap();
+    let host = |n: u8| keymap.keys().find(|(_, key)| *key == n).unwrap().0;
+    assert_eq!(host(0x5), HostKey::Up);
+    assert_eq!(host(0xA), HostKey::Digit(1));
+    assert_eq!(host(0xF), HostKey::Space);
+    assert_eq!(host(0x4), HostKey::Letter(b'Q' - b'A'));
+    assert!("G=Up".parse::<Keymap>().is_err());
+    assert!("5=Nope".parse::<Keymap>().is_err());
+    assert_eq!(host(0x0), HostKey::Letter(b'X' - b'A'));
+    assert_eq!(host(0xC), HostKey::Digit(4));
+
+    let layout = |layout, n: u8| {
+        Keymap::new(layout)
+            .keys()
+            .find(|(_, key)| *key == n)
+            .unwrap()
+            .0
+    };
+    assert_eq!(layout(Layout::Azerty, 0x4), HostKey::Letter(b'A' - b'A'));
+    assert_eq!(layout(Layout::Dvorak, 0x5), HostKey::Comma);
+    assert_eq!(layout(Layout::Dvorak, 0xA), HostKey::Semicolon);
+    assert_eq!(layout(Layout::Colemak, 0xE), HostKey::Letter(b'T' - b'A'));
+    assert_eq!(
+        "5=Up".parse::<Keymap>(),
+        Keymap::new(Layout::Qwerty).with_overrides("5=Up")
+    );
+}
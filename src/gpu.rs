@@ -0,0 +1,489 @@
+use std::collections::HashSet;
+
+use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
+use winit::{
+    dpi::LogicalSize,
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, TouchPhase,
+        VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Fullscreen, WindowBuilder},
+};
+
+use emuchip::{
+    audio::AudioPattern,
+    display::{FrameBuffer, HIRES_HEIGHT, HIRES_WIDTH},
+    host::{AudioSink, DisplaySink, Hotkey, InputSource, Stats},
+    keyboard::Keyboard,
+};
+
+use crate::{
+    crt::Crt,
+    frontend::{HostKey, Keymap, DEFAULT_PALETTE, QUIRK_KEYS, TITLE},
+    overlay::{self, Keypad, Message},
+    sound::Sound,
+};
+
+const LETTERS: [VirtualKeyCode; 26] = [
+    VirtualKeyCode::A,
+    VirtualKeyCode::B,
+    VirtualKeyCode::C,
+    VirtualKeyCode::D,
+    VirtualKeyCode::E,
+    VirtualKeyCode::F,
+    VirtualKeyCode::G,
+    VirtualKeyCode::H,
+    VirtualKeyCode::I,
+    VirtualKeyCode::J,
+    VirtualKeyCode::K,
+    VirtualKeyCode::L,
+    VirtualKeyCode::M,
+    VirtualKeyCode::N,
+    VirtualKeyCode::O,
+    VirtualKeyCode::P,
+    VirtualKeyCode::Q,
+    VirtualKeyCode::R,
+    VirtualKeyCode::S,
+    VirtualKeyCode::T,
+    VirtualKeyCode::U,
+    VirtualKeyCode::V,
+    VirtualKeyCode::W,
+    VirtualKeyCode::X,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+];
+
+const DIGITS: [VirtualKeyCode; 10] = [
+    VirtualKeyCode::Key0,
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
+
+fn host_key(key: HostKey) -> VirtualKeyCode {
+    match key {
+        HostKey::Letter(i) => LETTERS[i as usize],
+        HostKey::Digit(i) => DIGITS[i as usize],
+        HostKey::Space => VirtualKeyCode::Space,
+        HostKey::Enter => VirtualKeyCode::Return,
+        HostKey::Up => VirtualKeyCode::Up,
+        HostKey::Down => VirtualKeyCode::Down,
+        HostKey::Left => VirtualKeyCode::Left,
+        HostKey::Right => VirtualKeyCode::Right,
+        HostKey::Comma => VirtualKeyCode::Comma,
+        HostKey::Period => VirtualKeyCode::Period,
+        HostKey::Apostrophe => VirtualKeyCode::Apostrophe,
+        HostKey::Semicolon => VirtualKeyCode::Semicolon,
+    }
+}
+
+// The framebuffer is uploaded as a texture and scaled on the GPU, in whole
+// multiples with the leftover space letterboxed, and presented on vsync.
+// With the CRT effects on it's drawn at the window's size instead.
+pub struct GpuWindow {
+    // declared before the window so its surface is dropped first
+    pixels: Pixels,
+    window: winit::window::Window,
+    event_loop: EventLoop<()>,
+    palette: (u32, u32),
+    crt: Crt,
+    crt_on: bool,
+    // the debug overlay, drawn while on
+    stats: Option<Stats>,
+    overlay_on: bool,
+    // the clickable keypad, and what it shows held
+    keypad_on: bool,
+    keypad_held: [bool; 16],
+    // shown until it expires
+    message: Option<Message>,
+    // the pause menu's lines, drawn while it's open
+    menu: Option<Vec<String>>,
+    // where the mouse or a finger is on the window, and whether it's pressing
+    cursor: Option<(f32, f32)>,
+    pressing: bool,
+    // the window size changed or the effects were toggled
+    resized: bool,
+    // scratch space the screen is drawn to before converting to RGBA
+    buffer: Vec<u32>,
+    keys: Vec<(VirtualKeyCode, u8)>,
+    held: HashSet<VirtualKeyCode>,
+    // only reported when they change
+    modifiers: ModifiersState,
+    hotkeys: Vec<Hotkey>,
+    open: bool,
+    sound: Sound,
+}
+
+impl GpuWindow {
+    // scale is the initial on screen size of a low resolution pixel
+    pub fn new(
+        scale: usize,
+        palette: Option<(u32, u32)>,
+        keymap: &Keymap,
+        crt: Option<Crt>,
+        sound: Sound,
+        fullscreen: bool,
+        keypad: bool,
+    ) -> Result<Self, String> {
+        let event_loop = EventLoop::new();
+        let window = WindowBuilder::new()
+            .with_title(TITLE)
+            .with_inner_size(LogicalSize::new(
+                (HIRES_WIDTH * scale / 2) as f64,
+                (HIRES_HEIGHT * scale / 2) as f64,
+            ))
+            .with_min_inner_size(LogicalSize::new(HIRES_WIDTH as f64, HIRES_HEIGHT as f64))
+            .with_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)))
+            .build(&event_loop)
+            .map_err(|err| format!("could not open window: {err}"))?;
+
+        let palette = palette.unwrap_or(DEFAULT_PALETTE);
+        let size = window.inner_size();
+        let pixels = PixelsBuilder::new(
+            HIRES_WIDTH as u32,
+            HIRES_HEIGHT as u32,
+            SurfaceTexture::new(size.width, size.height, &window),
+        )
+        .enable_vsync(true)
+        .clear_color(Self::clear_color(palette.1))
+        .build()
+        .map_err(|err| format!("could not set up the GPU: {err}"))?;
+
+        Ok(Self {
+            pixels,
+            window,
+            event_loop,
+            palette,
+            crt: crt.unwrap_or_default(),
+            crt_on: crt.is_some(),
+            stats: None,
+            overlay_on: false,
+            keypad_on: keypad,
+            keypad_held: [false; 16],
+            message: None,
+            menu: None,
+            cursor: None,
+            pressing: false,
+            resized: true,
+            buffer: vec![],
+            keys: keymap.keys().map(|(key, n)| (host_key(key), n)).collect(),
+            held: HashSet::new(),
+            modifiers: ModifiersState::empty(),
+            hotkeys: vec![],
+            open: true,
+            sound,
+        })
+    }
+
+    // the effects are drawn at the window's own size, plain pixels are left to the GPU
+    fn buffer_size(&self) -> (usize, usize) {
+        if self.crt_on {
+            let size = self.window.inner_size();
+            (size.width.max(1) as usize, size.height.max(1) as usize)
+        } else {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        }
+    }
+
+    // the keypad key being clicked or touched
+    fn clicked_key(&self) -> Option<u8> {
+        if !self.keypad_on || !self.pressing {
+            return None;
+        }
+        let (x, y) = self.pixels.window_pos_to_pixel(self.cursor?).ok()?;
+        let (width, height) = self.buffer_size();
+        Keypad::new(width, height).key_at(x, y)
+    }
+
+    // of the letterbox around the screen
+    fn clear_color(rgb: u32) -> wgpu::Color {
+        let [_, r, g, b] = rgb.to_be_bytes();
+        wgpu::Color {
+            r: r as f64 / 255.0,
+            g: g as f64 / 255.0,
+            b: b as f64 / 255.0,
+            a: 1.0,
+        }
+    }
+
+    fn rgba(rgb: u32) -> [u8; 4] {
+        let [_, r, g, b] = rgb.to_be_bytes();
+        [r, g, b, 0xFF]
+    }
+
+    // handles everything winit has queued up, then hands control back
+    fn poll_events(&mut self) {
+        let Self {
+            event_loop,
+            window,
+            pixels,
+            held,
+            modifiers,
+            hotkeys,
+            open,
+            crt_on,
+            overlay_on,
+            keypad_on,
+            cursor,
+            pressing,
+            resized,
+            ..
+        } = self;
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::Poll;
+            let Event::WindowEvent { event, .. } = event else {
+                if let Event::MainEventsCleared = event {
+                    *control_flow = ControlFlow::Exit;
+                }
+                return;
+            };
+            match event {
+                WindowEvent::CloseRequested => *open = false,
+                WindowEvent::Resized(size) => {
+                    if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                        eprintln!("{err}");
+                        *open = false;
+                    }
+                    *resized = true;
+                }
+                WindowEvent::ModifiersChanged(state) => *modifiers = state,
+                WindowEvent::CursorMoved { position, .. } => {
+                    *cursor = Some((position.x as f32, position.y as f32))
+                }
+                WindowEvent::CursorLeft { .. } => *cursor = None,
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => *pressing = state == ElementState::Pressed,
+                WindowEvent::Touch(touch) => {
+                    *cursor = Some((touch.location.x as f32, touch.location.y as f32));
+                    *pressing = matches!(touch.phase, TouchPhase::Started | TouchPhase::Moved);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    if state == ElementState::Released {
+                        held.remove(&key);
+                        return;
+                    }
+                    match key {
+                        VirtualKeyCode::Equals
+                        | VirtualKeyCode::Plus
+                        | VirtualKeyCode::NumpadAdd => hotkeys.push(Hotkey::SpeedUp),
+                        VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                            hotkeys.push(Hotkey::SpeedDown)
+                        }
+                        VirtualKeyCode::N => hotkeys.push(Hotkey::FrameAdvance),
+                        _ => {}
+                    }
+                    // key repeat, not a new press
+                    if !held.insert(key) {
+                        return;
+                    }
+                    match key {
+                        VirtualKeyCode::Escape => hotkeys.push(Hotkey::Menu),
+                        VirtualKeyCode::Up => hotkeys.push(Hotkey::MenuUp),
+                        VirtualKeyCode::Down => hotkeys.push(Hotkey::MenuDown),
+                        VirtualKeyCode::Return => hotkeys.push(Hotkey::MenuPick),
+                        VirtualKeyCode::P => hotkeys.push(Hotkey::TogglePause),
+                        VirtualKeyCode::M => hotkeys.push(Hotkey::ToggleMute),
+                        VirtualKeyCode::R if modifiers.ctrl() => hotkeys.push(Hotkey::Reset),
+                        VirtualKeyCode::O if modifiers.ctrl() => hotkeys.push(Hotkey::OpenRom),
+                        VirtualKeyCode::F12 => hotkeys.push(Hotkey::Screenshot),
+                        VirtualKeyCode::F10 => hotkeys.push(Hotkey::ToggleGif),
+                        VirtualKeyCode::F8 => hotkeys.push(Hotkey::SaveProfile),
+                        VirtualKeyCode::F6 => hotkeys.push(Hotkey::ToggleSlowMotion),
+                        // on the current monitor, the resize that follows
+                        // letterboxes the screen
+                        VirtualKeyCode::F11 => match window.fullscreen() {
+                            Some(_) => window.set_fullscreen(None),
+                            None => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+                        },
+                        VirtualKeyCode::F9 => {
+                            *crt_on = !*crt_on;
+                            *resized = true;
+                        }
+                        VirtualKeyCode::F7 => {
+                            *overlay_on = !*overlay_on;
+                            *resized = true;
+                        }
+                        VirtualKeyCode::F5 => {
+                            *keypad_on = !*keypad_on;
+                            *resized = true;
+                        }
+                        _ => {}
+                    }
+                    let slots = [
+                        VirtualKeyCode::F1,
+                        VirtualKeyCode::F2,
+                        VirtualKeyCode::F3,
+                        VirtualKeyCode::F4,
+                    ];
+                    if let Some(i) = slots.iter().position(|slot| *slot == key) {
+                        let slot = i as u8 + 1;
+                        if modifiers.shift() {
+                            hotkeys.push(Hotkey::LoadState(slot));
+                        } else {
+                            hotkeys.push(Hotkey::SaveState(slot));
+                        }
+                    }
+                    if let Some(i) = DIGITS[1..].iter().position(|number| *number == key) {
+                        let slot = i as u8 + 1;
+                        if modifiers.ctrl() {
+                            hotkeys.push(Hotkey::SaveState(slot));
+                        } else if modifiers.alt() {
+                            hotkeys.push(Hotkey::LoadState(slot));
+                        } else if let Some(quirk) = QUIRK_KEYS.get(i).filter(|_| modifiers.shift())
+                        {
+                            hotkeys.push(Hotkey::ToggleQuirk(quirk));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+    }
+}
+
+impl InputSource for GpuWindow {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update_keys(&mut self, keyboard: &mut Keyboard) {
+        let clicked = self.clicked_key();
+        for &(key, n) in &self.keys {
+            let held = self.held.contains(&key) || clicked == Some(n);
+            keyboard.set_key(n, held);
+            self.keypad_held[n as usize] = held;
+        }
+    }
+
+    // Ctrl + 1-9 saves, Alt + 1-9 loads, F1-F4 save and Shift + F1-F4 load the
+    // first four slots, Shift + 1-4 flip QUIRK_KEYS, P pauses, M mutes, N
+    // advances a frame, Ctrl + R resets, Ctrl + O opens another rom, +/-
+    // changes speed, F12 takes a screenshot, F10 records a gif, F9 toggles the
+    // CRT effects, F8 writes the profile, F7 the debug overlay, F6 toggles slow
+    // motion, F5 the clickable keypad, F11 fullscreen, Esc opens the menu
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
+
+    fn rewind_held(&self) -> bool {
+        self.held.contains(&VirtualKeyCode::Back)
+    }
+}
+
+impl DisplaySink for GpuWindow {
+    fn set_title(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        self.poll_events();
+        self.sound.sync();
+        let (width, height) = self.buffer_size();
+        let resized = std::mem::take(&mut self.resized);
+        if resized {
+            if let Err(err) = self.pixels.resize_buffer(width as u32, height as u32) {
+                eprintln!("{err}");
+                self.open = false;
+                return;
+            }
+            self.buffer = vec![0; width * height];
+        }
+        let dirty = fb.take_dirty_rows();
+        // the overlay changes every frame
+        // the screen under the message comes back once it's gone
+        let expired = self.message.as_ref().is_some_and(Message::expired);
+        if expired {
+            self.message = None;
+        }
+        let everything = resized
+            || expired
+            || self.overlay_on
+            || self.keypad_on
+            || self.message.is_some()
+            || self.menu.is_some();
+        if dirty.is_some() || everything {
+            let crt = if self.crt_on { self.crt } else { Crt::OFF };
+            let rows = match dirty {
+                Some(rows) if !everything && crt == Crt::OFF => {
+                    Crt::render_rows(fb, self.palette, &mut self.buffer, width, height, rows)
+                }
+                _ => {
+                    crt.render(fb, self.palette, &mut self.buffer, width, height);
+                    0..height
+                }
+            };
+            if let Some(menu) = &self.menu {
+                overlay::draw_menu(menu, &mut self.buffer, width, height, self.palette.0);
+            } else if let Some(stats) = self.stats.as_ref().filter(|_| self.overlay_on) {
+                overlay::draw_stats(stats, &mut self.buffer, width, height, self.palette.0);
+            }
+            if let Some(message) = &self.message {
+                message.draw(&mut self.buffer, width, height, self.palette.0);
+            }
+            if self.keypad_on {
+                Keypad::new(width, height).draw(
+                    &mut self.buffer,
+                    width,
+                    self.keypad_held,
+                    self.palette.0,
+                );
+            }
+            let frame = self.pixels.frame_mut();
+            let pixels = rows.start * width..rows.end * width;
+            for (pixel, rgb) in frame[pixels.start * 4..pixels.end * 4]
+                .chunks_exact_mut(4)
+                .zip(&self.buffer[pixels])
+            {
+                pixel.copy_from_slice(&Self::rgba(*rgb));
+            }
+        }
+        // presenting every frame keeps the window alive and paced to vsync
+        if let Err(err) = self.pixels.render() {
+            eprintln!("{err}");
+            self.open = false;
+        }
+    }
+
+    fn show_stats(&mut self, stats: Stats) {
+        self.stats = Some(stats);
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = Some(Message::new(message));
+    }
+
+    fn show_menu(&mut self, menu: Option<Vec<String>>) {
+        self.menu = menu;
+    }
+
+    fn set_palette(&mut self, palette: (u32, u32)) {
+        self.pixels.clear_color(Self::clear_color(palette.1));
+        self.palette = palette;
+        self.resized = true;
+    }
+}
+
+impl AudioSink for GpuWindow {
+    fn beep(&mut self, pattern: Option<AudioPattern>) {
+        self.sound.beep(pattern);
+    }
+}
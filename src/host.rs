@@ -0,0 +1,327 @@
+use crate::{
+    audio::AudioPattern, decode::OpCodes, display::FrameBuffer, keyboard::Keyboard, memory::Memory,
+    quirks::Quirks, Chip8,
+};
+
+// calls the overlay has room for, the innermost ones
+const CALLS_SHOWN: usize = 4;
+
+// keypad values as laid out on the COSMAC VIP
+pub const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// Emulator controls, pressed on whatever the frontend has for them
+pub enum Hotkey {
+    SaveState(u8),
+    LoadState(u8),
+    TogglePause,
+    // one frame while paused
+    FrameAdvance,
+    // back to power on with the same rom
+    Reset,
+    // pick another rom and start it from power on
+    OpenRom,
+    SpeedUp,
+    SpeedDown,
+    Screenshot,
+    // start or stop recording a gif
+    ToggleGif,
+    ToggleMute,
+    // between full speed and --slow
+    ToggleSlowMotion,
+    // write the --profile report now
+    SaveProfile,
+    // flip a quirk by name, e.g. "shift"
+    ToggleQuirk(&'static str),
+    // open or close the pause menu
+    Menu,
+    // sent whenever Up, Down or Enter are pressed, only the menu takes them
+    MenuUp,
+    MenuDown,
+    MenuPick,
+}
+
+// What the debug overlay shows
+pub struct Stats {
+    pub fps: u32,
+    pub ips: u32,
+    pub regs: [u8; 16],
+    pub index: u16,
+    pub pc: u16,
+    pub delay: u8,
+    pub sound: u8,
+    // the keypad as the emulator has it, whoever pressed what
+    pub keys: [bool; 16],
+    pub quirks: Quirks,
+    // subroutine calls being run, and where the innermost few were made from
+    // with the opcode there
+    pub depth: usize,
+    pub calls: Vec<(u16, Option<u16>)>,
+}
+
+impl Stats {
+    // `fps` and `ips` as measured by whoever runs the frames
+    pub fn new(chip8: &Chip8, fps: u32, ips: u32) -> Self {
+        Self {
+            fps,
+            ips,
+            regs: std::array::from_fn(|reg| chip8.regs.get(reg as u8)),
+            index: chip8.mem.index.0,
+            pc: chip8.mem.pc.0,
+            delay: chip8.timers.delay,
+            sound: chip8.timers.sound,
+            keys: std::array::from_fn(|key| chip8.keyboard.get_key_status_from_num(key as u8)),
+            quirks: chip8.quirks,
+            depth: chip8.mem.stack.addresses().len(),
+            calls: chip8
+                .mem
+                .stack
+                .addresses()
+                .iter()
+                .rev()
+                .take(CALLS_SHOWN)
+                .map(|ret| {
+                    let site = ret.wrapping_sub(2);
+                    (site, chip8.mem.instruction_at(site))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let regs = |from: usize| {
+            let values: Vec<String> = self.regs[from..from + 8]
+                .iter()
+                .map(|value| format!("{value:02X}"))
+                .collect();
+            format!("V{from:X} {}", values.join(" "))
+        };
+        let mut lines = vec![
+            format!("FPS {} IPS {}", self.fps, self.ips),
+            format!("PC {:03X} I {:03X}", self.pc, self.index),
+            format!("DT {:02X} ST {:02X}", self.delay, self.sound),
+            regs(0),
+            regs(8),
+        ];
+        // held keys in their place on the keypad, - for the rest
+        for (row, keys) in KEYPAD.iter().enumerate() {
+            let label = if row == 0 { "PAD" } else { "   " };
+            let keys: String = keys
+                .iter()
+                .map(|key| {
+                    if self.keys[*key as usize] {
+                        char::from_digit(*key as u32, 16)
+                            .unwrap()
+                            .to_ascii_uppercase()
+                    } else {
+                        '-'
+                    }
+                })
+                .collect();
+            lines.push(format!("{label} {keys}"));
+        }
+        // the ones that are on
+        let quirks: Vec<&str> = Quirks::NAMES
+            .into_iter()
+            .filter(|name| self.quirks.get(name) == Ok(true))
+            .collect();
+        if quirks.is_empty() {
+            lines.push("QK none".to_string());
+        } else {
+            lines.push(format!("QK {}", quirks.join(" ")));
+        }
+        lines.push(format!(
+            "CALLS {} OF {}",
+            self.depth, self.quirks.stack_depth
+        ));
+        for (site, code) in &self.calls {
+            let op = match code {
+                Some(code) => OpCodes::decode_raw(*code).to_string(),
+                None => "out of memory".to_string(),
+            };
+            lines.push(format!("    {site:03X} {op}"));
+        }
+        lines
+    }
+}
+
+// Where keypad input and emulator controls come from
+pub trait InputSource {
+    fn is_open(&self) -> bool;
+
+    // snapshot of the keypad for the upcoming frame
+    fn update_keys(&mut self, keyboard: &mut Keyboard);
+
+    // emulator controls pressed since the last frame
+    fn hotkeys(&mut self) -> Vec<Hotkey>;
+
+    fn rewind_held(&self) -> bool;
+}
+
+// Where the screen goes
+pub trait DisplaySink {
+    // status such as the speed or being paused, changes every now and then
+    fn set_title(&mut self, title: &str);
+
+    // called once per frame, also the place to pump input events
+    fn draw(&mut self, fb: &mut FrameBuffer);
+
+    // called every frame before draw, for frontends that can show memory
+    fn show_memory(&mut self, _mem: &Memory) {}
+
+    // called every frame before draw, for frontends with a debug overlay
+    fn show_stats(&mut self, _stats: Stats) {}
+
+    // a confirmation such as a savestate being saved, for frontends that can
+    // put text over the screen
+    fn show_message(&mut self, _message: &str) {}
+
+    // called every frame before draw, with the lines of the pause menu while
+    // it's open, for frontends that can put text over the screen
+    fn show_menu(&mut self, _menu: Option<Vec<String>>) {}
+
+    // picked from the pause menu
+    fn set_palette(&mut self, _palette: (u32, u32)) {}
+}
+
+// Where the buzzer goes
+pub trait AudioSink {
+    // called every frame the sound timer is running, with the XO-CHIP
+    // pattern to play in place of the buzzer if the rom set one
+    fn beep(&mut self, pattern: Option<AudioPattern>);
+}
+
+// What the main loop needs from a window/terminal/... to run the emulator,
+// anything that is all three at once
+pub trait Frontend: InputSource + DisplaySink + AudioSink {}
+
+impl<T: InputSource + DisplaySink + AudioSink> Frontend for T {}
+
+// A frontend with no window, for tests and scripts: holds down the keypad
+// keys it's given and keeps what it was shown
+#[derive(Default)]
+pub struct Headless {
+    pub held: Vec<u8>,
+    pub title: String,
+    // the screen as last drawn
+    pub lit: Vec<(usize, usize)>,
+    pub draws: u32,
+    pub beeps: u32,
+}
+
+impl Headless {
+    pub fn holding(held: &[u8]) -> Self {
+        Self {
+            held: held.to_vec(),
+            ..Self::default()
+        }
+    }
+}
+
+impl InputSource for Headless {
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    fn update_keys(&mut self, keyboard: &mut Keyboard) {
+        for key in &self.held {
+            keyboard.set_key(*key, true);
+        }
+    }
+
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        vec![]
+    }
+
+    fn rewind_held(&self) -> bool {
+        false
+    }
+}
+
+impl DisplaySink for Headless {
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        self.lit = (0..fb.height())
+            .flat_map(|y| (0..fb.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| fb.get(x, y))
+            .collect();
+        self.draws += 1;
+    }
+}
+
+impl AudioSink for Headless {
+    fn beep(&mut self, _pattern: Option<AudioPattern>) {
+        self.beeps += 1;
+    }
+}
+
+#[test]
+fn test_stats_lines() {
+    let stats = Stats {
+        fps: 60,
+        ips: 700,
+        regs: std::array::from_fn(|reg| reg as u8 * 0x11),
+        index: 0x2A0,
+        pc: 0x200,
+        delay: 0x3C,
+        sound: 0,
+        keys: std::array::from_fn(|key| key == 0x5 || key == 0xF),
+        quirks: Quirks {
+            wrap_sprites: true,
+            display_wait: true,
+            ..Quirks::default()
+        },
+        depth: 1,
+        calls: vec![(0x204, Some(0x2300))],
+    };
+    assert_eq!(
+        stats.lines(),
+        [
+            "FPS 60 IPS 700",
+            "PC 200 I 2A0",
+            "DT 3C ST 00",
+            "V0 00 11 22 33 44 55 66 77",
+            "V8 88 99 AA BB CC DD EE FF",
+            "PAD ----",
+            "    -5--",
+            "    ----",
+            "    ---F",
+            "QK wrap display-wait",
+            "CALLS 1 OF 16",
+            "    204 CALL 0x300",
+        ]
+    );
+}
+
+#[test]
+fn test_headless_frontend() {
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 5, get stuck unless key 5 is held, draw the 1 of the font at
+    // (5, 5) and start the sound timer
+    let rom = [
+        0x60, 0x05, 0xE0, 0x9E, 0x12, 0x04, 0x60, 0x01, 0xF0, 0x29, 0x61, 0x05, 0xD1, 0x15, 0xF1,
+        0x18, 0x12, 0x10,
+    ];
+    chip8.load_rom(&rom).unwrap();
+    let mut headless = Headless::holding(&[5]);
+    let frontend: &mut dyn Frontend = &mut headless;
+    frontend.update_keys(&mut chip8.keyboard);
+    chip8.run_frame(9).unwrap();
+    if chip8.is_beeping() {
+        frontend.beep(chip8.audio_pattern());
+    }
+    frontend.draw(chip8.framebuffer_mut());
+
+    assert_eq!(chip8.mem.pc.0, 0x210);
+    assert_eq!(headless.beeps, 1);
+    // the 1 is 8 pixels, its top one is two in
+    assert_eq!(headless.lit.len(), 8);
+    assert_eq!(headless.lit[0], (7, 5));
+}
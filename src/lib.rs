@@ -0,0 +1,79 @@
+// 16 8-bit data registers named V0 to VF
+// I -> address register (12 bits)
+//
+// Delay timer & Sound timer: Count down at 60 times / s until 0
+// Beep when sound timer is non-zero
+//
+// Display res: 64 width, 32 height
+//
+// 35 opcodes, each are 2 bytes (big-endian)
+//      NNN: address
+//      NN: 8-bit constant
+//      N: 4-bit constant
+//      X and Y: 4-bit register identifier
+
+// TODO: fix unsigned integer sizes inconsistency
+//
+// Separately:
+// CPU: 700 times per second
+// Display: 60 times per second
+// Timer: 60 times per second
+//
+// The core in this crate has no dependency on any window or audio library,
+// frontends drive it through Chip8::step, Chip8::sync, Chip8::set_key
+// and render Chip8::framebuffer however they like. Chip8::step tells what
+// each instruction did, and Chip8::add_observer hears about every one of
+// them, for tools that watch the machine run. host has the input, display
+// and audio traits the emuchip binary drives its frontends through. Without
+// the std feature it only needs alloc, e.g. to drive a small LCD from a
+// microcontroller.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod analyze;
+pub mod audio;
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod coverage;
+pub mod database;
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod display;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
+pub mod emulator;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod host;
+pub mod keyboard;
+pub mod memory;
+#[cfg(feature = "std")]
+pub mod netplay;
+#[cfg(feature = "std")]
+pub mod octo;
+pub mod profile;
+pub mod quirks;
+pub mod registers;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(feature = "std")]
+pub mod screenshot;
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod symbols;
+pub mod timer;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "web")]
+pub mod web;
+
+pub use emulator::{Chip8, StepOutcome};
@@ -1,43 +1,1901 @@
-// 16 8-bit data registers named V0 to VF
-// I -> address register (12 bits)
-//
-// Delay timer & Sound timer: Count down at 60 times / s until 0
-// Beep when sound timer is non-zero
-//
-// Display res: 64 width, 32 height
-//
-// 35 opcodes, each are 2 bytes (big-endian)
-//      NNN: address
-//      NN: 8-bit constant
-//      N: 4-bit constant
-//      X and Y: 4-bit register identifier
-
-// TODO: fix unsigned integer sizes inconsistency
-//
-// Separately:
-// CPU: 700 times per second
-// Display: 60 times per second
-// Timer: 60 times per second
-
-mod decode;
-mod display;
-mod emulator;
-mod keyboard;
-mod memory;
-mod registers;
+// minifb + cpal frontend for the emuchip core
+
+mod builtin;
+mod cli;
+mod config;
+mod crt;
+mod frontend;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod overlay;
+mod remote;
+#[cfg(feature = "sdl2")]
+mod sdl;
 mod sound;
+#[cfg(feature = "tui")]
+mod tui;
+mod window;
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, Read},
+    mem,
+    path::Path,
+    process,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use emuchip::{
+    analyze::{self, Extension},
+    audio::AudioPattern,
+    bus::Port,
+    capture::{ApngCapture, GifCapture},
+    coverage::{Coverage, CoverageFormat},
+    database::{self, Database, RomInfo},
+    debugger::Debugger,
+    decode::OpCodes,
+    disasm,
+    display::{FrameBuffer, WIDTH},
+    error::EmulatorError,
+    host::{Frontend, Hotkey, Stats},
+    keyboard::Keyboard,
+    memory::{Memory, TypeAddr},
+    netplay::Session,
+    octo,
+    profile::Profiler,
+    quirks::{MachineRoutines, Platform, Quirks},
+    replay::{Player, Recorder, Replay},
+    rewind::Rewind,
+    screenshot,
+    snapshot::Snapshot,
+    symbols::Symbols,
+    trace::{read_trace, Differ, TraceFilter, Tracer},
+    Chip8,
+};
 
-use std::{thread, time::Duration};
+use cli::{
+    Backend, Cli, Command, DumpFormat, QuirkArgs, RunArgs, TestArgs, TraceArgs, DEFAULT_SPEED,
+};
+use config::Config;
+use frontend::{Keymap, DEFAULT_PALETTE, PALETTES};
+use overlay::{Menu, MenuItem, Meter};
+use remote::Remote;
+use sound::Sound;
+use window::Window;
 
-use emulator::Emulator;
+// how often the emulation runs a frame, 60 a second
+const FRAME: Duration = Duration::from_micros(16_667);
+// percent of full speed slow motion runs at unless --slow says otherwise
+const DEFAULT_SLOW: u32 = 10;
+// frames run back to back to catch up after falling behind, at most
+const MAX_CATCH_UP: u32 = 4;
+// the end of a wait that's spun instead of slept, longer than the usual
+// oversleep
+const SPIN: Duration = Duration::from_millis(2);
 
 fn main() {
-    let mut emu = Emulator::init();
-    while emu.is_running() {
-        for _ in 0..10 {
-            emu.tick();
+    let cli = Cli::parse_with_config().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+    let result = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Disasm {
+            rom,
+            symbols,
+            quirks,
+        } => disasm(&quirks, &rom, symbols.as_deref()),
+        Command::Assemble {
+            source,
+            output,
+            symbols,
+        } => assemble(&source, &output, symbols.as_deref()),
+        Command::Info { rom, quirks } => info(&quirks, &rom),
+        Command::Analyze { rom, quirks } => analyze(&quirks, &rom),
+        Command::Test(args) => test(args),
+        Command::Statediff { from, to } => statediff(&from, &to),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+fn tracer(args: &TraceArgs, symbols: Symbols) -> Result<Option<Tracer>, String> {
+    let out: Box<dyn io::Write + Send> = match (&args.trace_file, args.trace) {
+        (Some(path), _) => Box::new(io::BufWriter::new(
+            fs::File::create(path).map_err(|err| format!("could not create {path}: {err}"))?,
+        )),
+        (None, true) => Box::new(io::stdout()),
+        (None, false) => return Ok(None),
+    };
+    let filter = TraceFilter {
+        range: args.trace_range,
+        ops: args.trace_ops.clone(),
+    };
+    let mut tracer = Tracer::new(out, filter, args.trace_format);
+    tracer.set_symbols(symbols);
+    Ok(Some(tracer))
+}
+
+// skipped 0NNN calls are mentioned once for each routine
+fn machine_routines(chip8: &mut Chip8, args: &QuirkArgs) {
+    chip8.machine_routines = args.machine_routines;
+    if args.machine_routines != MachineRoutines::Skip {
+        return;
+    }
+    let mut seen = HashSet::new();
+    chip8.add_observer(move |outcome| match outcome.operation {
+        OpCodes::MachineRoutine(routine) if seen.insert(routine) => eprintln!(
+            "skipped machine code routine {routine:03X} called at {:03X}",
+            outcome.pc_before
+        ),
+        _ => {}
+    });
+}
+
+// the error is what matters, a dump that can't be written is only mentioned
+fn dump_on_crash(chip8: &Chip8, path: &str) {
+    match chip8.snapshot().dump(path) {
+        Ok(()) => eprintln!("dumped the state to {path}"),
+        Err(err) => eprintln!("{err}"),
+    }
+}
+
+fn save_profile(chip8: &Chip8, path: &str) -> Result<(), String> {
+    let report = chip8.profiler().map(Profiler::report).unwrap_or_default();
+    fs::write(path, report).map_err(|err| format!("could not write {path}: {err}"))
+}
+
+fn save_coverage(chip8: &Chip8, path: &str, format: CoverageFormat) -> Result<(), String> {
+    let map = chip8
+        .coverage()
+        .map(|coverage| coverage.export(chip8.rom_range(), format))
+        .unwrap_or_default();
+    fs::write(path, map).map_err(|err| format!("could not write {path}: {err}"))
+}
+
+// Octo source is assembled on the way in and brings its labels along, an
+// Octo cartridge also the settings it was saved with
+fn read_rom(path: &str) -> Result<(Vec<u8>, Option<RomInfo>, Symbols), String> {
+    let assemble =
+        |source: &str| octo::assemble_with_symbols(source).map_err(|err| format!("{path}: {err}"));
+    if let Some((title, source)) = builtin::find(path) {
+        let info = RomInfo {
+            title: title.to_string(),
+            quirks: None,
+            speed: None,
+            palette: None,
+        };
+        let (rom, symbols) = assemble(source)?;
+        return Ok((rom, Some(info), symbols));
+    }
+    let bytes = read_bytes(path)?;
+    if path.ends_with(".o8") {
+        let source = String::from_utf8(bytes).map_err(|_| format!("{path} isn't UTF-8 text"))?;
+        let (rom, symbols) = assemble(&source)?;
+        return Ok((rom, None, symbols));
+    }
+    if !path.ends_with(".gif") {
+        return Ok((bytes, None, Symbols::new()));
+    }
+    let cartridge = octo::read_cartridge(&bytes).map_err(|err| format!("{path}: {err}"))?;
+    let info = RomInfo {
+        title: Path::new(path)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        quirks: Some(cartridge.quirks),
+        speed: cartridge.speed,
+        palette: cartridge.palette,
+    };
+    let (rom, symbols) = assemble(&cartridge.source)?;
+    Ok((rom, Some(info), symbols))
+}
+
+// a file, - for stdin or an http(s) URL
+fn read_bytes(path: &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        let mut bytes = vec![];
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|err| format!("could not read stdin: {err}"))?;
+        return Ok(bytes);
+    }
+    if !is_url(path) {
+        return fs::read(path).map_err(|err| format!("could not read {path}: {err}"));
+    }
+    // curl brings TLS and redirects along, so the binary doesn't need them
+    let output = process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", path])
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => {
+                format!("curl not found, it has to be installed to download {path}")
+            }
+            _ => format!("could not run curl to download {path}: {err}"),
+        })?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("could not download {path}: {}", reason.trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// what a cartridge or else the ROM database, when one was given, has on the
+// loaded rom, with what the config file has around it
+fn known_rom(
+    args: &QuirkArgs,
+    sha1: &str,
+    rom: &str,
+    cartridge: Option<RomInfo>,
+) -> Result<Option<RomInfo>, String> {
+    let known = match (cartridge, &args.database) {
+        (Some(cartridge), _) => Some(cartridge),
+        (None, Some(path)) => {
+            let json =
+                fs::read_to_string(path).map_err(|err| format!("could not read {path}: {err}"))?;
+            Database::parse(&json)?.lookup(sha1).cloned()
+        }
+        (None, None) => None,
+    };
+    match config(args)? {
+        Some(config) => Ok(Some(config.rom_info(known, rom, sha1)?)),
+        None => Ok(known),
+    }
+}
+
+// the file --config names, Cli::parse_with_config fills it in from the
+// default location when there's a file there
+fn config(args: &QuirkArgs) -> Result<Option<Config>, String> {
+    args.config.as_deref().map(Config::load).transpose()
+}
+
+// starts a rom from power on, with the quirks the database has for it
+fn open_rom(
+    chip8: &mut Chip8,
+    args: &QuirkArgs,
+    path: &str,
+) -> Result<(Option<RomInfo>, Symbols), String> {
+    let (rom, cartridge, symbols) = read_rom(path)?;
+    let load = |chip8: &mut Chip8| chip8.load_rom(&rom).map_err(|err| format!("{path}: {err}"));
+    load(chip8)?;
+    // instructions are two bytes, but data at the end often isn't
+    if rom.len() % 2 == 1 {
+        eprintln!(
+            "warning: {path} is an odd {} bytes long, its last instruction may be cut short",
+            rom.len()
+        );
+    }
+    let known = known_rom(args, chip8.rom_sha1(), path, cartridge)?;
+    chip8.quirks = args.quirks(known.as_ref())?;
+    // again, at the address the quirks load it at
+    load(chip8)?;
+    chip8.reset();
+    Ok((known, symbols))
+}
+
+// where the quirks, from the flags, a cartridge or the database, load the rom,
+// for looking at it without running it
+fn load_addr(
+    args: &QuirkArgs,
+    path: &str,
+    rom: &[u8],
+    cartridge: Option<RomInfo>,
+) -> Result<TypeAddr, String> {
+    let known = known_rom(args, &database::sha1(rom), path, cartridge)?;
+    Ok(args.quirks(known.as_ref())?.load_addr)
+}
+
+// from --symbols, which wins over the labels the rom was assembled with
+fn symbols(args: &TraceArgs, assembled: Symbols) -> Result<Symbols, String> {
+    match &args.symbols {
+        Some(path) => Symbols::load(path),
+        None => Ok(assembled),
+    }
+}
+
+// native file picker, for when no rom was given or another one is wanted
+fn pick_rom() -> Option<String> {
+    rfd::FileDialog::new()
+        .set_title("Open ROM")
+        .add_filter("CHIP-8 ROM", &["ch8", "c8", "sc8", "xo8", "o8", "gif"])
+        .add_filter("All files", &["*"])
+        .pick_file()
+        .map(|path| path.display().to_string())
+}
+
+// None when the file can't be looked at, e.g. halfway through being replaced
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// start of the window title, the file name when the database doesn't know it
+fn title_name(known: Option<&RomInfo>, rom: &str) -> String {
+    match known {
+        Some(known) => format!("emuchip - {}", known.title),
+        None => {
+            let file = Path::new(rom).file_name().unwrap_or_default();
+            format!("emuchip - {}", file.to_string_lossy())
+        }
+    }
+}
+
+fn run(mut args: RunArgs) -> Result<(), String> {
+    if args.roms.is_empty() {
+        args.roms.push(builtin::MENU.to_string());
+    }
+    if args.roms.len() > 1 {
+        if !matches!(args.backend, Backend::Window) {
+            return Err("only the window backend runs several ROMs at once".to_string());
+        }
+        let tracing = args.trace.trace || args.trace.trace_file.is_some();
+        let netplay = args.host.is_some() || args.join.is_some();
+        if args.debug
+            || netplay
+            || args.remote.is_some()
+            || args.record.is_some()
+            || args.replay.is_some()
+            || args.profile.is_some()
+            || args.coverage.is_some()
+            || tracing
+        {
+            return Err(
+                "--debug, netplay, --remote, --record, --replay, --profile, --coverage and tracing take one ROM at a time"
+                    .to_string(),
+            );
+        }
+    }
+    let args = Arc::new(args);
+    // only ever with a single rom
+    let mut console = args.debug.then(spawn_console);
+    let mut instances = args
+        .roms
+        .iter()
+        .enumerate()
+        .map(|(index, rom)| Instance::open(&args, rom, index, console.take()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut next = Instant::now();
+    while !instances.is_empty() {
+        for instance in &mut instances {
+            instance.frame();
+        }
+        // the others keep running when one of the windows is closed
+        let (open, closed): (Vec<_>, Vec<_>) = instances
+            .into_iter()
+            .partition(|instance| instance.frontend.is_open() && !instance.finished);
+        instances = open;
+        for instance in closed {
+            instance.close()?;
+        }
+        // a slow frame pushes the next ones back rather than rushing them
+        next = (next + FRAME).max(Instant::now());
+        sleep_until(next);
+    }
+    Ok(())
+}
+
+// `keymap` is the rom's own, --keymap goes on top of it
+fn open_frontend(
+    args: &RunArgs,
+    palette: Option<(u32, u32)>,
+    keymap: Option<&str>,
+    index: usize,
+) -> Result<Box<dyn Frontend>, String> {
+    // sdl2 has the keys by position already
+    let layout = match args.backend {
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2 => frontend::Layout::Qwerty,
+        _ => args.layout,
+    };
+    let keymap = Keymap::new(layout)
+        .with_overrides(keymap.unwrap_or_default())?
+        .with_overrides(args.keymap.as_deref().unwrap_or_default())?;
+    Ok(match args.backend {
+        Backend::Window => {
+            let mut window = Window::new(
+                args.scale,
+                palette,
+                &keymap,
+                args.crt,
+                Sound::new(args.tone(), args.audio_device.as_deref()),
+                args.fullscreen,
+                args.keypad,
+            );
+            // left to right when there are several
+            if index > 0 {
+                let step = (WIDTH * args.scale + 20) * index;
+                window.set_position(500 + step as isize, 300);
+            }
+            Box::new(window)
+        }
+        #[cfg(feature = "tui")]
+        Backend::Tui if args.debug => {
+            return Err("the debug console can't share the terminal, use the window".to_string())
+        }
+        #[cfg(feature = "tui")]
+        Backend::Tui if args.crt.is_some() => {
+            return Err("CRT effects need the window or gpu backend".to_string())
+        }
+        #[cfg(feature = "tui")]
+        Backend::Tui if args.keypad => {
+            return Err("the keypad needs the window or gpu backend".to_string())
+        }
+        #[cfg(feature = "tui")]
+        Backend::Tui => Box::new(tui::Terminal::new(palette, &keymap)?),
+        #[cfg(feature = "gpu")]
+        Backend::Gpu => Box::new(gpu::GpuWindow::new(
+            args.scale,
+            palette,
+            &keymap,
+            args.crt,
+            Sound::new(args.tone(), args.audio_device.as_deref()),
+            args.fullscreen,
+            args.keypad,
+        )?),
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2 if args.crt.is_some() => {
+            return Err("CRT effects need the window or gpu backend".to_string())
+        }
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2 if args.keypad => {
+            return Err("the keypad needs the window or gpu backend".to_string())
+        }
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2 => Box::new(sdl::SdlWindow::new(
+            args.scale,
+            palette,
+            &keymap,
+            args.tone(),
+            args.audio_device.as_deref(),
+            args.fullscreen,
+        )?),
+    })
+}
+
+// One rom and the window it runs in, `emuchip run a.ch8 b.ch8` runs several
+// side by side. The emulation runs on a thread of its own, so neither a busy
+// window nor an emulation waiting on a peer holds up the other
+struct Instance<F: Frontend + ?Sized> {
+    frontend: Box<F>,
+    inputs: Sender<Input>,
+    frames: Receiver<Frame>,
+    thread: JoinHandle<Result<(), String>>,
+    // the screen as last emulated
+    fb: FrameBuffer,
+    shown_title: String,
+    // the last frame's sound, see Frame
+    beep: Option<Option<AudioPattern>>,
+    fixed_speed: bool,
+    // the menu asked for another rom
+    picking: bool,
+    // the emulation is done, the window closes
+    finished: bool,
+}
+
+// What the window sends its emulation every time it's drawn
+struct Input {
+    keys: Keyboard,
+    hotkeys: Vec<Hotkey>,
+    rewind_held: bool,
+    // picked with Ctrl + O
+    open: Option<String>,
+}
+
+// What an emulated frame leaves for the window
+struct Frame {
+    // None when nothing was drawn
+    screen: Option<FrameBuffer>,
+    title: String,
+    // Some while the sound timer runs, with the XO-CHIP pattern if the rom
+    // set one
+    beep: Option<Option<AudioPattern>>,
+    stats: Stats,
+    memory: Memory,
+    // to show on screen, also printed
+    messages: Vec<String>,
+    fixed_speed: bool,
+    // the pause menu's lines while it's open
+    menu: Option<Vec<String>>,
+    // picked from the menu
+    palette: Option<(u32, u32)>,
+    pick_rom: bool,
+}
+
+impl Instance<dyn Frontend> {
+    // `index` is where the window goes among the others
+    fn open(
+        args: &Arc<RunArgs>,
+        rom: &str,
+        index: usize,
+        console: Option<Receiver<String>>,
+    ) -> Result<Self, String> {
+        let machine = Machine::open(args, rom)?;
+        let frontend = open_frontend(args, machine.palette, machine.keymap.as_deref(), index)?;
+        Ok(Self::start(args, machine, frontend, console))
+    }
+}
+
+impl<F: Frontend + ?Sized> Instance<F> {
+    // runs `machine` with any frontend, the debug console pauses the
+    // emulation until told otherwise
+    fn start(
+        args: &Arc<RunArgs>,
+        mut machine: Machine,
+        frontend: Box<F>,
+        console: Option<Receiver<String>>,
+    ) -> Self {
+        if console.is_some() {
+            machine.debugger.pause();
+            println!("{}", machine.debugger.current(&machine.chip8));
+        }
+        let fixed_speed = machine.fixed_speed();
+        let (inputs, machine_inputs) = mpsc::channel();
+        let (machine_frames, frames) = mpsc::channel();
+        let args = Arc::clone(args);
+        let thread =
+            thread::spawn(move || machine.run(&args, &machine_inputs, machine_frames, console));
+        Self {
+            frontend,
+            inputs,
+            frames,
+            thread,
+            fb: FrameBuffer::new(),
+            shown_title: String::new(),
+            beep: None,
+            fixed_speed,
+            picking: false,
+            finished: false,
+        }
+    }
+
+    // one display refresh: input goes to the emulation, whatever it emulated
+    // since gets drawn
+    fn frame(&mut self) {
+        let mut hotkeys = self.frontend.hotkeys();
+        let count = hotkeys.len();
+        hotkeys.retain(|hotkey| !matches!(hotkey, Hotkey::OpenRom));
+        // the file picker belongs with the window
+        let mut open = None;
+        if hotkeys.len() < count || mem::take(&mut self.picking) {
+            if self.fixed_speed {
+                eprintln!("can't switch roms while recording, replaying or playing online");
+            } else {
+                open = pick_rom();
+            }
+        }
+        let mut keys = Keyboard::new();
+        self.frontend.update_keys(&mut keys);
+        // fails once the emulation is done, which the frames tell
+        let _ = self.inputs.send(Input {
+            keys,
+            hotkeys,
+            rewind_held: self.frontend.rewind_held(),
+            open,
+        });
+
+        loop {
+            match self.frames.try_recv() {
+                Ok(frame) => self.show(frame),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        // the frontends only sound while told to every frame
+        if let Some(pattern) = self.beep {
+            self.frontend.beep(pattern);
+        }
+        self.frontend.draw(&mut self.fb);
+    }
+
+    fn show(&mut self, frame: Frame) {
+        if let Some(mut screen) = frame.screen {
+            // the rows of a screen that never got drawn might not be
+            // dirty in this one
+            if self.fb.take_dirty_rows().is_some() {
+                screen.mark_updated();
+            }
+            self.fb = screen;
+        }
+        if frame.title != self.shown_title {
+            self.frontend.set_title(&frame.title);
+            self.shown_title = frame.title;
+        }
+        self.beep = frame.beep;
+        self.fixed_speed = frame.fixed_speed;
+        self.picking |= frame.pick_rom;
+        if let Some(palette) = frame.palette {
+            self.frontend.set_palette(palette);
+        }
+        self.frontend.show_menu(frame.menu);
+        self.frontend.show_stats(frame.stats);
+        self.frontend.show_memory(&frame.memory);
+        for message in &frame.messages {
+            self.frontend.show_message(message);
+        }
+    }
+
+    // once the window is closed, waits for the emulation to save what it was
+    // recording
+    fn close(self) -> Result<(), String> {
+        // give the terminal back before anything is printed
+        drop(self.frontend);
+        drop(self.inputs);
+        self.thread
+            .join()
+            .map_err(|_| "the emulation thread panicked".to_string())?
+    }
+}
+
+// The emulation side of an instance
+struct Machine {
+    chip8: Chip8,
+    rom: String,
+    // start of the title
+    name: String,
+    palette: Option<(u32, u32)>,
+    // from the config file, under --keymap
+    keymap: Option<String>,
+    debugger: Debugger,
+    // instructions per second, a multiple of 60 while the speed is fixed
+    speed: u32,
+    // one snapshot per frame
+    rewind: Rewind,
+    gif: Option<GifCapture<io::BufWriter<fs::File>>>,
+    muted: bool,
+    // percent of full speed slow motion runs at, and whether it's on
+    slow: u32,
+    slowed: bool,
+    meter: Meter,
+    // with --watch, when the rom file was last changed as of loading it
+    watched: Option<Option<SystemTime>>,
+    player: Option<Player>,
+    recorder: Option<Recorder>,
+    // a peer to share the keypad with
+    netplay: Option<Session>,
+    remote: Option<Remote>,
+    // the RPL flags as on disk, None while recording, replaying or playing
+    // online, where the run has to start the same every time
+    saved_flags: Option<[u8; 16]>,
+    // emulated frames so far, the clock for recorded input
+    frame: u64,
+    // the rom is done, the window closes
+    exited: bool,
+    // open while Some, the emulation waits on it
+    menu: Option<Menu>,
+    // picked from the menu, for the window
+    new_palette: Option<(u32, u32)>,
+    pick_rom: bool,
+    // quit from the menu, the window closes but the rom can be resumed
+    quit: bool,
+    // the latest from the window
+    keys: Keyboard,
+    rewind_held: bool,
+    // not handled yet
+    hotkeys: Vec<Hotkey>,
+    open: Option<String>,
+    // for the next frame to show
+    messages: Vec<String>,
+}
+
+impl Machine {
+    fn open(args: &RunArgs, rom: &str) -> Result<Self, String> {
+        let mut chip8 = Chip8::new(Quirks::default());
+        if let Some(seed) = args.seed {
+            chip8.set_seed(seed);
+        }
+        let (known, assembled) = open_rom(&mut chip8, &args.quirks, rom)?;
+        machine_routines(&mut chip8, &args.quirks);
+        let keymap = config(&args.quirks)?.map(|config| config.keymap(rom, chip8.rom_sha1()));
+        let symbols = symbols(&args.trace, assembled)?;
+        chip8.set_tracer(tracer(&args.trace, symbols.clone())?);
+        let mut debugger = Debugger::new();
+        debugger.set_symbols(symbols);
+        chip8.set_profiler(args.profile.as_ref().map(|_| Profiler::new()));
+        chip8.set_coverage(args.coverage.as_ref().map(|_| Coverage::new()));
+        if args.resume {
+            resume(&mut chip8, rom);
+        }
+        let palette = args
+            .palette
+            .or(known.as_ref().and_then(|known| known.palette));
+        let speed = args
+            .speed
+            .or(known.as_ref().and_then(|known| known.speed))
+            .unwrap_or(DEFAULT_SPEED);
+
+        let mut cycles = (speed / 60).max(1);
+        let player = match &args.replay {
+            Some(path) => {
+                let replay = Replay::load(path)?;
+                cycles = replay.cycles;
+                Some(Player::new(replay, &mut chip8))
+            }
+            None => None,
+        };
+        let recorder = args.record.is_some().then(|| Recorder::new(&chip8, cycles));
+        let netplay = match (&args.host, &args.join) {
+            (Some(addr), _) => {
+                println!("waiting for a peer on {addr}");
+                Some(Session::host(addr, &chip8, cycles)?)
+            }
+            (_, Some(addr)) => {
+                let (session, host_cycles) = Session::join(addr, &mut chip8)?;
+                cycles = host_cycles;
+                Some(session)
+            }
+            _ => None,
+        };
+        // recordings and peers go by instructions per frame
+        let fixed = player.is_some() || recorder.is_some() || netplay.is_some();
+        let speed = if fixed { cycles * 60 } else { speed.max(1) };
+        let remote = args.remote.as_deref().map(Remote::listen).transpose()?;
+        let saved_flags = (player.is_none() && recorder.is_none() && netplay.is_none())
+            .then(|| load_flags(&mut chip8, rom));
+        if let Some(addr) = &args.remote {
+            println!("remote control on ws://{addr}");
+        }
+        Ok(Self {
+            rom: rom.to_string(),
+            name: title_name(known.as_ref(), rom),
+            palette,
+            keymap,
+            debugger,
+            speed,
+            rewind: Rewind::new(args.rewind * 60),
+            gif: None,
+            muted: false,
+            slow: args.slow.unwrap_or(DEFAULT_SLOW),
+            slowed: args.slow.is_some(),
+            meter: Meter::new(),
+            watched: args.watch.then(|| modified(rom)),
+            player,
+            recorder,
+            netplay,
+            remote,
+            saved_flags,
+            frame: 0,
+            exited: false,
+            menu: None,
+            new_palette: None,
+            pick_rom: false,
+            quit: false,
+            keys: Keyboard::new(),
+            rewind_held: false,
+            hotkeys: vec![],
+            open: None,
+            messages: vec![],
+            chip8,
+        })
+    }
+
+    // emulates 60 frames a second until the window is closed, or the rom
+    // exits and then the window is closed
+    fn run(
+        mut self,
+        args: &RunArgs,
+        inputs: &Receiver<Input>,
+        frames: Sender<Frame>,
+        console: Option<Receiver<String>>,
+    ) -> Result<(), String> {
+        let mut last = Instant::now();
+        // emulated time owed to the clock
+        let mut behind = Duration::ZERO;
+        'frames: loop {
+            loop {
+                match inputs.try_recv() {
+                    Ok(input) => {
+                        self.keys = input.keys;
+                        self.rewind_held = input.rewind_held;
+                        self.hotkeys.extend(input.hotkeys);
+                        self.open = input.open.or(self.open.take());
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'frames,
+                }
+            }
+            for line in console.iter().flat_map(|console| console.try_iter()) {
+                match self.debugger.command(&mut self.chip8, &line) {
+                    Ok(output) => println!("{output}"),
+                    Err(err) => eprintln!("{err}"),
+                }
+            }
+
+            // slow motion stretches the frames, timers and all
+            let length = self.frame_length();
+            let now = Instant::now();
+            // a long stall, e.g. waiting on the peer, isn't made up for
+            behind = (behind + (now - last)).min(length * MAX_CATCH_UP);
+            last = now;
+            while behind >= length {
+                behind -= length;
+                let frame = self.frame(args);
+                if frames.send(frame).is_err() || self.exited || self.quit {
+                    break 'frames;
+                }
+            }
+            sleep_until(last + length.saturating_sub(behind));
+        }
+        // the window goes first, it may have the terminal to give back
+        drop(frames);
+        while inputs.recv().is_ok() {}
+        self.close(args)
+    }
+
+    // the peer can't wait on the menu, the game goes on under it
+    fn in_menu(&self) -> bool {
+        self.menu.is_some() && self.netplay.is_none()
+    }
+
+    fn frame_length(&self) -> Duration {
+        if self.slowed {
+            FRAME * 100 / self.slow
+        } else {
+            FRAME
+        }
+    }
+
+    // recorded input only lines up again at the speed it was recorded at, and
+    // a peer has to run at the same speed
+    fn fixed_speed(&self) -> bool {
+        self.player.is_some() || self.recorder.is_some() || self.netplay.is_some()
+    }
+
+    // what's held here, in a keyboard of its own so chip8 sees each key change
+    // once everything held is in
+    fn local_keys(&self) -> Keyboard {
+        let mut local = Keyboard::new();
+        let remote = self.remote.as_ref().map(Remote::held).unwrap_or_default();
+        for key in 0..16 {
+            local.set_key(
+                key,
+                self.keys.get_key_status_from_num(key) || remote[key as usize],
+            );
+        }
+        local
+    }
+
+    // input, then a frame's worth of emulation
+    fn frame(&mut self, args: &RunArgs) -> Frame {
+        if let Some(remote) = &mut self.remote {
+            remote.poll(&mut self.debugger, &mut self.chip8);
+        }
+        let mut frame_advance = false;
+        for hotkey in mem::take(&mut self.hotkeys) {
+            frame_advance |= matches!(hotkey, Hotkey::FrameAdvance) && self.debugger.is_paused();
+            self.muted ^= matches!(hotkey, Hotkey::ToggleMute);
+            self.handle_hotkey(args, hotkey);
+        }
+        // a rom to start over with
+        let mut reload = self.open.take();
+
+        if let Some(loaded) = self.watched {
+            let now = modified(&self.rom);
+            if now.is_some() && now != loaded {
+                // tried once per change, a broken save waits for the next
+                self.watched = Some(now);
+                reload = reload.or(Some(self.rom.clone()));
+            }
+        }
+        if let Some(path) = reload {
+            self.reload(args, path);
+        }
+
+        // a replay has all the input
+        let local = self.player.is_none().then(|| self.local_keys());
+        let in_menu = self.in_menu();
+        let fixed = self.fixed_speed();
+        let chip8 = &mut self.chip8;
+        if let (Some(local), None) = (&local, &self.netplay) {
+            for key in 0..16 {
+                chip8.set_key(key, local.get_key_status_from_num(key));
+            }
+        }
+        // instructions this frame, for the overlay
+        let mut ran = 0;
+        let mut beep = None;
+        // the peer can't follow back in time, nor the recording or the replay
+        if self.rewind_held && !fixed {
+            if let Some(snapshot) = self.rewind.pop() {
+                chip8.restore(snapshot);
+            }
+        } else if !self.debugger.is_paused() && !in_menu {
+            if let Some(player) = &mut self.player {
+                player.apply(self.frame, chip8);
+            }
+            if let (Some(session), Some(local)) = (&mut self.netplay, &local) {
+                if let Err(err) = session.exchange(self.frame, local, chip8) {
+                    eprintln!("{err}, playing on alone");
+                    self.netplay = None;
+                }
+            }
+            if let Some(recorder) = &mut self.recorder {
+                recorder.capture(self.frame, &chip8.keyboard);
+            }
+            let cycles = instructions_in(self.speed, self.frame);
+            self.frame += 1;
+
+            for _ in 0..cycles {
+                match self.debugger.step(chip8) {
+                    Ok(true) => ran += 1,
+                    Ok(false) => {
+                        match self.debugger.take_hit() {
+                            Some(hit) => println!("watch {hit}\n{}", self.debugger.current(chip8)),
+                            None => println!("breakpoint\n{}", self.debugger.current(chip8)),
+                        }
+                        break;
+                    }
+                    // the menu is done once something's picked
+                    Err(err) if err.is_exit() && self.rom == builtin::MENU => {
+                        self.open = builtin::pick(chip8.regs.get(0));
+                        break;
+                    }
+                    Err(err) if err.is_exit() => {
+                        println!("{err}");
+                        self.exited = true;
+                        break;
+                    }
+                    // paused on the instruction that failed
+                    Err(err) => {
+                        eprintln!("{err}\n{}", self.debugger.current(chip8));
+                        if let Some(path) = &args.trace.dump_on_crash {
+                            dump_on_crash(chip8, path);
+                        }
+                        break;
+                    }
+                }
+            }
+            if chip8.is_beeping() && !self.muted {
+                beep = Some(chip8.audio_pattern());
+            }
+            chip8.sync();
+            if let Some(saved) = &mut self.saved_flags {
+                let flags = chip8.flags();
+                if flags != *saved {
+                    if let Err(err) = fs::write(flags_path(&self.rom, chip8.rom_sha1()), flags) {
+                        eprintln!("could not save flags: {err}");
+                    }
+                    *saved = flags;
+                }
+            }
+            self.rewind.push(chip8.snapshot());
+            if let Some(capture) = &mut self.gif {
+                if let Err(err) = capture.capture(chip8.framebuffer()) {
+                    eprintln!("{err}, stopped recording");
+                    self.gif = None;
+                }
+            }
+        }
+        if frame_advance {
+            self.debugger.pause();
+        }
+        self.meter.frame(ran);
+        // hotkeys, the console and breakpoints can all pause; the measured
+        // rates only change once a second, and so does the title
+        let menu = self
+            .menu
+            .as_ref()
+            .map(|menu| menu.lines(quirks_name(&chip8.quirks), palette_name(self.palette)));
+        let mut title = if let Some(lines) = &menu {
+            let selected = lines.iter().find(|line| line.starts_with('>'));
+            format!(
+                "{} - menu {}, Up/Down and Enter to pick, Esc to resume",
+                self.name,
+                selected.map_or("", |line| &line[2..])
+            )
+        } else if self.debugger.is_paused() {
+            format!("{} - paused, P to resume", self.name)
+        } else {
+            format!(
+                "{} - {} instructions/s, {} fps {} ips measured",
+                self.name, self.speed, self.meter.fps, self.meter.ips
+            )
+        };
+        if self.slowed {
+            title += &format!(", {}% speed", self.slow);
+        }
+        if self.muted {
+            title += ", muted";
+        }
+        if let Some(remote) = &mut self.remote {
+            remote.send_screen(chip8.framebuffer());
+        }
+        let fb = chip8.framebuffer_mut();
+        let screen = fb.clone();
+        // the window only redraws the rows that changed since its last copy
+        let screen = fb.take_dirty_rows().map(|_| screen);
+        Frame {
+            screen,
+            title,
+            beep,
+            stats: Stats::new(chip8, self.meter.fps, self.meter.ips),
+            memory: chip8.mem.clone(),
+            messages: mem::take(&mut self.messages),
+            fixed_speed: self.fixed_speed(),
+            menu,
+            palette: self.new_palette.take(),
+            pick_rom: mem::take(&mut self.pick_rom),
+        }
+    }
+
+    fn reload(&mut self, args: &RunArgs, path: String) {
+        self.leave(args);
+        match open_rom(&mut self.chip8, &args.quirks, &path) {
+            Ok((known, assembled)) => {
+                self.name = title_name(known.as_ref(), &path);
+                // the labels move along with the source, a symbol file stays
+                if args.trace.symbols.is_none() {
+                    if let Some(tracer) = self.chip8.tracer_mut() {
+                        tracer.set_symbols(assembled.clone());
+                    }
+                    self.debugger.set_symbols(assembled);
+                }
+                // the history, the profile and the coverage are of the old rom
+                self.rewind = Rewind::new(args.rewind * 60);
+                self.chip8
+                    .set_profiler(args.profile.as_ref().map(|_| Profiler::new()));
+                self.chip8
+                    .set_coverage(args.coverage.as_ref().map(|_| Coverage::new()));
+                if self.watched.is_some() {
+                    self.watched = Some(modified(&path));
+                }
+                if self.saved_flags.is_some() {
+                    self.saved_flags = Some(load_flags(&mut self.chip8, &path));
+                }
+                println!("loaded {path}");
+                self.rom = path;
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    // keeps the state for --resume, or drops the one there was once the rom
+    // is done
+    fn leave(&self, args: &RunArgs) {
+        if !args.resume {
+            return;
+        }
+        let path = resume_path(&self.rom, self.chip8.rom_sha1());
+        if self.exited {
+            let _ = fs::remove_file(path);
+            return;
+        }
+        if let Err(err) = self.chip8.snapshot().save(&path) {
+            eprintln!("{err}");
+        }
+    }
+
+    // once the window is closed, saves what was being recorded
+    fn close(self, args: &RunArgs) -> Result<(), String> {
+        self.leave(args);
+        if let Some(capture) = self.gif {
+            capture.finish()?;
+        }
+        if let (Some(recorder), Some(path)) = (self.recorder, &args.record) {
+            recorder.finish().save(path)?;
+            println!("saved replay to {path}");
+        }
+        if let Some(path) = &args.profile {
+            save_profile(&self.chip8, path)?;
+            println!("saved profile to {path}");
+        }
+        if let Some(path) = &args.coverage {
+            save_coverage(&self.chip8, path, args.coverage_format)?;
+            println!("saved coverage to {path}");
+        }
+        Ok(())
+    }
+
+    fn handle_hotkey(&mut self, args: &RunArgs, hotkey: Hotkey) {
+        let fixed = self.fixed_speed();
+        let (chip8, rom) = (&mut self.chip8, self.rom.as_str());
+        match hotkey {
+            // the peer would fall out of sync, or wait on us and give up
+            Hotkey::LoadState(_)
+            | Hotkey::Reset
+            | Hotkey::TogglePause
+            | Hotkey::FrameAdvance
+            | Hotkey::ToggleSlowMotion
+                if self.netplay.is_some() =>
+            {
+                eprintln!("not while playing online")
+            }
+            // a replay only has the keypad, it wouldn't run the same again
+            Hotkey::LoadState(_) | Hotkey::Reset if fixed => {
+                eprintln!("not while recording or replaying")
+            }
+            Hotkey::SaveState(slot) => {
+                let path = state_path(rom, chip8.rom_sha1(), slot);
+                match chip8.snapshot().save(&path) {
+                    Ok(()) => {
+                        println!("saved state {slot} to {path}");
+                        self.messages.push(format!("State {slot} saved"));
+                    }
+                    Err(err) => {
+                        eprintln!("{err}");
+                        self.messages.push(format!("State {slot} not saved"));
+                    }
+                }
+            }
+            Hotkey::LoadState(slot) => {
+                match Snapshot::load(&state_path(rom, chip8.rom_sha1(), slot)) {
+                    Ok(snapshot) => {
+                        chip8.restore(snapshot);
+                        println!("loaded state {slot}");
+                        self.messages.push(format!("State {slot} loaded"));
+                    }
+                    Err(err) => {
+                        eprintln!("{err}");
+                        self.messages.push(format!("No state {slot}"));
+                    }
+                }
+            }
+            Hotkey::TogglePause if self.debugger.is_paused() => self.debugger.resume(),
+            Hotkey::TogglePause => self.debugger.pause(),
+            // the frame pauses again once it's done
+            Hotkey::FrameAdvance if self.debugger.is_paused() => self.debugger.resume(),
+            Hotkey::FrameAdvance => {}
+            // the frame keeps track of it
+            Hotkey::ToggleMute | Hotkey::OpenRom => {}
+            Hotkey::ToggleSlowMotion => self.slowed = !self.slowed,
+            Hotkey::Reset => chip8.reset(),
+            Hotkey::SaveProfile => match &args.profile {
+                Some(path) => match save_profile(chip8, path) {
+                    Ok(()) => println!("saved profile to {path}"),
+                    Err(err) => eprintln!("{err}"),
+                },
+                None => eprintln!("run with --profile FILE to profile"),
+            },
+            Hotkey::ToggleGif => match self.gif.take() {
+                Some(capture) => match capture.finish() {
+                    Ok(_) => println!("stopped recording"),
+                    Err(err) => eprintln!("{err}"),
+                },
+                None => {
+                    let path = numbered_path(rom, "clip", "gif");
+                    let palette = self.palette.unwrap_or(DEFAULT_PALETTE);
+                    match GifCapture::create(&path, args.scale, palette) {
+                        Ok(capture) => {
+                            self.gif = Some(capture);
+                            println!("recording to {path}, F10 to stop");
+                        }
+                        Err(err) => eprintln!("{err}"),
+                    }
+                }
+            },
+            Hotkey::Screenshot => {
+                let path = numbered_path(rom, "shot", "png");
+                let palette = self.palette.unwrap_or(DEFAULT_PALETTE);
+                match screenshot::save_png(chip8.framebuffer(), args.scale, palette, &path) {
+                    Ok(()) => println!("saved screenshot to {path}"),
+                    Err(err) => eprintln!("{err}"),
+                }
+            }
+            Hotkey::SpeedUp | Hotkey::SpeedDown if self.fixed_speed() => {
+                eprintln!("speed is fixed while recording, replaying or playing online")
+            }
+            // in steps of about a quarter, at least one instruction per frame
+            Hotkey::SpeedUp => self.speed = (self.speed * 5 / 4).max(self.speed + 60),
+            Hotkey::SpeedDown => self.speed = (self.speed * 4 / 5).max(60),
+            // the recording or the peer would play out differently
+            Hotkey::ToggleQuirk(_) if self.fixed_speed() => {
+                eprintln!("quirks are fixed while recording, replaying or playing online")
+            }
+            Hotkey::ToggleQuirk(name) => {
+                // the frontends only send known names
+                let state = match self.chip8.quirks.toggle(name) {
+                    Ok(true) => "on",
+                    _ => "off",
+                };
+                println!("quirk {name} {state}");
+                self.messages.push(format!("Quirk {name} {state}"));
+            }
+            Hotkey::Menu if self.menu.is_some() => self.menu = None,
+            Hotkey::Menu => self.menu = Some(Menu::default()),
+            Hotkey::MenuUp => self.menu.iter_mut().for_each(Menu::up),
+            Hotkey::MenuDown => self.menu.iter_mut().for_each(Menu::down),
+            Hotkey::MenuPick => {
+                if let Some(menu) = self.menu.take() {
+                    self.pick_from_menu(args, menu);
+                }
+            }
+        }
+    }
+
+    // the menu closes on whatever's picked, except for going through the
+    // quirks and palettes
+    fn pick_from_menu(&mut self, args: &RunArgs, menu: Menu) {
+        match menu.selected() {
+            MenuItem::Resume => {}
+            MenuItem::Reset => self.handle_hotkey(args, Hotkey::Reset),
+            MenuItem::OpenRom => self.pick_rom = true,
+            MenuItem::Quirks if self.fixed_speed() => {
+                eprintln!("quirks are fixed while recording, replaying or playing online");
+                self.menu = Some(menu);
+            }
+            MenuItem::Quirks => {
+                let presets = quirk_presets();
+                let next = presets
+                    .iter()
+                    .position(|(name, _)| *name == quirks_name(&self.chip8.quirks))
+                    .map_or(0, |index| (index + 1) % presets.len());
+                let (name, quirks) = presets[next];
+                // the rom stays where it was loaded
+                self.chip8.quirks = Quirks {
+                    load_addr: self.chip8.quirks.load_addr,
+                    ..quirks
+                };
+                println!("quirks set to {name}");
+                self.menu = Some(menu);
+            }
+            MenuItem::Palette => {
+                let current = self.palette.unwrap_or(DEFAULT_PALETTE);
+                let next = PALETTES
+                    .iter()
+                    .position(|(_, palette)| *palette == current)
+                    .map_or(0, |index| (index + 1) % PALETTES.len());
+                self.palette = Some(PALETTES[next].1);
+                self.new_palette = self.palette;
+                self.menu = Some(menu);
+            }
+            MenuItem::Quit => self.quit = true,
+        }
+    }
+}
+
+// what the menu goes through, by their --platform names
+fn quirk_presets() -> [(&'static str, Quirks); 4] {
+    [
+        ("default", Quirks::default()),
+        ("cosmac", Quirks::from_platform(Platform::Cosmac)),
+        ("schip", Quirks::from_platform(Platform::Schip)),
+        ("xochip", Quirks::from_platform(Platform::XoChip)),
+    ]
+}
+
+// the preset the quirks are, wherever the rom was loaded, or custom
+fn quirks_name(quirks: &Quirks) -> &'static str {
+    quirk_presets()
+        .iter()
+        .find(|(_, preset)| {
+            Quirks {
+                load_addr: quirks.load_addr,
+                ..*preset
+            } == *quirks
+        })
+        .map_or("custom", |(name, _)| name)
+}
+
+fn palette_name(palette: Option<(u32, u32)>) -> &'static str {
+    let palette = palette.unwrap_or(DEFAULT_PALETTE);
+    PALETTES
+        .iter()
+        .find(|(_, preset)| *preset == palette)
+        .map_or("custom", |(name, _)| name)
+}
+
+// thread::sleep overshoots by a few ms on some platforms, more than enough to
+// drop a frame now and then, so it stops short and spins the rest of the way
+fn sleep_until(deadline: Instant) {
+    let wait = deadline.saturating_duration_since(Instant::now());
+    if wait > SPIN {
+        thread::sleep(wait - SPIN);
+    }
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
+
+// how many instructions frame number `frame` runs at `speed` a second, the
+// rest of speed / 60 is spread out so every second runs exactly `speed`
+fn instructions_in(speed: u32, frame: u64) -> u32 {
+    let done = |frames: u64| speed as u64 * frames / 60;
+    (done(frame + 1) - done(frame)) as u32
+}
+
+// savestates live next to the rom, or in the data directory, named by its
+// hash so they still go with it once renamed, e.g. 3cd1...f0a2.state1
+fn state_path(rom: &str, sha1: &str, slot: u8) -> String {
+    data_path(rom, &format!("{sha1}.state{slot}"))
+}
+
+// the state the rom was left in, e.g. 3cd1...f0a2.resume
+fn resume_path(rom: &str, sha1: &str) -> String {
+    data_path(rom, &format!("{sha1}.resume"))
+}
+
+// next to a rom file, stdin, builtin and downloaded roms have no folder of
+// their own so theirs go in the data directory
+fn data_path(rom: &str, file_name: &str) -> String {
+    let local = rom != "-" && builtin::find(rom).is_none() && !is_url(rom);
+    if local {
+        return Path::new(rom)
+            .with_file_name(file_name)
+            .to_string_lossy()
+            .into_owned();
+    }
+    let Some(dir) = config::data_dir() else {
+        return file_name.to_string();
+    };
+    if let Err(err) = fs::create_dir_all(&dir) {
+        eprintln!("could not create {}: {err}", dir.display());
+    }
+    dir.join(file_name).to_string_lossy().into_owned()
+}
+
+// picks up where the rom was left, if it was left running
+fn resume(chip8: &mut Chip8, rom: &str) {
+    let path = resume_path(rom, chip8.rom_sha1());
+    if !Path::new(&path).exists() {
+        return;
+    }
+    match Snapshot::load(&path) {
+        Ok(snapshot) => {
+            chip8.restore(snapshot);
+            println!("resumed from {path}");
+        }
+        Err(err) => eprintln!("{err}"),
+    }
+}
+
+// SCHIP RPL flags, e.g. high scores, are kept along with the states,
+// e.g. 3cd1...f0a2.flags
+fn flags_path(rom: &str, sha1: &str) -> String {
+    data_path(rom, &format!("{sha1}.flags"))
+}
+
+// none saved yet is the same as all zero
+fn load_flags(chip8: &mut Chip8, rom: &str) -> [u8; 16] {
+    let mut flags = [0; 16];
+    if let Ok(bytes) = fs::read(flags_path(rom, chip8.rom_sha1())) {
+        for (flag, byte) in flags.iter_mut().zip(bytes) {
+            *flag = byte;
         }
-        emu.sync();
-        thread::sleep(Duration::from_millis(16));
     }
+    chip8.set_flags(flags);
+    flags
+}
+
+// next to the rom as well, pong.ch8.shot1.png and counting
+fn numbered_path(rom: &str, kind: &str, extension: &str) -> String {
+    (1..)
+        .map(|n| format!("{rom}.{kind}{n}.{extension}"))
+        .find(|path| !Path::new(path).exists())
+        .unwrap()
+}
+
+// debugger commands are read line by line from stdin on their own thread
+fn spawn_console() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn disasm(args: &QuirkArgs, path: &str, symbols: Option<&str>) -> Result<(), String> {
+    let (rom, cartridge, assembled) = read_rom(path)?;
+    let start = load_addr(args, path, &rom, cartridge)?;
+    let symbols = match symbols {
+        Some(path) => Symbols::load(path)?,
+        None => assembled,
+    };
+    print!("{}", disasm::disassemble(&rom, start, &symbols));
+    Ok(())
+}
+
+fn assemble(source: &str, output: &str, symbols: Option<&str>) -> Result<(), String> {
+    let text =
+        String::from_utf8(read_bytes(source)?).map_err(|_| format!("{source} isn't UTF-8 text"))?;
+    let (rom, labels) =
+        octo::assemble_with_symbols(&text).map_err(|err| format!("{source}: {err}"))?;
+    fs::write(output, &rom).map_err(|err| format!("could not write {output}: {err}"))?;
+    if let Some(path) = symbols {
+        fs::write(path, labels.to_string())
+            .map_err(|err| format!("could not write {path}: {err}"))?;
+    }
+    Ok(())
+}
+
+fn statediff(from: &str, to: &str) -> Result<(), String> {
+    let diff = Snapshot::load(from)?.diff(&Snapshot::load(to)?);
+    if diff.is_empty() {
+        println!("no differences");
+    } else {
+        println!("{diff}");
+    }
+    Ok(())
+}
+
+fn analyze(args: &QuirkArgs, path: &str) -> Result<(), String> {
+    let (rom, cartridge, _) = read_rom(path)?;
+    let start = load_addr(args, path, &rom, cartridge)?;
+    print!("{}", analyze::analyze(&rom, start));
+    Ok(())
+}
+
+fn info(args: &QuirkArgs, path: &str) -> Result<(), String> {
+    let (rom, cartridge, _) = read_rom(path)?;
+    let start = load_addr(args, path, &rom, cartridge)?;
+    let ops: Vec<OpCodes> = disasm::decode_rom(&rom, start)
+        .into_iter()
+        .map(|line| line.op)
+        .collect();
+    let unknown = ops
+        .iter()
+        .filter(|op| matches!(op, OpCodes::Unimplemented))
+        .count();
+    let uses = |wanted| ops.iter().any(|op| analyze::extension(op) == Some(wanted));
+    let schip = uses(Extension::Schip);
+    let xochip = uses(Extension::XoChip);
+    let routines = ops
+        .iter()
+        .any(|op| matches!(op, OpCodes::MachineRoutine(_)));
+
+    println!("rom:          {path}");
+    println!("size:         {} bytes", rom.len());
+    println!("sha1:         {}", database::sha1(&rom));
+    println!(
+        "loads at:     {start:03X} - {:03X}",
+        start as usize + rom.len().saturating_sub(1)
+    );
+    println!("instructions: {} ({unknown} unknown or data)", ops.len());
+    println!("uses SCHIP:   {}", if schip { "yes" } else { "no" });
+    println!("uses XO-CHIP: {}", if xochip { "yes" } else { "no" });
+    println!("machine code: {}", if routines { "yes" } else { "no" });
+    // the 1260 header Chip8::load_rom looks for
+    let two_page = rom.starts_with(&[0x12, 0x60]);
+    println!("64x64 hi-res: {}", if two_page { "yes" } else { "no" });
+    Ok(())
+}
+
+// frames are numbered from 1, padded so they sort
+fn dump_frame(fb: &FrameBuffer, dir: &str, format: DumpFormat, frame: u32) -> Result<(), String> {
+    let path = Path::new(dir).join(match format {
+        DumpFormat::Png => format!("{frame:05}.png"),
+        DumpFormat::Ppm => format!("{frame:05}.ppm"),
+    });
+    let path = path.to_string_lossy();
+    match format {
+        DumpFormat::Png => screenshot::save_png(fb, 8, DEFAULT_PALETTE, &path),
+        DumpFormat::Ppm => screenshot::save_ppm(fb, 8, DEFAULT_PALETTE, &path),
+    }
+}
+
+// Chip8::run_frame checking every instruction against the trace first, the
+// outer error is the first difference
+fn run_against(
+    chip8: &mut Chip8,
+    differ: &mut Differ,
+    cycles: u32,
+) -> Result<Result<(), EmulatorError>, String> {
+    for _ in 0..cycles {
+        differ.check(chip8)?;
+        if let Err(err) = chip8.step() {
+            return Ok(Err(err));
+        }
+    }
+    chip8.sync();
+    Ok(Ok(()))
+}
+
+fn test(args: TestArgs) -> Result<(), String> {
+    // the same every time, so --expect holds for roms using random numbers
+    let mut chip8 = Chip8::with_seed(Quirks::default(), args.seed);
+    let (known, assembled) = open_rom(&mut chip8, &args.quirks, &args.rom)?;
+    machine_routines(&mut chip8, &args.quirks);
+    chip8.exit_on_halt = args.exit_on_infinite_loop;
+    chip8.set_tracer(tracer(&args.trace, symbols(&args.trace, assembled)?)?);
+    chip8.set_profiler(args.profile.as_ref().map(|_| Profiler::new()));
+    chip8.set_coverage(args.coverage.as_ref().map(|_| Coverage::new()));
+    if let Some(addr) = args.console {
+        let addr = addr as usize;
+        chip8.map(addr..addr + 1, Port::new(|byte| print!("{}", byte as char)));
+    }
+    let status = Arc::new(Mutex::new(None));
+    if let Some(addr) = args.status {
+        let (addr, result) = (addr as usize, status.clone());
+        chip8.map(
+            addr..addr + 1,
+            Port::new(move |byte| *result.lock().unwrap() = Some(byte)),
+        );
+    }
+    let speed = args
+        .speed
+        .or(known.and_then(|known| known.speed))
+        .unwrap_or(DEFAULT_SPEED);
+    let mut capture = match &args.gif {
+        Some(path) => Some(GifCapture::create(path, 8, DEFAULT_PALETTE)?),
+        None => None,
+    };
+    let mut apng = match &args.apng {
+        Some(path) => Some(ApngCapture::create(path, 8, DEFAULT_PALETTE)?),
+        None => None,
+    };
+    if let Some(dir) = &args.dump_frames {
+        fs::create_dir_all(dir).map_err(|err| format!("could not create {dir}: {err}"))?;
+    }
+    let mut against = match &args.against {
+        Some(path) => {
+            let bytes = fs::read(path).map_err(|err| format!("could not read {path}: {err}"))?;
+            Some(Differ::new(
+                read_trace(&bytes).map_err(|err| format!("{path}: {err}"))?,
+            ))
+        }
+        None => None,
+    };
+    if speed == 0 && args.max_cycles.is_some() {
+        return Err("--max-cycles never runs out at --speed 0".to_string());
+    }
+    // with --max-cycles the last frame is cut short
+    let mut cycles = 0;
+    for frame in 0.. {
+        let budget = match args.max_cycles {
+            Some(max) if cycles >= max => break,
+            Some(max) => (max - cycles).min(u64::from(instructions_in(speed, frame.into()))) as u32,
+            None if frame == args.frames => break,
+            None => instructions_in(speed, frame.into()),
+        };
+        cycles += u64::from(budget);
+        let result = match &mut against {
+            Some(differ) => run_against(&mut chip8, differ, budget)?,
+            None => chip8.run_frame(budget),
+        };
+        match result {
+            // the screen as the rom left it
+            Err(err) if err.is_exit() => {
+                println!("{err}");
+                break;
+            }
+            Err(err) => {
+                if let Some(path) = &args.trace.dump_on_crash {
+                    dump_on_crash(&chip8, path);
+                }
+                return Err(err.to_string());
+            }
+            Ok(()) => {}
+        }
+        if let Some(capture) = &mut capture {
+            capture.capture(chip8.framebuffer())?;
+        }
+        if let Some(apng) = &mut apng {
+            apng.capture(chip8.framebuffer());
+        }
+        if let Some(dir) = &args.dump_frames {
+            dump_frame(chip8.framebuffer(), dir, args.dump_format, frame + 1)?;
+        }
+        if status.lock().unwrap().is_some() {
+            break;
+        }
+    }
+    if let Some(capture) = capture {
+        capture.finish()?;
+    }
+    if let Some(apng) = apng {
+        apng.finish()?;
+    }
+    if let Some(screenshot) = &args.screenshot {
+        screenshot::save_png(chip8.framebuffer(), 8, DEFAULT_PALETTE, screenshot)?;
+    }
+    if let Some(path) = &args.profile {
+        save_profile(&chip8, path)?;
+    }
+    if let Some(path) = &args.coverage {
+        save_coverage(&chip8, path, args.coverage_format)?;
+    }
+    let fb = chip8.framebuffer();
+    print!("{fb}");
+    println!("sha1: {}", fb.sha1());
+    if let Some(differ) = &against {
+        println!(
+            "trace: {} of {} instructions match",
+            differ.matched(),
+            differ.len()
+        );
+    }
+    if args.status.is_some() {
+        match *status.lock().unwrap() {
+            Some(0) => println!("status: passed"),
+            Some(code) => return Err(format!("the ROM failed with status {code}")),
+            None => return Err("the ROM never wrote its status".to_string()),
+        }
+    }
+    match args.expect {
+        Some(expected) if !expected.eq_ignore_ascii_case(&fb.sha1()) => {
+            Err(format!("the screen doesn't match, expected {expected}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+// counts up in V0 forever
+#[cfg(test)]
+const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+// a rom written to a temporary file for a test, removed once dropped
+#[cfg(test)]
+struct TempRom(String);
+
+#[cfg(test)]
+impl TempRom {
+    fn new(name: &str, rom: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("emuchip-{name}-{}.ch8", process::id()));
+        fs::write(&path, rom).unwrap();
+        Self(path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempRom {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// `emuchip run` on `rom` with `extra` flags, the rom stays as long as the
+// TempRom does
+#[cfg(test)]
+fn run_args(name: &str, rom: &[u8], extra: &[&str]) -> (RunArgs, TempRom) {
+    use clap::Parser;
+
+    let file = TempRom::new(name, rom);
+    let argv = ["emuchip", "run", file.0.as_str()].into_iter();
+    let Command::Run(args) = Cli::parse_from(argv.chain(extra.iter().copied())).command else {
+        unreachable!()
+    };
+    (args, file)
+}
+
+#[test]
+fn test_emulation_thread() {
+    // V0 = 5, loop until key 5 is held, then exit
+    let rom = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x00, 0xFD];
+    let (args, file) = run_args("thread", &rom, &[]);
+
+    let mut machine = Machine::open(&args, &file.0).unwrap();
+    machine.handle_hotkey(&args, Hotkey::ToggleSlowMotion);
+    assert_eq!(machine.frame_length(), FRAME * 10);
+    machine.handle_hotkey(&args, Hotkey::ToggleSlowMotion);
+    let (inputs, machine_inputs) = mpsc::channel();
+    let (machine_frames, frames) = mpsc::channel();
+    let thread = thread::spawn(move || machine.run(&args, &machine_inputs, machine_frames, None));
+    // keeps emulating without the window
+    let frame = frames.recv().unwrap();
+    assert!(frame.title.contains("instructions/s, 0 fps 0 ips measured"));
+    assert!(frames.recv().is_ok());
+
+    let mut keys = Keyboard::new();
+    keys.set_key(5, true);
+    inputs
+        .send(Input {
+            keys,
+            hotkeys: vec![],
+            rewind_held: false,
+            open: None,
+        })
+        .unwrap();
+    // done once the rom exits, but only closes along with the window
+    while frames.recv().is_ok() {}
+    assert!(!thread.is_finished());
+    drop(inputs);
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_instructions_per_frame() {
+    let second = |speed| {
+        (0..60)
+            .map(|frame| instructions_in(speed, frame))
+            .sum::<u32>()
+    };
+    assert_eq!(second(700), 700);
+    assert_eq!(second(30), 30);
+    assert_eq!(instructions_in(600, 7), 10);
+    // 11 or 12, never drifting from one second to the next
+    assert!((0..600).all(|frame| (11..=12).contains(&instructions_in(700, frame))));
+    assert_eq!(
+        (60..120)
+            .map(|frame| instructions_in(700, frame))
+            .sum::<u32>(),
+        700
+    );
+}
+
+#[test]
+fn test_slow_test_runs() {
+    use clap::Parser;
+
+    // I = F00, write V0 = 0 there, which passes
+    let file = TempRom::new("slow", &[0xAF, 0x00, 0xF0, 0x55]);
+    let test_args = |extra: &[&str]| {
+        let argv = ["emuchip", "test", file.0.as_str(), "--status", "F00"].into_iter();
+        let Command::Test(args) = Cli::parse_from(argv.chain(extra.iter().copied())).command else {
+            unreachable!()
+        };
+        args
+    };
+    // fewer than one instruction a frame still adds up to two in four frames
+    test(test_args(&["--speed", "30", "--frames", "4"])).unwrap();
+    test(test_args(&["--speed", "30", "--max-cycles", "2"])).unwrap();
+    assert!(test(test_args(&["--speed", "30", "--frames", "2"])).is_err());
+}
+
+#[test]
+fn test_sleep_until() {
+    let deadline = Instant::now() + Duration::from_millis(5);
+    sleep_until(deadline);
+    // never early, the spin makes up for sleep's coarseness
+    assert!(Instant::now() >= deadline);
+    // long gone, no wait at all
+    let start = Instant::now();
+    sleep_until(deadline);
+    assert!(start.elapsed() < Duration::from_millis(1));
+}
+
+#[test]
+fn test_state_path() {
+    // the same rom under another name finds the same states
+    assert_eq!(state_path("roms/pong.ch8", "3cd1", 2), "roms/3cd1.state2");
+    assert_eq!(state_path("roms/PONG", "3cd1", 2), "roms/3cd1.state2");
+    assert_eq!(state_path("pong.ch8", "3cd1", 1), "3cd1.state1");
+    assert_eq!(resume_path("roms/pong.ch8", "3cd1"), "roms/3cd1.resume");
+    assert_eq!(flags_path("roms/pong.ch8", "3cd1"), "roms/3cd1.flags");
+    // not from a folder, so not in the current one either
+    for rom in ["-", builtin::MENU, "https://example.com/roms/pong.ch8"] {
+        let path = resume_path(rom, "3cd1");
+        assert!(path.ends_with("3cd1.resume"), "{path}");
+        assert_ne!(path, "3cd1.resume");
+        assert!(!path.starts_with("https:"), "{path}");
+        let state = state_path(rom, "3cd1", 1);
+        assert_eq!(Path::new(&state).parent(), Path::new(&path).parent());
+    }
+}
+
+#[test]
+fn test_resume() {
+    let (args, file) = run_args("resume", &COUNTER, &[]);
+    // the same file again
+    let (resumed, _again) = run_args("resume", &COUNTER, &["--resume"]);
+    let path = file.0.as_str();
+    let mut machine = Machine::open(&args, path).unwrap();
+    machine.frame(&args);
+    assert_ne!(machine.chip8.regs.get(0), 0);
+    // only kept when asked for
+    machine.close(&args).unwrap();
+    let state = resume_path(path, &database::sha1(&COUNTER));
+    assert!(!Path::new(&state).exists());
+    let mut machine = Machine::open(&resumed, path).unwrap();
+    machine.frame(&resumed);
+    let counted = machine.chip8.regs.get(0);
+    assert_ne!(counted, 0);
+    machine.close(&resumed).unwrap();
+    assert!(Path::new(&state).exists());
+
+    // from power on without --resume
+    assert_eq!(Machine::open(&args, path).unwrap().chip8.regs.get(0), 0);
+    let machine = Machine::open(&resumed, path).unwrap();
+    assert_eq!(machine.chip8.regs.get(0), counted);
+    fs::remove_file(state).unwrap();
+}
+
+#[test]
+fn test_pause_menu() {
+    let (args, file) = run_args("menu", &COUNTER, &[]);
+
+    let mut machine = Machine::open(&args, &file.0).unwrap();
+    machine.hotkeys = vec![Hotkey::Menu, Hotkey::MenuDown, Hotkey::MenuDown];
+    let frame = machine.frame(&args);
+    // nothing runs under the menu
+    assert_eq!(machine.chip8.regs.get(0), 0);
+    assert!(frame.title.contains("menu Open rom"));
+    assert_eq!(frame.menu.unwrap()[2], "> Open rom");
+
+    // going through the quirks and palettes keeps it open
+    machine.hotkeys = vec![Hotkey::MenuDown, Hotkey::MenuPick, Hotkey::MenuDown];
+    machine.hotkeys.extend([Hotkey::MenuPick, Hotkey::MenuPick]);
+    let frame = machine.frame(&args);
+    assert_eq!(
+        machine.chip8.quirks,
+        Quirks::from_platform(Platform::Cosmac)
+    );
+    assert_eq!(frame.palette, Some(PALETTES[2].1));
+    assert_eq!(frame.menu.unwrap()[4], "> Palette amber");
+
+    machine.hotkeys = vec![Hotkey::MenuUp, Hotkey::MenuUp, Hotkey::MenuPick];
+    assert!(machine.frame(&args).pick_rom);
+    machine.hotkeys = vec![Hotkey::Menu, Hotkey::MenuUp, Hotkey::MenuPick];
+    machine.frame(&args);
+    assert!(machine.quit);
+    // and Esc again gets back to the game
+    machine.hotkeys = vec![Hotkey::Menu, Hotkey::Menu];
+    machine.frame(&args);
+    assert_ne!(machine.chip8.regs.get(0), 0);
+
+    // and the quirks can be flipped one by one
+    machine.handle_hotkey(&args, Hotkey::ToggleQuirk("display-wait"));
+    assert!(!machine.chip8.quirks.display_wait);
+    assert_eq!(machine.messages, ["Quirk display-wait off"]);
+}
+
+#[test]
+fn test_recording_keeps_state() {
+    let replay = std::env::temp_dir().join(format!("emuchip-record-{}.replay", process::id()));
+    let replay = replay.display().to_string();
+    let (args, file) = run_args("record", &COUNTER, &["--record", &replay]);
+
+    let mut machine = Machine::open(&args, &file.0).unwrap();
+    machine.frame(&args);
+    let counted = machine.chip8.regs.get(0);
+    // neither would be in the replay, so neither happens
+    machine.handle_hotkey(&args, Hotkey::Reset);
+    assert_eq!(machine.chip8.regs.get(0), counted);
+    machine.rewind_held = true;
+    machine.frame(&args);
+    assert!(machine.chip8.regs.get(0) > counted);
+}
+
+#[test]
+fn test_headless_instance() {
+    use emuchip::host::Headless;
+
+    // V0 = 5, loop until key 5 is held, then exit
+    let rom = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x00, 0xFD];
+    let (args, file) = run_args("headless", &rom, &[]);
+    let args = Arc::new(args);
+
+    let machine = Machine::open(&args, &file.0).unwrap();
+    let mut instance = Instance::start(&args, machine, Box::new(Headless::holding(&[5])), None);
+    // the keypad gets the rom to exit, which ends the emulation
+    while !instance.finished {
+        instance.frame();
+        assert!(instance.frontend.draws < 600);
+        sleep_until(Instant::now() + FRAME);
+    }
+    assert!(instance
+        .frontend
+        .title
+        .starts_with("emuchip - emuchip-headless"));
+    instance.close().unwrap();
+}
+
+#[test]
+fn test_watch_broken_save() {
+    let (args, file) = run_args("watch", &COUNTER, &["--watch"]);
+    let mut machine = Machine::open(&args, &file.0).unwrap();
+    machine.frame(&args);
+
+    // half saved, so it doesn't load
+    let later = SystemTime::now() + Duration::from_secs(5);
+    fs::write(&file.0, []).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&file.0)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    machine.frame(&args);
+    // the change was seen, the next frame doesn't try it again
+    assert_eq!(machine.watched, Some(Some(later)));
+    assert_ne!(machine.chip8.regs.get(0), 0);
 }
@@ -1,9 +1,21 @@
-use std::{fs, process};
+use alloc::{vec, vec::Vec};
 
-use crate::registers::{IndexRegister, ProgramCounter};
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+
+use crate::{
+    decode::OpCodes,
+    registers::{IndexRegister, ProgramCounter},
+};
 
 pub type TypeAddr = u16; // in reality u12
 type FontBytes = [u8; 5 * 16];
+type BigFontBytes = [u8; 10 * 10];
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const PROGRAM_START: TypeAddr = 0x200;
+pub const FONT_START: TypeAddr = 0x50;
+pub const BIG_FONT_START: TypeAddr = 0xA0;
 
 const DEFAULT_FONT: FontBytes = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -24,50 +36,102 @@ const DEFAULT_FONT: FontBytes = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// SCHIP 8x10 digits, 0 through 9
+const DEFAULT_BIG_FONT: BigFontBytes = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+#[derive(Clone)]
 struct Font {
     data: FontBytes,
+    big_data: BigFontBytes,
 }
 
 impl Default for Font {
     fn default() -> Self {
-        Self { data: DEFAULT_FONT }
+        Self {
+            data: DEFAULT_FONT,
+            big_data: DEFAULT_BIG_FONT,
+        }
     }
 }
 
+// Instructions by the address they start at, the word and what it decodes
+// to, kept until a write lands on either of their bytes. Filled for all of
+// memory when a rom loads, copies like snapshots start out empty and fill in
+// as they run
+#[derive(Default)]
+struct Decoded(Vec<Option<(u16, OpCodes)>>);
+
+impl Clone for Decoded {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Memory {
     // 4k bytes
     // font data stored from 050 -> 09F (000 -> 04F is empty by convention)
-    bytes: [u8; 4096],
+    // big SCHIP font data stored from 0A0 -> 103
+    #[serde(with = "BigArray")]
+    bytes: [u8; MEMORY_SIZE],
     pub pc: ProgramCounter,
     pub index: IndexRegister,
+    // constant, reloaded with every rom
+    #[serde(skip)]
     font: Font,
     pub stack: Stack,
+    #[serde(skip)]
+    decoded: Decoded,
 }
 
 impl Memory {
     pub fn new() -> Self {
         Self {
-            bytes: [0; 4096],
-            pc: ProgramCounter(0x200, 0),
+            bytes: [0; MEMORY_SIZE],
+            pc: ProgramCounter(PROGRAM_START, 0),
             index: IndexRegister(0x0),
             stack: Stack::new(),
             font: Font::default(),
+            decoded: Decoded::default(),
         }
     }
 
-    pub fn set(&mut self, addr: TypeAddr, val: u8) {
-        self.bytes[addr as usize] = val;
+    // None past the end of memory
+    pub fn read(&self, addr: usize) -> Option<u8> {
+        self.bytes.get(addr).copied()
     }
 
-    pub fn get(&self, addr: TypeAddr) -> u8 {
-        self.bytes[addr as usize]
+    // false past the end of memory, nothing is written
+    pub fn write(&mut self, addr: usize, val: u8) -> bool {
+        match self.bytes.get_mut(addr) {
+            Some(byte) => {
+                *byte = val;
+                // as the first or the second byte of an instruction
+                for start in [addr, addr.wrapping_sub(1)] {
+                    if let Some(op) = self.decoded.0.get_mut(start) {
+                        *op = None;
+                    }
+                }
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn increment_pc(&mut self) {
-        let result = self.pc.increment();
-        if !result {
-            process::exit(0);
-        }
+    // false once the program counter has run past the end of the rom
+    pub fn increment_pc(&mut self) -> bool {
+        self.pc.increment()
     }
 
     pub fn decrement_pc(&mut self) {
@@ -77,13 +141,25 @@ impl Memory {
         self.pc.decrement();
     }
 
-    pub fn next_instruction(&mut self) -> u16 {
-        let (l, r) = (
-            self.bytes[self.pc.0 as usize],
-            self.bytes[(self.pc.0 + 1) as usize],
-        );
-        self.increment_pc();
-        ((l as u16) << 8) | r as u16
+    // the 2 byte big-endian opcode at addr, None if it runs past the end of memory
+    pub fn instruction_at(&self, addr: TypeAddr) -> Option<u16> {
+        let (l, r) = (self.read(addr as usize)?, self.read(addr as usize + 1)?);
+        Some(((l as u16) << 8) | r as u16)
+    }
+
+    // the opcode at addr and what it decodes to, only decoded again once
+    // something has written to it
+    pub fn op_at(&mut self, addr: TypeAddr) -> Option<(u16, OpCodes)> {
+        if let Some(&Some(decoded)) = self.decoded.0.get(addr as usize) {
+            return Some(decoded);
+        }
+        let code = self.instruction_at(addr)?;
+        let decoded = (code, OpCodes::decode_raw(code));
+        if self.decoded.0.is_empty() {
+            self.decoded.0 = vec![None; MEMORY_SIZE];
+        }
+        self.decoded.0[addr as usize] = Some(decoded);
+        Some(decoded)
     }
 
     pub fn set_pc(&mut self, addr: TypeAddr) {
@@ -94,26 +170,41 @@ impl Memory {
         self.index.set_addr(addr);
     }
 
-    // loads program instructions starting at address 0x09F
-    pub fn load_rom(&mut self, bytes: &[u8]) {
+    // loads program instructions starting at `start`, where the program
+    // counter starts too
+    pub fn load_rom(&mut self, bytes: &[u8], start: TypeAddr) {
+        self.pc.set_addr(start);
         self.pc.set_end(bytes.len());
-        let start_index = 0x200;
-        if start_index + bytes.len() <= 4096 {
+        let start_index = start as usize;
+        if start_index + bytes.len() <= MEMORY_SIZE {
             self.bytes[start_index..start_index + bytes.len()].copy_from_slice(bytes);
         }
 
         // load font
-        let start_index = 0x50;
+        let start_index = FONT_START as usize;
         self.bytes[start_index..start_index + self.font.data.len()]
             .copy_from_slice(&self.font.data);
+
+        let start_index = BIG_FONT_START as usize;
+        self.bytes[start_index..start_index + self.font.big_data.len()]
+            .copy_from_slice(&self.font.big_data);
+
+        self.decoded.0 = (0..MEMORY_SIZE as TypeAddr)
+            .map(|addr| {
+                self.instruction_at(addr)
+                    .map(|code| (code, OpCodes::decode_raw(code)))
+            })
+            .collect();
     }
+}
 
-    pub fn load_rom_by_file(&mut self, path: &str) {
-        let program = fs::read(path).unwrap();
-        self.load_rom(program.as_slice());
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Stack {
     addresses: Vec<TypeAddr>,
 }
@@ -130,4 +221,15 @@ impl Stack {
     pub fn pop(&mut self) -> Option<TypeAddr> {
         self.addresses.pop()
     }
+
+    // return addresses, oldest call first
+    pub fn addresses(&self) -> &[TypeAddr] {
+        &self.addresses
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
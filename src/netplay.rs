@@ -0,0 +1,180 @@
+use std::{
+    io::{BufReader, BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{keyboard::Keyboard, quirks::Quirks, Chip8};
+
+// sent first by both sides, followed by a protocol version byte
+const MAGIC: &[u8; 4] = b"EMCN";
+const VERSION: u8 = 2;
+
+// a peer this quiet is taken to be gone
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// What the host starts the guest with, so both run the same program the same
+// way
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    seed: u64,
+    // instructions per frame
+    cycles: u32,
+    quirks: Quirks,
+    rom_sha1: String,
+}
+
+// The keypad state of one side for one frame, a bit per key
+#[derive(Serialize, Deserialize)]
+struct Input {
+    frame: u64,
+    keys: u16,
+}
+
+// Shared control of one emulation with a peer over TCP. Both sides run the
+// same deterministic emulation and swap keypad state every frame, pressing
+// whatever either side holds, so they stay in lockstep
+pub struct Session {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Session {
+    // waits on `addr` for a peer to join, chip8 must have its rom loaded
+    pub fn host(addr: &str, chip8: &Chip8, cycles: u32) -> Result<Self, String> {
+        let listener =
+            TcpListener::bind(addr).map_err(|err| format!("could not listen on {addr}: {err}"))?;
+        Self::accept(&listener, chip8, cycles)
+    }
+
+    fn accept(listener: &TcpListener, chip8: &Chip8, cycles: u32) -> Result<Self, String> {
+        let (stream, _) = listener
+            .accept()
+            .map_err(|err| format!("no peer joined: {err}"))?;
+        let mut session = Self::new(stream)?;
+        session.send(&Hello {
+            seed: chip8.seed(),
+            cycles,
+            quirks: chip8.quirks,
+            rom_sha1: chip8.rom_sha1().to_string(),
+        })?;
+        Ok(session)
+    }
+
+    // connects to a host running the same rom and takes on its seed and
+    // quirks, returns the instructions per frame to run at
+    pub fn join(addr: &str, chip8: &mut Chip8) -> Result<(Self, u32), String> {
+        let stream = TcpStream::connect(addr)
+            .map_err(|err| format!("could not connect to {addr}: {err}"))?;
+        let mut session = Self::new(stream)?;
+        let hello: Hello = session.receive()?;
+        if hello.rom_sha1 != chip8.rom_sha1() {
+            return Err("the host is running a different rom".to_string());
+        }
+        chip8.quirks = hello.quirks;
+        chip8.set_seed(hello.seed);
+        chip8.reset();
+        Ok((session, hello.cycles))
+    }
+
+    fn new(stream: TcpStream) -> Result<Self, String> {
+        let error = |err| format!("could not set up the connection: {err}");
+        // a frame of input is tiny, don't hold it back
+        stream.set_nodelay(true).map_err(error)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(error)?;
+        let mut session = Self {
+            reader: BufReader::new(stream.try_clone().map_err(error)?),
+            writer: BufWriter::new(stream),
+        };
+
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        session.send(&header)?;
+        let peer: Vec<u8> = session.receive()?;
+        match peer.as_slice() {
+            [m0, m1, m2, m3, version] if [*m0, *m1, *m2, *m3] == *MAGIC => {
+                if *version != VERSION {
+                    return Err(format!("the peer speaks netplay version {version}"));
+                }
+                Ok(session)
+            }
+            _ => Err("the peer is not emuchip".to_string()),
+        }
+    }
+
+    // call once per frame in place of setting the keypad, blocks until the peer
+    // has sent the same frame. The local keys are read into a keyboard of
+    // their own, chip8 must only see each side's presses once both are in
+    pub fn exchange(
+        &mut self,
+        frame: u64,
+        local: &Keyboard,
+        chip8: &mut Chip8,
+    ) -> Result<(), String> {
+        let keys = (0..16)
+            .filter(|key| local.get_key_status_from_num(*key))
+            .fold(0, |keys, key| keys | 1 << key);
+        self.send(&Input { frame, keys })?;
+        let peer: Input = self.receive()?;
+        if peer.frame != frame {
+            return Err(format!(
+                "out of sync with the peer, frame {} against {frame}",
+                peer.frame
+            ));
+        }
+        for key in 0..16 {
+            chip8.set_key(key, (keys | peer.keys) & 1 << key != 0);
+        }
+        Ok(())
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<(), String> {
+        bincode::serialize_into(&mut self.writer, message)
+            .map_err(|err| err.to_string())
+            .and_then(|_| self.writer.flush().map_err(|err| err.to_string()))
+            .map_err(|err| format!("lost the peer: {err}"))
+    }
+
+    fn receive<T: for<'de> Deserialize<'de>>(&mut self) -> Result<T, String> {
+        bincode::deserialize_from(&mut self.reader).map_err(|err| format!("lost the peer: {err}"))
+    }
+}
+
+#[test]
+fn test_sessions_stay_in_lockstep() {
+    // wait for a key, V1 = random & key, loop
+    let rom = [0xF0, 0x0A, 0xC1, 0xFF, 0x81, 0x02, 0x12, 0x00];
+    // each side presses its own key for a few frames
+    let run = |chip8: &mut Chip8, session: &mut Session, key: u8, held: std::ops::Range<u64>| {
+        let mut local = Keyboard::new();
+        for frame in 0..12 {
+            local.set_key(key, held.contains(&frame));
+            session.exchange(frame, &local, chip8).unwrap();
+            for _ in 0..8 {
+                chip8.step().unwrap();
+            }
+            chip8.sync();
+        }
+        (chip8.regs.get(0), chip8.regs.get(1), chip8.mem.pc.0)
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let host = std::thread::spawn(move || {
+        let mut chip8 = Chip8::with_seed(Quirks::default(), 1);
+        chip8.load_rom(&rom).unwrap();
+        let mut session = Session::accept(&listener, &chip8, 8).unwrap();
+        run(&mut chip8, &mut session, 0x7, 2..4)
+    });
+
+    let mut chip8 = Chip8::with_seed(Quirks::default(), 2);
+    chip8.load_rom(&rom).unwrap();
+    let (mut session, cycles) = Session::join(&addr, &mut chip8).unwrap();
+    assert_eq!((cycles, chip8.seed()), (8, 1));
+    let guest = run(&mut chip8, &mut session, 0x9, 6..9);
+    assert_eq!(host.join().unwrap(), guest);
+    // the guest's key went through on the host too
+    assert_eq!(guest.0, 0x9);
+}
@@ -0,0 +1,754 @@
+use std::{collections::HashMap, mem};
+
+use serde::Deserialize;
+
+use crate::{
+    decode::OpCodes,
+    memory::{TypeAddr, MEMORY_SIZE, PROGRAM_START},
+    quirks::{Platform, Quirks},
+    symbols::Symbols,
+};
+
+// Assembles Octo source, what most CHIP-8 programs are written in nowadays,
+// into a rom loaded at 200. Covers labels, :const, :alias, :org, :byte,
+// :call, :next and :unpack, every instruction the emulator runs, if/then,
+// if/begin/else/end and loop/while/again. Macros, :calc, the comparisons
+// Octo builds out of VF and the XO-CHIP instructions the emulator doesn't
+// have are errors
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_with_symbols(source).map(|(rom, _)| rom)
+}
+
+// the rom along with where its labels ended up
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, Symbols), String> {
+    let tokens = tokenize(source);
+    // Octo starts at main, the jump to it isn't needed when main comes first
+    let (rom, labels) = Assembler::new(&tokens, true).run()?;
+    let (rom, labels) = match labels.get("main") {
+        Some(&main) if main != PROGRAM_START + 2 => (rom, labels),
+        _ => Assembler::new(&tokens, false).run()?,
+    };
+    let mut labels: Vec<_> = labels.into_iter().collect();
+    labels.sort_by_key(|&(name, addr)| (addr, name));
+    let mut symbols = Symbols::new();
+    for (name, addr) in labels {
+        symbols.insert(addr, name)?;
+    }
+    Ok((rom, symbols))
+}
+
+// An Octo cartridge, a GIF with the source and settings of a program hidden
+// in the two low bits of its pixels
+pub struct Cartridge {
+    pub source: String,
+    pub quirks: Quirks,
+    // instructions per second
+    pub speed: Option<u32>,
+    // foreground and background
+    pub palette: Option<(u32, u32)>,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    program: String,
+    #[serde(default)]
+    options: Options,
+}
+
+// the settings Octo saves along, its quirks are named for where they differ
+// from its defaults
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Options {
+    // instructions per frame
+    tickrate: Option<u32>,
+    fill_color: Option<String>,
+    background_color: Option<String>,
+    shift_quirks: Option<bool>,
+    load_store_quirks: Option<bool>,
+    clip_quirks: Option<bool>,
+    jump_quirks: Option<bool>,
+    logic_quirks: Option<bool>,
+    v_blank_quirks: Option<bool>,
+}
+
+// Every four pixels of every frame make a byte, high bits first. The bytes
+// are the length of the payload as 32 bits big endian, then the payload, JSON
+// with the source and the options
+pub fn read_cartridge(gif: &[u8]) -> Result<Cartridge, String> {
+    let not_a_cartridge = || "not an Octo cartridge".to_string();
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(gif)
+        .map_err(|err| format!("not a GIF: {err}"))?;
+    let mut bytes = vec![];
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|err| format!("bad GIF: {err}"))?
+    {
+        bytes.extend(
+            frame
+                .buffer
+                .chunks_exact(4)
+                .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | pixel & 3)),
+        );
+    }
+    let [a, b, c, d, payload @ ..] = bytes.as_slice() else {
+        return Err(not_a_cartridge());
+    };
+    let len = u32::from_be_bytes([*a, *b, *c, *d]) as usize;
+    let payload = payload.get(..len).ok_or_else(not_a_cartridge)?;
+    let Payload { program, options } =
+        serde_json::from_slice(payload).map_err(|_| not_a_cartridge())?;
+
+    // Octo's defaults are XO-CHIP's
+    let mut quirks = Quirks::from_platform(Platform::XoChip);
+    // Octo names these for what emuchip does without the quirk
+    let opposite = [
+        (&mut quirks.shift_uses_vy, options.shift_quirks),
+        (&mut quirks.memory_increments_i, options.load_store_quirks),
+        (&mut quirks.wrap_sprites, options.clip_quirks),
+    ];
+    for (quirk, octo) in opposite {
+        if let Some(octo) = octo {
+            *quirk = !octo;
+        }
+    }
+    let alike = [
+        (&mut quirks.jump_uses_vx, options.jump_quirks),
+        (&mut quirks.vf_reset, options.logic_quirks),
+        (&mut quirks.display_wait, options.v_blank_quirks),
+    ];
+    for (quirk, octo) in alike {
+        if let Some(octo) = octo {
+            *quirk = octo;
+        }
+    }
+    let rgb = |color: Option<String>| u32::from_str_radix(color?.trim_start_matches('#'), 16).ok();
+    let palette = rgb(options.fill_color).zip(rgb(options.background_color));
+    Ok(Cartridge {
+        source: program,
+        quirks,
+        speed: options.tickrate.map(|tickrate| tickrate * 60),
+        palette,
+    })
+}
+
+// words with the line they're on, comments run from # to the end of the line
+fn tokenize(source: &str) -> Vec<(usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |word| (i + 1, word))
+        })
+        .collect()
+}
+
+// what an if or a loop waits on to know where its jumps go
+enum Block {
+    // the jump past the body
+    If(TypeAddr),
+    // the jump past the else
+    Else(TypeAddr),
+    // where again jumps back to, and the jumps out of its whiles
+    Loop(TypeAddr, Vec<TypeAddr>),
+}
+
+// an address operand naming a label, filled in once every label is known
+struct Fixup<'a> {
+    at: TypeAddr,
+    label: &'a str,
+    line: usize,
+    // the two loads of :unpack rather than one instruction
+    unpack: bool,
+}
+
+struct Assembler<'a> {
+    tokens: &'a [(usize, &'a str)],
+    pos: usize,
+    // of the last word read, for errors
+    line: usize,
+    jump_to_main: bool,
+    rom: Vec<u8>,
+    here: TypeAddr,
+    labels: HashMap<&'a str, TypeAddr>,
+    constants: HashMap<&'a str, i32>,
+    aliases: HashMap<&'a str, u8>,
+    fixups: Vec<Fixup<'a>>,
+    // open ifs and loops, innermost last, with the line they start on
+    blocks: Vec<(usize, Block)>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(tokens: &'a [(usize, &'a str)], jump_to_main: bool) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            line: 1,
+            jump_to_main,
+            rom: vec![],
+            here: PROGRAM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: vec![],
+            blocks: vec![],
+        }
+    }
+
+    // the rom and its labels
+    fn run(mut self) -> Result<(Vec<u8>, HashMap<&'a str, TypeAddr>), String> {
+        if self.jump_to_main {
+            self.op(OpCodes::Jump(0))?;
+        }
+        while self.pos < self.tokens.len() {
+            let word = self.next()?;
+            self.statement(word)?;
+        }
+        if let Some((line, block)) = self.blocks.last() {
+            let name = match block {
+                Block::If(_) | Block::Else(_) => "if",
+                Block::Loop(..) => "loop",
+            };
+            return Err(format!("line {line}: {name} without an end"));
+        }
+        for fixup in mem::take(&mut self.fixups) {
+            let Some(&target) = self.labels.get(fixup.label) else {
+                return Err(format!(
+                    "line {}: '{}' isn't an instruction or a label",
+                    fixup.line, fixup.label
+                ));
+            };
+            if fixup.unpack {
+                let offset = (fixup.at - PROGRAM_START) as usize;
+                self.rom[offset + 1] |= (target >> 8) as u8 & 0xF;
+                self.rom[offset + 3] = target as u8;
+            } else {
+                self.patch(fixup.at, target);
+            }
+        }
+        let main = self.labels.get("main").copied();
+        if let (true, Some(main)) = (self.jump_to_main, main) {
+            self.patch(PROGRAM_START, main);
+        }
+        Ok((self.rom, self.labels))
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {message}", self.line)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let Some(&(line, word)) = self.tokens.get(self.pos) else {
+            return Err(self.error("unexpected end of file"));
+        };
+        self.pos += 1;
+        self.line = line;
+        Ok(word)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|(_, word)| *word)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let word = self.next()?;
+        if word == expected {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{expected}', found '{word}'")))
+        }
+    }
+
+    // a number in decimal, hex or binary, or a constant
+    fn value(&self, word: &str) -> Option<i32> {
+        if let Some(&value) = self.constants.get(word) {
+            return Some(value);
+        }
+        let (sign, digits) = match word.strip_prefix('-') {
+            Some(digits) => (-1, digits),
+            None => (1, word),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i32::from_str_radix(binary, 2)
+        } else {
+            digits.parse()
+        };
+        value.ok().map(|value| sign * value)
+    }
+
+    // a byte, negative numbers in two's complement
+    fn byte_of(&self, word: &str) -> Result<u8, String> {
+        match self.value(word) {
+            Some(value @ -128..=255) => Ok(value as u8),
+            _ => Err(self.error(&format!("'{word}' isn't a byte"))),
+        }
+    }
+
+    fn byte_value(&mut self) -> Result<u8, String> {
+        let word = self.next()?;
+        self.byte_of(word)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let word = self.next()?;
+        match self.value(word) {
+            Some(value @ 0..=15) => Ok(value as u8),
+            _ => Err(self.error(&format!("'{word}' isn't a number from 0 to 15"))),
+        }
+    }
+
+    fn register_of(&self, word: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(word) {
+            return Some(reg);
+        }
+        let digit = word.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let word = self.next()?;
+        self.register_of(word)
+            .ok_or_else(|| self.error(&format!("'{word}' isn't a register")))
+    }
+
+    // an address, a label is filled in at the end
+    fn target(&mut self, unpack: bool) -> Result<TypeAddr, String> {
+        let word = self.next()?;
+        match self.value(word) {
+            Some(addr @ 0..=0xFFF) => Ok(addr as TypeAddr),
+            Some(_) => Err(self.error(&format!("'{word}' isn't an address"))),
+            None => {
+                self.fixups.push(Fixup {
+                    at: self.here,
+                    label: word,
+                    line: self.line,
+                    unpack,
+                });
+                Ok(0)
+            }
+        }
+    }
+
+    fn label(&mut self, name: &'a str, addr: TypeAddr) -> Result<(), String> {
+        if self.register_of(name).is_some() || self.value(name).is_some() {
+            return Err(self.error(&format!("'{name}' can't be a label")));
+        }
+        if self.labels.insert(name, addr).is_some() {
+            return Err(self.error(&format!("label '{name}' is defined twice")));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.here as usize >= MEMORY_SIZE {
+            return Err(self.error("the program doesn't fit in memory"));
+        }
+        let offset = (self.here - PROGRAM_START) as usize;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn op(&mut self, op: OpCodes) -> Result<(), String> {
+        let [high, low] = op
+            .encode()
+            .expect("assembled instructions encode")
+            .to_be_bytes();
+        self.byte(high)?;
+        self.byte(low)
+    }
+
+    fn address(&mut self, op: fn(TypeAddr) -> OpCodes) -> Result<(), String> {
+        let addr = self.target(false)?;
+        self.op(op(addr))
+    }
+
+    // points the instruction at `at` to `target`
+    fn patch(&mut self, at: TypeAddr, target: TypeAddr) {
+        let offset = (at - PROGRAM_START) as usize;
+        self.rom[offset] |= (target >> 8) as u8 & 0xF;
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn statement(&mut self, word: &'a str) -> Result<(), String> {
+        if let Some(x) = self.register_of(word) {
+            return self.assignment(x);
+        }
+        match word {
+            ":" => {
+                let name = self.next()?;
+                self.label(name, self.here)
+            }
+            // the byte after the next instruction's first, for code that
+            // rewrites itself
+            ":next" => {
+                let name = self.next()?;
+                self.label(name, self.here + 1)
+            }
+            ":const" => {
+                let name = self.next()?;
+                let word = self.next()?;
+                let value = self
+                    .value(word)
+                    .ok_or_else(|| self.error(&format!("'{word}' isn't a number")))?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+                Ok(())
+            }
+            ":org" => {
+                let word = self.next()?;
+                match self.value(word) {
+                    Some(addr) if (PROGRAM_START as i32..MEMORY_SIZE as i32).contains(&addr) => {
+                        self.here = addr as TypeAddr;
+                        Ok(())
+                    }
+                    _ => Err(self.error(&format!("can't :org to '{word}'"))),
+                }
+            }
+            ":byte" => {
+                let byte = self.byte_value()?;
+                self.byte(byte)
+            }
+            ":call" => self.address(OpCodes::PushSubroutine),
+            // v0 = N and the high nibble of the address, v1 = the rest
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let addr = self.target(true)?;
+                self.op(OpCodes::SetRegister(0, nibble << 4 | (addr >> 8) as u8))?;
+                self.op(OpCodes::SetRegister(1, addr as u8))
+            }
+            // only for Octo's debugger
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => self.next().and_then(|_| self.next()).map(|_| ()),
+            "clear" => self.op(OpCodes::ClearScreen),
+            "return" | ";" => self.op(OpCodes::PopSubroutine),
+            "hires" => self.op(OpCodes::HighRes),
+            "lores" => self.op(OpCodes::LowRes),
+            "exit" => self.op(OpCodes::Exit),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.op(OpCodes::ScrollDown(n))
+            }
+            "scroll-left" => self.op(OpCodes::ScrollLeft),
+            "scroll-right" => self.op(OpCodes::ScrollRight),
+            "audio" => self.op(OpCodes::LoadAudio),
+            "bcd" => {
+                let x = self.register()?;
+                self.op(OpCodes::ToDecimal(x))
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    return Err(self.error("register ranges aren't supported"));
+                }
+                if word == "save" {
+                    self.op(OpCodes::StoreRegisterToMemory(x))
+                } else {
+                    self.op(OpCodes::LoadRegisterFromMemory(x))
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.op(OpCodes::StoreFlags(x))
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.op(OpCodes::LoadFlags(x))
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.op(OpCodes::Display(x, y, n))
+            }
+            "jump" => self.address(OpCodes::Jump),
+            "jump0" => self.address(OpCodes::JumpWithOffset),
+            "native" => self.address(OpCodes::MachineRoutine),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.op(match word {
+                    "delay" => OpCodes::CopyRegisterToDelay(x),
+                    "buzzer" => OpCodes::CopyRegisterToSound(x),
+                    _ => OpCodes::SetPitch(x),
+                })
+            }
+            "i" => self.index(),
+            "if" => self.conditional(),
+            "else" => {
+                let Some((line, Block::If(at))) = self.blocks.pop() else {
+                    return Err(self.error("else without an if ... begin"));
+                };
+                let end = self.here;
+                self.op(OpCodes::Jump(0))?;
+                self.patch(at, self.here);
+                self.blocks.push((line, Block::Else(end)));
+                Ok(())
+            }
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If(at) | Block::Else(at))) => {
+                    self.patch(at, self.here);
+                    Ok(())
+                }
+                _ => Err(self.error("end without an if ... begin")),
+            },
+            "loop" => {
+                self.blocks
+                    .push((self.line, Block::Loop(self.here, vec![])));
+                Ok(())
+            }
+            // leaves the loop unless the condition holds
+            "while" => {
+                let (holds, _) = self.condition()?;
+                self.op(holds)?;
+                let at = self.here;
+                self.op(OpCodes::Jump(0))?;
+                let innermost = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(_, block)| match block {
+                        Block::Loop(_, breaks) => Some(breaks),
+                        _ => None,
+                    });
+                match innermost {
+                    Some(breaks) => {
+                        breaks.push(at);
+                        Ok(())
+                    }
+                    None => Err(self.error("while outside a loop")),
+                }
+            }
+            "again" => {
+                let Some((_, Block::Loop(start, breaks))) = self.blocks.pop() else {
+                    return Err(self.error("again without a loop"));
+                };
+                self.op(OpCodes::Jump(start))?;
+                for at in breaks {
+                    self.patch(at, self.here);
+                }
+                Ok(())
+            }
+            ":macro" | ":calc" | ":stringmode" | ":assert" | "scroll-up" | "plane" => {
+                Err(self.error(&format!("'{word}' isn't supported")))
+            }
+            // a number is a byte of data, a label name calls it
+            _ => match self.value(word) {
+                Some(_) => {
+                    let byte = self.byte_of(word)?;
+                    self.byte(byte)
+                }
+                None => {
+                    self.fixups.push(Fixup {
+                        at: self.here,
+                        label: word,
+                        line: self.line,
+                        unpack: false,
+                    });
+                    self.op(OpCodes::PushSubroutine(0))
+                }
+            },
+        }
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), String> {
+        let operator = self.next()?;
+        let word = self.next()?;
+        let op = match (operator, self.register_of(word)) {
+            (":=", Some(y)) => OpCodes::CopyRegister(x, y),
+            (":=", None) => match word {
+                "random" => OpCodes::Random(x, self.byte_value()?),
+                "delay" => OpCodes::CopyDelayToRegister(x),
+                "key" => OpCodes::GetKey(x),
+                _ => OpCodes::SetRegister(x, self.byte_of(word)?),
+            },
+            ("+=", Some(y)) => OpCodes::Add(x, y),
+            ("+=", None) => OpCodes::AddToRegister(x, self.byte_of(word)?),
+            ("-=", Some(y)) => OpCodes::SubtractForward(x, y),
+            ("-=", None) => OpCodes::AddToRegister(x, self.byte_of(word)?.wrapping_neg()),
+            ("=-", Some(y)) => OpCodes::SubtractBackward(x, y),
+            ("|=", Some(y)) => OpCodes::Or(x, y),
+            ("&=", Some(y)) => OpCodes::And(x, y),
+            ("^=", Some(y)) => OpCodes::XOr(x, y),
+            (">>=", Some(y)) => OpCodes::RightShift(x, y),
+            ("<<=", Some(y)) => OpCodes::LeftShift(x, y),
+            _ => return Err(self.error(&format!("can't assemble v{x:x} {operator} {word}"))),
+        };
+        self.op(op)
+    }
+
+    fn index(&mut self) -> Result<(), String> {
+        match self.next()? {
+            "+=" => {
+                let x = self.register()?;
+                self.op(OpCodes::AddToIndex(x))
+            }
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.op(OpCodes::PointChar(x))
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.op(OpCodes::PointBigChar(x))
+                }
+                Some("long") => Err(self.error("'i := long' isn't supported")),
+                _ => self.address(OpCodes::SetIndexRegister),
+            },
+            operator => Err(self.error(&format!("can't assemble i {operator}"))),
+        }
+    }
+
+    // the skips over the next instruction when the condition holds and when
+    // it doesn't
+    fn condition(&mut self) -> Result<(OpCodes, OpCodes), String> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        match operator {
+            "key" => return Ok((OpCodes::SkipIfPressed(x), OpCodes::SkipIfNotPressed(x))),
+            "-key" => return Ok((OpCodes::SkipIfNotPressed(x), OpCodes::SkipIfPressed(x))),
+            _ => {}
+        }
+        let word = self.next()?;
+        let (equal, not_equal) = match self.register_of(word) {
+            Some(y) => (
+                OpCodes::SkipEqualRegister(x, y),
+                OpCodes::SkipNotEqualRegister(x, y),
+            ),
+            None => {
+                let nn = self.byte_of(word)?;
+                (
+                    OpCodes::SkipEqualConstant(x, nn),
+                    OpCodes::SkipNotEqualConstant(x, nn),
+                )
+            }
+        };
+        match operator {
+            "==" => Ok((equal, not_equal)),
+            "!=" => Ok((not_equal, equal)),
+            _ => Err(self.error(&format!(
+                "'{operator}' isn't supported, only ==, !=, key and -key"
+            ))),
+        }
+    }
+
+    // `then` guards the next instruction, `begin` jumps past the body when
+    // the condition doesn't hold
+    fn conditional(&mut self) -> Result<(), String> {
+        let (holds, fails) = self.condition()?;
+        match self.next()? {
+            "then" => self.op(fails),
+            "begin" => {
+                self.op(holds)?;
+                self.blocks.push((self.line, Block::If(self.here)));
+                self.op(OpCodes::Jump(0))
+            }
+            word => Err(self.error(&format!("expected 'then' or 'begin', found '{word}'"))),
+        }
+    }
+}
+
+#[test]
+fn test_assemble() {
+    let rom = assemble(
+        "
+        :const STEP 3
+        :alias x v1
+        : main
+            x := 0
+            loop
+                x += STEP
+                if x == 9 then exit
+                while x != 12
+            again
+            draw
+        : draw  # draws the dot
+            i := dot
+            sprite v0 x 1
+            ;
+        : dot 0b10000000
+        ",
+    )
+    .unwrap();
+    assert_eq!(
+        rom,
+        [
+            0x61, 0x00, 0x71, 0x03, 0x41, 0x09, 0x00, 0xFD, 0x41, 0x0C, 0x12, 0x0E, 0x12, 0x02,
+            0x22, 0x10, 0xA2, 0x16, 0xD0, 0x11, 0x00, 0xEE, 0x80,
+        ]
+    );
+
+    // data before main gets a jump over it
+    let rom = assemble(
+        ": data 0x12 0x34
+         : main
+             if v0 key begin v1 := 1 else v1 := -2 end",
+    )
+    .unwrap();
+    assert_eq!(
+        rom,
+        [0x12, 0x04, 0x12, 0x34, 0xE0, 0x9E, 0x12, 0x0C, 0x61, 0x01, 0x12, 0x0E, 0x61, 0xFE]
+    );
+    let (_, symbols) = assemble_with_symbols(": data 0x12 0x34 : main clear").unwrap();
+    assert_eq!(symbols.to_string(), "202 data\n204 main\n");
+
+    let error = |source| assemble(source).unwrap_err();
+    assert_eq!(
+        error("\njump nowhere"),
+        "line 2: 'nowhere' isn't an instruction or a label"
+    );
+    assert_eq!(error("loop clear"), "line 1: loop without an end");
+    assert_eq!(error("v0 := 300"), "line 1: '300' isn't a byte");
+    assert!(error("if v0 < 3 then clear").contains("isn't supported"));
+}
+
+#[test]
+fn test_read_cartridge() {
+    let payload = br##"{"program": ": main clear", "options":
+        {"tickrate": 20, "shiftQuirks": true, "vBlankQuirks": true,
+         "fillColor": "#FFCC00", "backgroundColor": "#996600", "fontStyle": "octo"}}"##;
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(payload);
+    // frames of 16x8 pixels hold 32 bytes, the rest of the last is zeros
+    let mut pixels: Vec<u8> = bytes
+        .iter()
+        .flat_map(|byte| [6, 4, 2, 0].map(|shift| 4 | (byte >> shift) & 3))
+        .collect();
+    pixels.resize(pixels.len().div_ceil(128) * 128, 4);
+    let mut gif = vec![];
+    {
+        let mut encoder = gif::Encoder::new(&mut gif, 16, 8, &[0; 8 * 3]).unwrap();
+        for frame in pixels.chunks(128) {
+            let frame = gif::Frame::from_indexed_pixels(16, 8, frame.to_vec(), None);
+            encoder.write_frame(&frame).unwrap();
+        }
+    }
+
+    let cartridge = read_cartridge(&gif).unwrap();
+    assert_eq!(assemble(&cartridge.source).unwrap(), [0x00, 0xE0]);
+    let mut quirks = Quirks::from_platform(Platform::XoChip);
+    quirks.shift_uses_vy = false;
+    quirks.display_wait = true;
+    assert_eq!(cartridge.quirks, quirks);
+    assert_eq!(cartridge.speed, Some(1200));
+    assert_eq!(cartridge.palette, Some((0xFFCC00, 0x996600)));
+
+    assert!(read_cartridge(b"GIF89a").is_err());
+}
@@ -0,0 +1,402 @@
+use std::time::{Duration, Instant};
+
+use emuchip::host::{Stats, KEYPAD};
+
+// glyphs are 3x5 with a pixel of space around them
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+// how long a message stays on screen
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+// 3 bits a row, digits, letters in upper case and what the overlay uses,
+// anything else is blank
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        'a'..='z' => glyph(c.to_ascii_uppercase()),
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        _ => [0; 5],
+    }
+}
+
+// Real frames and instructions per second, counted over the last second
+pub struct Meter {
+    start: Instant,
+    frames: u32,
+    instructions: u32,
+    pub fps: u32,
+    pub ips: u32,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            frames: 0,
+            instructions: 0,
+            fps: 0,
+            ips: 0,
+        }
+    }
+
+    // once a frame, with how many instructions ran in it
+    pub fn frame(&mut self, instructions: u32) {
+        self.frames += 1;
+        self.instructions += instructions;
+        let elapsed = self.start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let rate = |count: u32| (count as f64 / elapsed.as_secs_f64()).round() as u32;
+            self.fps = rate(self.frames);
+            self.ips = rate(self.instructions);
+            self.start = Instant::now();
+            self.frames = 0;
+            self.instructions = 0;
+        }
+    }
+}
+
+// the debug overlay in the top left corner of a `width` wide buffer
+pub fn draw_stats(stats: &Stats, out: &mut [u32], width: usize, height: usize, color: u32) {
+    draw_text(out, width, height, &stats.lines(), false, color);
+}
+
+// A line of text shown for a little while, e.g. "State 2 saved"
+pub struct Message {
+    text: String,
+    shown: Instant,
+}
+
+impl Message {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            shown: Instant::now(),
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.shown.elapsed() >= MESSAGE_TIME
+    }
+
+    // in the bottom left corner of a `width` wide buffer
+    pub fn draw(&self, out: &mut [u32], width: usize, height: usize, color: u32) {
+        draw_text(
+            out,
+            width,
+            height,
+            std::slice::from_ref(&self.text),
+            true,
+            color,
+        );
+    }
+}
+
+// What the pause menu offers, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuItem {
+    Resume,
+    Reset,
+    OpenRom,
+    // goes through the presets
+    Quirks,
+    Palette,
+    Quit,
+}
+
+const MENU_ITEMS: [MenuItem; 6] = [
+    MenuItem::Resume,
+    MenuItem::Reset,
+    MenuItem::OpenRom,
+    MenuItem::Quirks,
+    MenuItem::Palette,
+    MenuItem::Quit,
+];
+
+// The menu Esc brings up over the paused emulation, Up and Down go through
+// it and wrap around
+#[derive(Default)]
+pub struct Menu {
+    selected: usize,
+}
+
+impl Menu {
+    pub fn up(&mut self) {
+        self.selected = (self.selected + MENU_ITEMS.len() - 1) % MENU_ITEMS.len();
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1) % MENU_ITEMS.len();
+    }
+
+    pub fn selected(&self) -> MenuItem {
+        MENU_ITEMS[self.selected]
+    }
+
+    // with the names of the quirks and palette in use, > in front of the
+    // selected entry
+    pub fn lines(&self, quirks: &str, palette: &str) -> Vec<String> {
+        MENU_ITEMS
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let label = match item {
+                    MenuItem::Resume => "Resume".to_string(),
+                    MenuItem::Reset => "Reset".to_string(),
+                    MenuItem::OpenRom => "Open rom".to_string(),
+                    MenuItem::Quirks => format!("Quirks {quirks}"),
+                    MenuItem::Palette => format!("Palette {palette}"),
+                    MenuItem::Quit => "Quit".to_string(),
+                };
+                let marker = if index == self.selected { '>' } else { ' ' };
+                format!("{marker} {label}")
+            })
+            .collect()
+    }
+}
+
+// the menu's lines in the top left corner of a `width` wide buffer, where the
+// debug overlay would be
+pub fn draw_menu(lines: &[String], out: &mut [u32], width: usize, height: usize, color: u32) {
+    draw_text(out, width, height, lines, false, color);
+}
+
+// lines of text in the top or bottom left corner of a `width` wide buffer, on
+// a darkened box so it reads over lit pixels
+fn draw_text(
+    out: &mut [u32],
+    width: usize,
+    height: usize,
+    lines: &[String],
+    bottom: bool,
+    color: u32,
+) {
+    // about 64 characters across whatever the size
+    let scale = (width / 256).max(1);
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let box_width = ((columns * GLYPH_WIDTH + 1) * scale).min(width);
+    let box_height = ((lines.len() * GLYPH_HEIGHT + 1) * scale).min(height);
+    let box_top = if bottom { height - box_height } else { 0 };
+    for row in out.chunks_exact_mut(width).skip(box_top).take(box_height) {
+        for pixel in &mut row[..box_width] {
+            *pixel = (*pixel >> 2) & 0x3F3F3F;
+        }
+    }
+
+    for (line_index, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            for (y, bits) in glyph(c).iter().enumerate() {
+                for x in 0..3 {
+                    if bits & (0b100 >> x) == 0 {
+                        continue;
+                    }
+                    let left = (column * GLYPH_WIDTH + 1 + x) * scale;
+                    let top = (line_index * GLYPH_HEIGHT + 1 + y) * scale;
+                    for py in top..(top + scale).min(box_height) {
+                        for px in left..(left + scale).min(box_width) {
+                            out[(box_top + py) * width + px] = color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The hex keypad over the bottom right of the screen, to click or touch in
+// place of the keyboard
+pub struct Keypad {
+    left: usize,
+    top: usize,
+    // size of a square button, the first row and column of which is a gap
+    button: usize,
+}
+
+impl Keypad {
+    // for a `width` x `height` buffer, half as tall
+    pub fn new(width: usize, height: usize) -> Self {
+        let button = (height / 8).max(GLYPH_HEIGHT + 1);
+        Self {
+            left: width.saturating_sub(button * 4),
+            top: height.saturating_sub(button * 4),
+            button,
+        }
+    }
+
+    // the key under a point of the buffer
+    pub fn key_at(&self, x: usize, y: usize) -> Option<u8> {
+        let column = x.checked_sub(self.left)? / self.button;
+        let row = y.checked_sub(self.top)? / self.button;
+        KEYPAD.get(row)?.get(column).copied()
+    }
+
+    // held keys are lit, the rest darkened so the screen shows through
+    pub fn draw(&self, out: &mut [u32], width: usize, held: [bool; 16], color: u32) {
+        let scale = (self.button / GLYPH_HEIGHT / 2).max(1);
+        for (row, keys) in KEYPAD.iter().enumerate() {
+            for (column, key) in keys.iter().enumerate() {
+                let left = self.left + column * self.button;
+                let top = self.top + row * self.button;
+                let lit = held[*key as usize];
+                let glyph = glyph(
+                    char::from_digit(*key as u32, 16)
+                        .unwrap()
+                        .to_ascii_uppercase(),
+                );
+                let glyph_left = left + (self.button - 3 * scale) / 2;
+                let glyph_top = top + (self.button - 5 * scale) / 2;
+                for y in top + 1..top + self.button {
+                    for x in left + 1..(left + self.button).min(width) {
+                        let Some(pixel) = out.get_mut(y * width + x) else {
+                            continue;
+                        };
+                        let (gx, gy) = (x.wrapping_sub(glyph_left), y.wrapping_sub(glyph_top));
+                        let inked = gx < 3 * scale
+                            && gy < 5 * scale
+                            && glyph[gy / scale] & (0b100 >> (gx / scale)) != 0;
+                        *pixel = match (lit, inked) {
+                            (true, true) => 0,
+                            (true, false) | (false, true) => color,
+                            (false, false) => (*pixel >> 2) & 0x3F3F3F,
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_overlay_text() {
+    use emuchip::quirks::Quirks;
+
+    let stats = Stats {
+        fps: 60,
+        ips: 700,
+        regs: std::array::from_fn(|reg| reg as u8 * 0x11),
+        index: 0x2A0,
+        pc: 0x200,
+        delay: 0x3C,
+        sound: 0,
+        keys: std::array::from_fn(|key| key == 0x5 || key == 0xF),
+        quirks: Quirks {
+            wrap_sprites: true,
+            display_wait: true,
+            ..Quirks::default()
+        },
+        depth: 1,
+        calls: vec![(0x204, Some(0x2300))],
+    };
+    // every character in there has a glyph
+    for c in stats.lines().concat().chars().filter(|c| *c != ' ') {
+        assert_ne!(glyph(c), [0; 5], "{c}");
+    }
+
+    let mut out = vec![0xFFFFFF; 128 * 64];
+    draw_stats(&stats, &mut out, 128, 64, 0xFF0000);
+    // the box is darkened, the F of FPS is drawn from its top left corner
+    assert_eq!(out[0], 0x3F3F3F);
+    assert_eq!(out[128 + 1], 0xFF0000);
+    assert_eq!(out[63 * 128 + 127], 0xFFFFFF);
+}
+
+#[test]
+fn test_keypad() {
+    // 8 pixel buttons in the bottom right 32x32 of the screen
+    let keypad = Keypad::new(128, 64);
+    assert_eq!(keypad.key_at(96, 32), Some(0x1));
+    assert_eq!(keypad.key_at(127, 63), Some(0xF));
+    assert_eq!(keypad.key_at(105, 58), Some(0x0));
+    assert_eq!(keypad.key_at(95, 63), None);
+    assert_eq!(keypad.key_at(127, 31), None);
+
+    let mut out = vec![0xFFFFFF; 128 * 64];
+    let mut held = [false; 16];
+    held[0xF] = true;
+    keypad.draw(&mut out, 128, held, 0xFF0000);
+    // the gap, a darkened button and a lit one
+    assert_eq!(out[32 * 128 + 96], 0xFFFFFF);
+    assert_eq!(out[33 * 128 + 97], 0x3F3F3F);
+    assert_eq!(out[57 * 128 + 121], 0xFF0000);
+    assert!(out[..32 * 128].iter().all(|pixel| *pixel == 0xFFFFFF));
+}
+
+#[test]
+fn test_message() {
+    let message = Message::new("State 2 saved");
+    assert!(!message.expired());
+    for c in "STATE 2 SAVED LOADED NO".chars().filter(|c| *c != ' ') {
+        assert_ne!(glyph(c), [0; 5], "{c}");
+    }
+    assert_eq!(glyph('s'), glyph('S'));
+
+    let mut out = vec![0xFFFFFF; 128 * 64];
+    message.draw(&mut out, 128, 64, 0xFF0000);
+    // a 7 pixel tall box along the bottom, the S drawn from its top left
+    assert_eq!(out[56 * 128], 0xFFFFFF);
+    assert_eq!(out[57 * 128], 0x3F3F3F);
+    assert_eq!(out[58 * 128 + 2], 0xFF0000);
+    assert!(out[..57 * 128].iter().all(|pixel| *pixel == 0xFFFFFF));
+}
+
+#[test]
+fn test_menu() {
+    let mut menu = Menu::default();
+    assert_eq!(menu.selected(), MenuItem::Resume);
+    menu.up();
+    assert_eq!(menu.selected(), MenuItem::Quit);
+    menu.down();
+    menu.down();
+    assert_eq!(menu.selected(), MenuItem::Reset);
+
+    let lines = menu.lines("schip", "amber");
+    assert_eq!(lines[1], "> Reset");
+    assert_eq!(lines[3], "  Quirks schip");
+    assert_eq!(lines[4], "  Palette amber");
+    for c in lines.concat().chars().filter(|c| *c != ' ') {
+        assert_ne!(glyph(c), [0; 5], "{c}");
+    }
+
+    let mut out = vec![0xFFFFFF; 128 * 64];
+    draw_menu(&lines, &mut out, 128, 64, 0xFF0000);
+    // six lines down the left, the > of Reset at the start of the second
+    assert_eq!(out[0], 0x3F3F3F);
+    assert_eq!(out[7 * 128 + 1], 0xFF0000);
+    assert_eq!(out[40 * 128], 0xFFFFFF);
+}
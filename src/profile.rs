@@ -0,0 +1,132 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    decode::OpCodes,
+    memory::{TypeAddr, MEMORY_SIZE},
+};
+
+// how many lines each part of the report gets
+const TOP: usize = 10;
+
+// Counts what a rom spends its instructions on, for finding what to optimize
+pub struct Profiler {
+    // executions per address, with what was last executed there
+    hits: Vec<(u64, OpCodes)>,
+    // backward jumps taken, keyed by (target, jump)
+    loops: BTreeMap<(TypeAddr, TypeAddr), u64>,
+    // instructions spent blocked in LD VX, K
+    waiting: u64,
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            hits: vec![(0, OpCodes::Unimplemented); MEMORY_SIZE],
+            loops: BTreeMap::new(),
+            waiting: 0,
+            total: 0,
+        }
+    }
+
+    // `next` is where the program counter went after running `op` at `pc`
+    pub fn record(&mut self, pc: TypeAddr, op: OpCodes, next: TypeAddr) {
+        self.total += 1;
+        let hit = &mut self.hits[pc as usize % MEMORY_SIZE];
+        *hit = (hit.0 + 1, op);
+        match op {
+            OpCodes::GetKey(_) if next == pc => self.waiting += 1,
+            OpCodes::Jump(_) | OpCodes::JumpWithOffset(_) if next <= pc => {
+                *self.loops.entry((next, pc)).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+        let mut out = format!("instructions: {}\n", self.total);
+        out += &format!(
+            "waiting for a key: {} ({:.1}%)\n",
+            self.waiting,
+            percent(self.waiting)
+        );
+
+        let mut hottest: Vec<(usize, &(u64, OpCodes))> = self
+            .hits
+            .iter()
+            .enumerate()
+            .filter(|(_, (count, _))| *count > 0)
+            .collect();
+        hottest.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(&b.0)));
+        out += "\nhottest instructions\n";
+        for (addr, (count, op)) in hottest.iter().take(TOP) {
+            out += &format!(
+                "  {addr:03X}  {:<18} {count:>10} {:>5.1}%\n",
+                op.to_string(),
+                percent(*count)
+            );
+        }
+
+        let mut loops: Vec<_> = self.loops.iter().collect();
+        loops.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        out += "\nhottest loops\n";
+        for ((start, end), count) in loops.iter().take(TOP) {
+            out += &format!("  {start:03X}-{end:03X}  {count:>10} times\n");
+        }
+
+        // by instruction kind, the name of the variant without its operands
+        let mut ops: BTreeMap<String, u64> = BTreeMap::new();
+        for (count, op) in hottest.iter().map(|(_, hit)| hit) {
+            let name = format!("{op:?}");
+            let name = name.split('(').next().unwrap_or_default();
+            *ops.entry(name.to_string()).or_default() += count;
+        }
+        let mut ops: Vec<_> = ops.into_iter().collect();
+        ops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        out += "\nmost used instructions\n";
+        for (name, count) in ops.iter().take(TOP) {
+            out += &format!("  {name:<24} {count:>10} {:>5.1}%\n", percent(*count));
+        }
+        out
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_profile_report() {
+    let mut profiler = Profiler::new();
+    // a loop at 200-204 run three times, then waiting on a key at 206
+    for _ in 0..3 {
+        profiler.record(0x200, OpCodes::AddToRegister(0, 1), 0x202);
+        profiler.record(0x202, OpCodes::Display(0, 0, 5), 0x204);
+        profiler.record(0x204, OpCodes::Jump(0x200), 0x200);
+    }
+    profiler.record(0x206, OpCodes::GetKey(0), 0x206);
+    profiler.record(0x206, OpCodes::GetKey(0), 0x208);
+
+    // columns are padded, compare words
+    let report = profiler.report();
+    let lines: Vec<String> = report
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        lines[..2],
+        ["instructions: 11", "waiting for a key: 1 (9.1%)"]
+    );
+    assert!(lines.contains(&"206 LD V0, K 2 18.2%".to_string()));
+    assert!(lines.contains(&"200-204 3 times".to_string()));
+    assert!(lines.contains(&"AddToRegister 3 27.3%".to_string()));
+}
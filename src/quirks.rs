@@ -0,0 +1,253 @@
+use alloc::{format, string::String};
+use core::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::memory::{TypeAddr, PROGRAM_START};
+
+// Behaviors that differ between CHIP-8 interpreters over the years.
+// Defaults match how emuchip has always behaved.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quirks {
+    // 8XY6/8XYE: copy VY into VX before shifting
+    pub shift_uses_vy: bool,
+    // FX55/FX65: leave I pointing past the last register touched
+    pub memory_increments_i: bool,
+    // 8XY1/8XY2/8XY3: reset VF to 0 afterwards
+    pub vf_reset: bool,
+    // BNNN: jump to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // DXYN: wrap sprites around the edges instead of clipping them
+    pub wrap_sprites: bool,
+    // DXYN: wait for the next display refresh before drawing
+    pub display_wait: bool,
+    // FX1E: set VF when I goes past 0xFFF, like the Amiga interpreter
+    pub index_overflow: bool,
+    // FX0A: take the key once it's released instead of when it's pressed
+    pub key_release: bool,
+    // 2NNN: nested calls allowed before the stack overflows
+    pub stack_depth: u8,
+    // what reading or writing past 0xFFF does
+    pub out_of_bounds: OutOfBounds,
+    // where the rom is loaded and starts running
+    pub load_addr: TypeAddr,
+}
+
+impl Quirks {
+    pub fn from_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Cosmac => Self {
+                shift_uses_vy: true,
+                memory_increments_i: true,
+                vf_reset: true,
+                jump_uses_vx: false,
+                wrap_sprites: false,
+                display_wait: true,
+                index_overflow: false,
+                key_release: true,
+                stack_depth: 12,
+                out_of_bounds: OutOfBounds::Wrap,
+                load_addr: PROGRAM_START,
+            },
+            // a VIP-like interpreter with programs loaded higher up
+            Platform::Eti660 => Self {
+                load_addr: 0x600,
+                ..Self::from_platform(Platform::Cosmac)
+            },
+            Platform::Chip48 | Platform::Schip => Self {
+                shift_uses_vy: false,
+                memory_increments_i: false,
+                vf_reset: false,
+                jump_uses_vx: true,
+                wrap_sprites: false,
+                display_wait: false,
+                index_overflow: false,
+                key_release: false,
+                stack_depth: Self::STACK_DEPTH,
+                out_of_bounds: OutOfBounds::Error,
+                load_addr: PROGRAM_START,
+            },
+            Platform::XoChip => Self {
+                shift_uses_vy: true,
+                memory_increments_i: true,
+                vf_reset: false,
+                jump_uses_vx: false,
+                wrap_sprites: true,
+                display_wait: false,
+                index_overflow: false,
+                key_release: false,
+                stack_depth: Self::STACK_DEPTH,
+                out_of_bounds: OutOfBounds::Error,
+                load_addr: PROGRAM_START,
+            },
+        }
+    }
+
+    // the deepest stack the original interpreters had room for
+    pub const STACK_DEPTH: u8 = 16;
+
+    // the quirks that are either on or off, by their command line names
+    pub const NAMES: [&'static str; 8] = [
+        "shift",
+        "memory",
+        "vf-reset",
+        "jump",
+        "wrap",
+        "display-wait",
+        "index-overflow",
+        "key-release",
+    ];
+
+    // toggle a single quirk by its command line name
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        *self.quirk_mut(name)? = enabled;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<bool, String> {
+        let mut quirks = *self;
+        quirks.quirk_mut(name).map(|quirk| *quirk)
+    }
+
+    // flip a single quirk, giving back whether it's on now
+    pub fn toggle(&mut self, name: &str) -> Result<bool, String> {
+        let quirk = self.quirk_mut(name)?;
+        *quirk = !*quirk;
+        Ok(*quirk)
+    }
+
+    fn quirk_mut(&mut self, name: &str) -> Result<&mut bool, String> {
+        let quirk = match name {
+            "shift" => &mut self.shift_uses_vy,
+            "memory" => &mut self.memory_increments_i,
+            "vf-reset" => &mut self.vf_reset,
+            "jump" => &mut self.jump_uses_vx,
+            "wrap" => &mut self.wrap_sprites,
+            "display-wait" => &mut self.display_wait,
+            "index-overflow" => &mut self.index_overflow,
+            "key-release" => &mut self.key_release,
+            _ => return Err(format!("unknown quirk '{name}'")),
+        };
+        Ok(quirk)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            memory_increments_i: false,
+            vf_reset: false,
+            jump_uses_vx: false,
+            wrap_sprites: false,
+            display_wait: false,
+            index_overflow: false,
+            key_release: false,
+            stack_depth: Self::STACK_DEPTH,
+            out_of_bounds: OutOfBounds::Error,
+            load_addr: PROGRAM_START,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutOfBounds {
+    // drop the address down to 12 bits, like the VIP's 4K of memory
+    Wrap,
+    // use the last byte of memory instead
+    Clamp,
+    // stop with an emulator error
+    Error,
+}
+
+impl FromStr for OutOfBounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Self::Wrap),
+            "clamp" => Ok(Self::Clamp),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("unknown out of bounds behavior '{s}'")),
+        }
+    }
+}
+
+// What 0NNN does when no handler takes the routine, kept out of Quirks since
+// no interpreter other than the VIP's could run them
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MachineRoutines {
+    // carry on with the next instruction
+    Skip,
+    // stop with an emulator error
+    #[default]
+    Halt,
+}
+
+impl FromStr for MachineRoutines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "halt" => Ok(Self::Halt),
+            _ => Err(format!("unknown machine routine behavior '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    Cosmac,
+    Eti660,
+    Chip48,
+    Schip,
+    XoChip,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cosmac" => Ok(Self::Cosmac),
+            "eti660" => Ok(Self::Eti660),
+            "chip48" => Ok(Self::Chip48),
+            "schip" => Ok(Self::Schip),
+            "xochip" => Ok(Self::XoChip),
+            _ => Err(format!("unknown platform '{s}'")),
+        }
+    }
+}
+
+// the name --platform takes
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cosmac => "cosmac",
+            Self::Eti660 => "eti660",
+            Self::Chip48 => "chip48",
+            Self::Schip => "schip",
+            Self::XoChip => "xochip",
+        })
+    }
+}
+
+#[test]
+fn test_toggle_quirks() {
+    let mut quirks = Quirks::default();
+    assert_eq!(quirks.toggle("wrap"), Ok(true));
+    assert!(quirks.wrap_sprites);
+    assert_eq!(quirks.toggle("wrap"), Ok(false));
+    assert!(quirks.toggle("nope").is_err());
+
+    let cosmac = Quirks::from_platform(Platform::Cosmac);
+    let on: alloc::vec::Vec<_> = Quirks::NAMES
+        .into_iter()
+        .filter(|name| cosmac.get(name).unwrap())
+        .collect();
+    assert_eq!(
+        on,
+        ["shift", "memory", "vf-reset", "display-wait", "key-release"]
+    );
+}
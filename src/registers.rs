@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::memory::TypeAddr;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Registers {
     registers: [u8; 16],
 }
@@ -30,7 +33,7 @@ impl Registers {
 }
 
 // Special registers
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgramCounter(pub TypeAddr, pub TypeAddr);
 
 impl ProgramCounter {
@@ -51,6 +54,7 @@ impl ProgramCounter {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct IndexRegister(pub TypeAddr);
 
 impl IndexRegister {
@@ -58,3 +62,9 @@ impl IndexRegister {
         self.0 = addr;
     }
 }
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use emuchip::{debugger::Debugger, display::FrameBuffer, Chip8};
+
+// appended to the client's key before hashing, from RFC 6455
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+
+// a command line is tiny, anything bigger isn't a client of ours
+const MAX_MESSAGE: u64 = 4096;
+
+const HELP: &str = "press K       hold down keypad key K (hex) until released
+release K     let go of keypad key K
+anything else goes to the debugger, see its help";
+
+// A command line from a client, answered on its connection
+struct Request {
+    line: String,
+    client: TcpStream,
+}
+
+struct Client {
+    stream: TcpStream,
+    // hasn't had a screen yet
+    fresh: bool,
+}
+
+// WebSocket server for dashboards and external debuggers. Every client gets
+// the screen as a binary message whenever it changes, width and height bytes
+// followed by a byte per pixel, and can send text messages of debugger console
+// commands, plus press/release for the keypad, each answered with a text
+// message of the output
+pub struct Remote {
+    requests: Receiver<Request>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    // the screen as last sent
+    screen: Vec<u8>,
+    // keypad keys held down by clients
+    held: [bool; 16],
+}
+
+impl Remote {
+    pub fn listen(addr: &str) -> Result<Self, String> {
+        let listener =
+            TcpListener::bind(addr).map_err(|err| format!("could not listen on {addr}: {err}"))?;
+        let clients = Arc::new(Mutex::new(vec![]));
+        let (sender, requests) = mpsc::channel();
+
+        let joined = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (joined, sender) = (Arc::clone(&joined), sender.clone());
+                // the handshake waits on the client, don't hold up the others
+                thread::spawn(move || {
+                    if let Ok(stream) = handshake(stream) {
+                        serve(stream, &joined, &sender);
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            requests,
+            clients,
+            screen: vec![],
+            held: [false; 16],
+        })
+    }
+
+    pub fn held(&self) -> [bool; 16] {
+        self.held
+    }
+
+    // once a frame, runs what clients asked for since the last one
+    pub fn poll(&mut self, debugger: &mut Debugger, chip8: &mut Chip8) {
+        while let Ok(mut request) = self.requests.try_recv() {
+            let reply = match self.command(debugger, chip8, &request.line) {
+                Ok(output) => output,
+                Err(err) => format!("error: {err}"),
+            };
+            // a client that went away is dropped on the next screen
+            let _ = write_message(&mut request.client, TEXT, reply.as_bytes());
+        }
+    }
+
+    fn command(
+        &mut self,
+        debugger: &mut Debugger,
+        chip8: &mut Chip8,
+        line: &str,
+    ) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let pressed = match words.next() {
+            Some("press") => true,
+            Some("release") => false,
+            Some("h" | "help") => {
+                return Ok(format!("{HELP}\n\n{}", debugger.command(chip8, line)?))
+            }
+            // clients don't get to write files here
+            Some("dump") if words.next().is_some() => {
+                return Err("the dump comes back as the reply, leave out the file".to_string())
+            }
+            _ => return debugger.command(chip8, line),
+        };
+        let key = words
+            .next()
+            .and_then(|key| u8::from_str_radix(key, 16).ok())
+            .filter(|key| *key < 16)
+            .ok_or("key must be 0-F")?;
+        self.held[key as usize] = pressed;
+        let state = if pressed { "pressed" } else { "released" };
+        Ok(format!("{state} {key:X}"))
+    }
+
+    // sends the screen to clients that haven't seen it
+    pub fn send_screen(&mut self, fb: &FrameBuffer) {
+        let mut screen = vec![fb.width() as u8, fb.height() as u8];
+        screen.extend(fb.pixels().map(u8::from));
+        let changed = screen != self.screen;
+        self.screen = screen;
+
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| {
+            if !changed && !client.fresh {
+                return true;
+            }
+            client.fresh = false;
+            write_message(&mut client.stream, BINARY, &self.screen).is_ok()
+        });
+    }
+}
+
+// answers the HTTP upgrade request a WebSocket connection starts with
+fn handshake(stream: TcpStream) -> io::Result<TcpStream> {
+    let mut reader = BufReader::new(stream);
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let mut stream = reader.into_inner();
+    let Some(key) = key else {
+        stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")?;
+        return Err(io::ErrorKind::InvalidData.into());
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    Ok(stream)
+}
+
+// reads a client's commands until it hangs up
+fn serve(stream: TcpStream, clients: &Mutex<Vec<Client>>, sender: &Sender<Request>) {
+    let Ok(mut reader) = stream.try_clone() else {
+        return;
+    };
+    if let Ok(writer) = stream.try_clone() {
+        clients.lock().unwrap().push(Client {
+            stream: writer,
+            fresh: true,
+        });
+    }
+    while let Ok((opcode, payload)) = read_message(&mut reader) {
+        match opcode {
+            TEXT => {
+                let Ok(client) = stream.try_clone() else {
+                    return;
+                };
+                let line = String::from_utf8_lossy(&payload).into_owned();
+                if sender.send(Request { line, client }).is_err() {
+                    return;
+                }
+            }
+            CLOSE => return,
+            // pings and anything binary aren't needed
+            _ => {}
+        }
+    }
+}
+
+fn accept_key(key: &str) -> String {
+    let digest = sha1_smol::Sha1::from(format!("{key}{WEBSOCKET_GUID}")).digest();
+    base64(&digest.bytes())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// one whole message from a client, whose frames are always masked;
+// fragmented messages aren't supported
+fn read_message(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+// servers send unmasked frames
+fn write_message(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    stream.write_all(&frame)
+}
+
+#[test]
+fn test_remote_commands() {
+    // the example from RFC 6455
+    assert_eq!(
+        accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+
+    let mut remote = Remote::listen("127.0.0.1:0").unwrap();
+    let mut debugger = Debugger::new();
+    let mut chip8 = Chip8::new(Default::default());
+    chip8.load_rom(&[0x60, 0x2A]).unwrap();
+    let mut run = |line: &str| {
+        remote
+            .command(&mut debugger, &mut chip8, line)
+            .unwrap_or_else(|err| err)
+    };
+    assert_eq!(run("press a"), "pressed A");
+    assert_eq!(run("press 10"), "key must be 0-F");
+    assert!(run("step").starts_with("202"));
+    assert!(run("regs").contains("V0=2A"));
+    assert!(run("dump").contains("\"v\":[42,"));
+    assert!(run("dump /tmp/x.json").starts_with("the dump comes back"));
+    assert!(remote.held()[0xA]);
+
+    // a masked client frame
+    let mut frame = vec![0x81, 0x80 | 4, 1, 2, 3, 4];
+    frame.extend(
+        b"regs"
+            .iter()
+            .zip([1, 2, 3, 4])
+            .map(|(byte, mask)| byte ^ mask),
+    );
+    assert_eq!(
+        read_message(&mut frame.as_slice()).unwrap(),
+        (TEXT, b"regs".to_vec())
+    );
+    let mut out = vec![];
+    write_message(&mut out, TEXT, b"ok").unwrap();
+    assert_eq!(out, [0x81, 2, b'o', b'k']);
+}
@@ -0,0 +1,157 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{keyboard::Keyboard, quirks::Quirks, Chip8};
+
+// replay files start with these, followed by a format version byte
+const MAGIC: &[u8; 4] = b"EMCR";
+const VERSION: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    // frames since the start of the run
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+// Everything needed to reproduce a run of a rom exactly
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    // instructions per frame
+    pub cycles: u32,
+    pub quirks: Quirks,
+    pub events: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend(bincode::serialize(self).expect("replay is always serializable"));
+        fs::write(path, bytes).map_err(|err| format!("could not write {path}: {err}"))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        match bytes.as_slice() {
+            [m0, m1, m2, m3, version, data @ ..] if [*m0, *m1, *m2, *m3] == *MAGIC => {
+                if *version != VERSION {
+                    return Err(format!("unsupported replay version {version}"));
+                }
+                bincode::deserialize(data).map_err(|err| format!("corrupt replay: {err}"))
+            }
+            _ => Err(format!("{path} is not an emuchip replay")),
+        }
+    }
+}
+
+// Captures keypad changes at frame boundaries
+pub struct Recorder {
+    replay: Replay,
+    keys: [bool; 16],
+}
+
+impl Recorder {
+    pub fn new(chip8: &Chip8, cycles: u32) -> Self {
+        Self {
+            replay: Replay {
+                seed: chip8.seed(),
+                cycles,
+                quirks: chip8.quirks,
+                events: vec![],
+            },
+            keys: [false; 16],
+        }
+    }
+
+    // call once per frame after the keypad has been updated
+    pub fn capture(&mut self, frame: u64, keyboard: &Keyboard) {
+        for key in 0..16 {
+            let pressed = keyboard.get_key_status_from_num(key);
+            if pressed != self.keys[key as usize] {
+                self.keys[key as usize] = pressed;
+                self.replay.events.push(InputEvent {
+                    frame,
+                    key,
+                    pressed,
+                });
+            }
+        }
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+// Feeds recorded keypad changes back in at the frames they happened
+pub struct Player {
+    events: Vec<InputEvent>,
+    next: usize,
+}
+
+impl Player {
+    // sets up chip8 the way the recording started
+    pub fn new(replay: Replay, chip8: &mut Chip8) -> Self {
+        chip8.set_seed(replay.seed);
+        chip8.quirks = replay.quirks;
+        Self {
+            events: replay.events,
+            next: 0,
+        }
+    }
+
+    // call once per frame in place of reading real input
+    pub fn apply(&mut self, frame: u64, chip8: &mut Chip8) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+            chip8.set_key(event.key, event.pressed);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
+
+#[test]
+fn test_replay_reproduces_run() {
+    // wait for a key, V1 = random & key, loop
+    let rom = [0xF0, 0x0A, 0xC1, 0xFF, 0x81, 0x02, 0x12, 0x00];
+    let run = |chip8: &mut Chip8, player: &mut Option<Player>, recorder: &mut Option<Recorder>| {
+        for frame in 0..10 {
+            if let Some(player) = player {
+                player.apply(frame, chip8);
+            } else {
+                chip8.set_key(0x7, (3..6).contains(&frame));
+            }
+            if let Some(recorder) = recorder {
+                recorder.capture(frame, &chip8.keyboard);
+            }
+            for _ in 0..8 {
+                chip8.step().unwrap();
+            }
+            chip8.sync();
+        }
+        chip8.regs.get(1)
+    };
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    let mut recorder = Some(Recorder::new(&chip8, 8));
+    let recorded = run(&mut chip8, &mut None, &mut recorder);
+    let replay = recorder.unwrap().finish();
+    assert_eq!(replay.events.len(), 2);
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_rom(&rom).unwrap();
+    let mut player = Some(Player::new(replay, &mut chip8));
+    assert_eq!(run(&mut chip8, &mut player, &mut None), recorded);
+    assert!(player.unwrap().is_finished());
+}
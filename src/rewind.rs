@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+use crate::snapshot::Snapshot;
+
+// Ring buffer of recent snapshots, oldest ones are dropped once full
+pub struct Rewind {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    // most recent snapshot, walking further back with every call
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        self.snapshots.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[test]
+fn test_rewind_drops_oldest() {
+    use crate::{quirks::Quirks, Chip8};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    let mut rewind = Rewind::new(2);
+    for value in 1..=3 {
+        chip8.regs.set_register(0, value);
+        rewind.push(chip8.snapshot());
+    }
+
+    assert_eq!(rewind.len(), 2);
+    assert_eq!(rewind.pop().unwrap().regs.get(0), 3);
+    assert_eq!(rewind.pop().unwrap().regs.get(0), 2);
+    assert!(rewind.pop().is_none());
+}
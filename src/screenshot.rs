@@ -0,0 +1,106 @@
+use std::fs;
+
+use crate::display::{FrameBuffer, HEIGHT, WIDTH};
+
+// Every pixel of the screen blown up to (WIDTH * scale) x (HEIGHT * scale), row
+// by row, high resolution pixels are half the size so the image is the same either
+// way, and 64x64 hi-res CHIP-8 pixels half as tall as they are wide
+pub(crate) fn upscale(fb: &FrameBuffer, scale: usize) -> (usize, usize, Vec<bool>) {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+    let (pixel_width, pixel_height) = (width / fb.width(), height / fb.height());
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| fb.get(x / pixel_width, y / pixel_height)))
+        .collect();
+    (width, height, pixels)
+}
+
+pub(crate) fn rgb(color: u32) -> [u8; 3] {
+    let [_, r, g, b] = color.to_be_bytes();
+    [r, g, b]
+}
+
+// the upscaled screen as RGB bytes
+fn rgb_image(
+    fb: &FrameBuffer,
+    scale: usize,
+    (foreground, background): (u32, u32),
+) -> (usize, usize, Vec<u8>) {
+    let (width, height, pixels) = upscale(fb, scale);
+    let (on, off) = (rgb(foreground), rgb(background));
+    let data = pixels
+        .iter()
+        .flat_map(|lit| if *lit { on } else { off })
+        .collect();
+    (width, height, data)
+}
+
+// Renders the screen to a PNG, `scale` is the size of a low resolution pixel
+// and the palette is foreground and background as 0xRRGGBB
+pub fn encode_png(fb: &FrameBuffer, scale: usize, palette: (u32, u32)) -> Vec<u8> {
+    let (width, height, data) = rgb_image(fb, scale, palette);
+    let mut bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .expect("writing to memory can't fail");
+    bytes
+}
+
+// The same as a binary PPM, which anything can read without a decoder
+pub fn encode_ppm(fb: &FrameBuffer, scale: usize, palette: (u32, u32)) -> Vec<u8> {
+    let (width, height, data) = rgb_image(fb, scale, palette);
+    let mut bytes = format!("P6\n{width} {height}\n255\n").into_bytes();
+    bytes.extend(data);
+    bytes
+}
+
+pub fn save_png(
+    fb: &FrameBuffer,
+    scale: usize,
+    palette: (u32, u32),
+    path: &str,
+) -> Result<(), String> {
+    fs::write(path, encode_png(fb, scale, palette))
+        .map_err(|err| format!("could not write {path}: {err}"))
+}
+
+pub fn save_ppm(
+    fb: &FrameBuffer,
+    scale: usize,
+    palette: (u32, u32),
+    path: &str,
+) -> Result<(), String> {
+    fs::write(path, encode_ppm(fb, scale, palette))
+        .map_err(|err| format!("could not write {path}: {err}"))
+}
+
+#[test]
+fn test_encode_png() {
+    let mut fb = FrameBuffer::new();
+    // top left pixel only
+    fb.paint(0, 0, vec![0x80], false);
+    let bytes = encode_png(&fb, 2, (0xFFFFFF, 0x000000));
+
+    let decoder = png::Decoder::new(bytes.as_slice());
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!((info.width, info.height), (128, 64));
+    // the lit pixel is 2x2, its neighbours are dark
+    let at = |x: usize, y: usize| data[(y * 128 + x) * 3];
+    assert_eq!((at(0, 0), at(1, 1), at(2, 0), at(0, 2)), (0xFF, 0xFF, 0, 0));
+}
+
+#[test]
+fn test_encode_ppm() {
+    let mut fb = FrameBuffer::new();
+    fb.paint(0, 0, vec![0x80], false);
+    let bytes = encode_ppm(&fb, 1, (0x123456, 0x000000));
+    let header = b"P6\n64 32\n255\n";
+    assert_eq!(&bytes[..header.len()], header);
+    assert_eq!(bytes.len(), header.len() + 64 * 32 * 3);
+    assert_eq!(bytes[header.len()..][..6], [0x12, 0x34, 0x56, 0, 0, 0]);
+}
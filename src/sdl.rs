@@ -0,0 +1,360 @@
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    event::Event,
+    keyboard::{Mod, Scancode},
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    video::FullscreenType,
+    EventPump, Sdl,
+};
+
+use emuchip::{
+    audio::AudioPattern,
+    display::{FrameBuffer, HIRES_HEIGHT, HIRES_WIDTH},
+    host::{AudioSink, DisplaySink, Hotkey, InputSource},
+    keyboard::Keyboard,
+};
+
+use crate::{
+    frontend::{HostKey, Keymap, DEFAULT_PALETTE, QUIRK_KEYS, TITLE},
+    sound::{find_device, Tone, Voice},
+};
+
+// by physical key position
+const LETTERS: [Scancode; 26] = [
+    Scancode::A,
+    Scancode::B,
+    Scancode::C,
+    Scancode::D,
+    Scancode::E,
+    Scancode::F,
+    Scancode::G,
+    Scancode::H,
+    Scancode::I,
+    Scancode::J,
+    Scancode::K,
+    Scancode::L,
+    Scancode::M,
+    Scancode::N,
+    Scancode::O,
+    Scancode::P,
+    Scancode::Q,
+    Scancode::R,
+    Scancode::S,
+    Scancode::T,
+    Scancode::U,
+    Scancode::V,
+    Scancode::W,
+    Scancode::X,
+    Scancode::Y,
+    Scancode::Z,
+];
+
+const DIGITS: [Scancode; 10] = [
+    Scancode::Num0,
+    Scancode::Num1,
+    Scancode::Num2,
+    Scancode::Num3,
+    Scancode::Num4,
+    Scancode::Num5,
+    Scancode::Num6,
+    Scancode::Num7,
+    Scancode::Num8,
+    Scancode::Num9,
+];
+
+fn host_key(key: HostKey) -> Scancode {
+    match key {
+        HostKey::Letter(i) => LETTERS[i as usize],
+        HostKey::Digit(i) => DIGITS[i as usize],
+        HostKey::Space => Scancode::Space,
+        HostKey::Enter => Scancode::Return,
+        HostKey::Up => Scancode::Up,
+        HostKey::Down => Scancode::Down,
+        HostKey::Left => Scancode::Left,
+        HostKey::Right => Scancode::Right,
+        HostKey::Comma => Scancode::Comma,
+        HostKey::Period => Scancode::Period,
+        HostKey::Apostrophe => Scancode::Apostrophe,
+        HostKey::Semicolon => Scancode::Semicolon,
+    }
+}
+
+// played for as long as the device is open
+impl AudioCallback for Voice {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+pub struct SdlWindow {
+    // every other sdl handle is only valid while the context lives
+    _context: Sdl,
+    canvas: Canvas<sdl2::video::Window>,
+    events: EventPump,
+    // no sound rather than no emulator when there's no audio device
+    audio: Option<AudioDevice<Voice>>,
+    foreground: Color,
+    background: Color,
+    keys: Vec<(Scancode, u8)>,
+    hotkeys: Vec<Hotkey>,
+    open: bool,
+    beeping: bool,
+    // XO-CHIP pattern to play in place of the tone
+    pattern: Option<AudioPattern>,
+}
+
+impl SdlWindow {
+    // scale is the on screen size of a low resolution pixel
+    pub fn new(
+        scale: usize,
+        palette: Option<(u32, u32)>,
+        keymap: &Keymap,
+        tone: Tone,
+        audio_device: Option<&str>,
+        fullscreen: bool,
+    ) -> Result<Self, String> {
+        let context = sdl2::init()?;
+        let mut window = context.video()?.window(
+            TITLE,
+            (HIRES_WIDTH * scale / 2) as u32,
+            (HIRES_HEIGHT * scale / 2) as u32,
+        );
+        window.position_centered().resizable();
+        if fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().map_err(|err| err.to_string())?;
+        let mut canvas = window
+            .into_canvas()
+            .accelerated()
+            .build()
+            .map_err(|err| err.to_string())?;
+        // draw in SCHIP pixels, sdl scales them up to the window
+        canvas
+            .set_logical_size(HIRES_WIDTH as u32, HIRES_HEIGHT as u32)
+            .map_err(|err| err.to_string())?;
+        canvas.set_integer_scale(true)?;
+
+        let audio = context.audio().and_then(|audio| {
+            let spec = AudioSpecDesired {
+                freq: Some(44100),
+                channels: Some(1),
+                samples: None,
+            };
+            // sdl opens devices by name, looked up the same way as for cpal
+            let device = match audio_device {
+                Some(wanted) => {
+                    let names: Vec<String> = (0..audio.num_audio_playback_devices().unwrap_or(0))
+                        .map(|index| audio.audio_playback_device_name(index).unwrap_or_default())
+                        .collect();
+                    let index = find_device(&names, wanted)
+                        .ok_or_else(|| format!("no output device '{wanted}'"))?;
+                    Some(names[index].clone())
+                }
+                None => None,
+            };
+            audio.open_playback(device.as_deref(), &spec, |spec| {
+                Voice::new(tone, spec.freq as u32)
+            })
+        });
+        // plays silence between beeps so the tone fades in and out
+        let audio = match audio {
+            Ok(device) => {
+                device.resume();
+                Some(device)
+            }
+            Err(err) => {
+                eprintln!("no sound: {err}");
+                None
+            }
+        };
+
+        let (foreground, background) = palette.unwrap_or(DEFAULT_PALETTE);
+        Ok(Self {
+            events: context.event_pump()?,
+            _context: context,
+            canvas,
+            audio,
+            foreground: Self::color(foreground),
+            background: Self::color(background),
+            keys: keymap.keys().map(|(key, n)| (host_key(key), n)).collect(),
+            hotkeys: vec![],
+            open: true,
+            beeping: false,
+            pattern: None,
+        })
+    }
+
+    fn color(rgb: u32) -> Color {
+        Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    fn poll_events(&mut self) {
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. } => self.open = false,
+                Event::KeyDown {
+                    scancode: Some(Scancode::Equals | Scancode::KpPlus),
+                    ..
+                } => self.hotkeys.push(Hotkey::SpeedUp),
+                Event::KeyDown {
+                    scancode: Some(Scancode::Minus | Scancode::KpMinus),
+                    ..
+                } => self.hotkeys.push(Hotkey::SpeedDown),
+                Event::KeyDown {
+                    scancode: Some(Scancode::N),
+                    ..
+                } => self.hotkeys.push(Hotkey::FrameAdvance),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                    let alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    match scancode {
+                        Scancode::Escape => self.hotkeys.push(Hotkey::Menu),
+                        Scancode::Up => self.hotkeys.push(Hotkey::MenuUp),
+                        Scancode::Down => self.hotkeys.push(Hotkey::MenuDown),
+                        Scancode::Return => self.hotkeys.push(Hotkey::MenuPick),
+                        Scancode::P => self.hotkeys.push(Hotkey::TogglePause),
+                        Scancode::M => self.hotkeys.push(Hotkey::ToggleMute),
+                        Scancode::R if ctrl => self.hotkeys.push(Hotkey::Reset),
+                        Scancode::O if ctrl => self.hotkeys.push(Hotkey::OpenRom),
+                        Scancode::F12 => self.hotkeys.push(Hotkey::Screenshot),
+                        Scancode::F10 => self.hotkeys.push(Hotkey::ToggleGif),
+                        Scancode::F8 => self.hotkeys.push(Hotkey::SaveProfile),
+                        Scancode::F6 => self.hotkeys.push(Hotkey::ToggleSlowMotion),
+                        // the logical size letterboxes the screen
+                        Scancode::F11 => {
+                            let window = self.canvas.window_mut();
+                            let next = match window.fullscreen_state() {
+                                FullscreenType::Off => FullscreenType::Desktop,
+                                _ => FullscreenType::Off,
+                            };
+                            if let Err(err) = window.set_fullscreen(next) {
+                                eprintln!("could not switch fullscreen: {err}");
+                            }
+                        }
+                        _ => {}
+                    }
+                    let slots = [Scancode::F1, Scancode::F2, Scancode::F3, Scancode::F4];
+                    if let Some(i) = slots.iter().position(|key| *key == scancode) {
+                        let slot = i as u8 + 1;
+                        if shift {
+                            self.hotkeys.push(Hotkey::LoadState(slot));
+                        } else {
+                            self.hotkeys.push(Hotkey::SaveState(slot));
+                        }
+                    }
+                    let Some(i) = DIGITS[1..].iter().position(|key| *key == scancode) else {
+                        continue;
+                    };
+                    let slot = i as u8 + 1;
+                    if ctrl {
+                        self.hotkeys.push(Hotkey::SaveState(slot));
+                    } else if alt {
+                        self.hotkeys.push(Hotkey::LoadState(slot));
+                    } else if let Some(quirk) = QUIRK_KEYS.get(i).filter(|_| shift) {
+                        self.hotkeys.push(Hotkey::ToggleQuirk(quirk));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(&mut self, fb: &FrameBuffer) -> Result<(), String> {
+        // hi-res CHIP-8 pixels are half as tall as they are wide
+        let scale = (HIRES_WIDTH / fb.width()) as u32;
+        let scale_y = (HIRES_HEIGHT / fb.height()) as u32;
+        let lit: Vec<Rect> = fb
+            .pixels()
+            .enumerate()
+            .filter(|(_, lit)| *lit)
+            .map(|(index, _)| {
+                let (x, y) = ((index % fb.width()) as u32, (index / fb.width()) as u32);
+                Rect::new((x * scale) as i32, (y * scale_y) as i32, scale, scale_y)
+            })
+            .collect();
+
+        self.canvas.set_draw_color(self.background);
+        self.canvas.clear();
+        self.canvas.set_draw_color(self.foreground);
+        self.canvas.fill_rects(&lit)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+impl InputSource for SdlWindow {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update_keys(&mut self, keyboard: &mut Keyboard) {
+        let state = self.events.keyboard_state();
+        for &(key, n) in &self.keys {
+            keyboard.set_key(n, state.is_scancode_pressed(key));
+        }
+    }
+
+    // Ctrl + 1-9 saves, Alt + 1-9 loads, F1-F4 save and Shift + F1-F4 load the
+    // first four slots, Shift + 1-4 flip QUIRK_KEYS, P pauses, M mutes, N
+    // advances a frame, Ctrl + R resets, Ctrl + O opens another rom, +/-
+    // changes speed, F12 takes a screenshot, F10 records a gif, F8 writes the
+    // profile, F6 toggles slow motion, F11 fullscreen, Esc opens the menu,
+    // which shows in the title
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
+
+    fn rewind_held(&self) -> bool {
+        self.events
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Backspace)
+    }
+}
+
+impl DisplaySink for SdlWindow {
+    fn set_title(&mut self, title: &str) {
+        // only fails for titles with a nul byte
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    fn set_palette(&mut self, (foreground, background): (u32, u32)) {
+        self.foreground = Self::color(foreground);
+        self.background = Self::color(background);
+    }
+
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        self.poll_events();
+        if let Some(audio) = &mut self.audio {
+            let mut voice = audio.lock();
+            voice.on = std::mem::take(&mut self.beeping);
+            voice.pattern = self.pattern;
+        }
+        // the window contents can be lost on resize, so redraw every frame
+        fb.take_update();
+        if let Err(err) = self.render(fb) {
+            eprintln!("{err}");
+            self.open = false;
+        }
+    }
+}
+
+impl AudioSink for SdlWindow {
+    // sounds for as long as it's called every frame
+    fn beep(&mut self, pattern: Option<AudioPattern>) {
+        self.beeping = true;
+        self.pattern = pattern;
+    }
+}
@@ -0,0 +1,355 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display::FrameBuffer,
+    memory::{Memory, MEMORY_SIZE},
+    registers::Registers,
+    timer::Timers,
+};
+
+// changed memory bytes are listed this many to a line
+const DIFF_ROW: usize = 8;
+
+// savestate files start with these, followed by a format version byte
+#[cfg(feature = "std")]
+const MAGIC: &[u8; 4] = b"EMC8";
+#[cfg(feature = "std")]
+const VERSION: u8 = 6;
+
+// The machine state in plain terms for tools outside emuchip, see to_json
+#[derive(Serialize)]
+struct Dump {
+    v: [u8; 16],
+    pc: u16,
+    i: u16,
+    stack: Vec<u16>,
+    delay: u8,
+    sound: u8,
+    hires: bool,
+    // all 4096 bytes
+    memory: Vec<u8>,
+    // a row of # and . per line of the screen
+    screen: Vec<String>,
+    // the XO-CHIP audio pattern, when the rom set one
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+// Where the random number generator is, so CXNN draws the same numbers again
+// after a restore
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+
+impl From<&ChaCha8Rng> for RngState {
+    fn from(rng: &ChaCha8Rng) -> Self {
+        Self {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+}
+
+impl From<&RngState> for ChaCha8Rng {
+    fn from(state: &RngState) -> Self {
+        use rand::SeedableRng;
+
+        let mut rng = ChaCha8Rng::from_seed(state.seed);
+        rng.set_stream(state.stream);
+        rng.set_word_pos(state.word_pos);
+        rng
+    }
+}
+
+// Complete machine state, enough to resume execution exactly where it was taken
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub regs: Registers,
+    pub mem: Memory,
+    pub timers: Timers,
+    pub fb: FrameBuffer,
+    pub vblank: bool,
+    pub pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub rng: RngState,
+}
+
+impl Snapshot {
+    // as one line of JSON, for analysis tools and bug reports
+    pub fn to_json(&self) -> String {
+        let dump = Dump {
+            v: core::array::from_fn(|reg| self.regs.get(reg as u8)),
+            pc: self.mem.pc.0,
+            i: self.mem.index.0,
+            stack: self.mem.stack.addresses().to_vec(),
+            delay: self.timers.delay,
+            sound: self.timers.sound,
+            hires: self.fb.is_hires(),
+            memory: (0..MEMORY_SIZE)
+                .map(|addr| self.mem.read(addr).unwrap_or_default())
+                .collect(),
+            screen: self.fb.to_string().lines().map(String::from).collect(),
+            pattern: self.pattern,
+            pitch: self.pitch,
+        };
+        serde_json::to_string(&dump).expect("the dump is always serializable")
+    }
+
+    // what changed from self to other, a line per difference, for bisecting
+    // emulation bugs between two runs
+    pub fn diff(&self, other: &Self) -> String {
+        let mut lines = vec![];
+        let mut changed = |name: &str, from: String, to: String| {
+            if from != to {
+                lines.push(format!("{name}: {from} -> {to}"));
+            }
+        };
+        for reg in 0..16 {
+            let (from, to) = (self.regs.get(reg), other.regs.get(reg));
+            changed(
+                &format!("V{reg:X}"),
+                format!("{from:02X}"),
+                format!("{to:02X}"),
+            );
+        }
+        let addr = |addr: u16| format!("{addr:03X}");
+        changed("PC", addr(self.mem.pc.0), addr(other.mem.pc.0));
+        changed("I", addr(self.mem.index.0), addr(other.mem.index.0));
+        changed(
+            "DT",
+            format!("{:02X}", self.timers.delay),
+            format!("{:02X}", other.timers.delay),
+        );
+        changed(
+            "ST",
+            format!("{:02X}", self.timers.sound),
+            format!("{:02X}", other.timers.sound),
+        );
+        let stack = |snapshot: &Self| {
+            let addresses: Vec<String> = snapshot
+                .mem
+                .stack
+                .addresses()
+                .iter()
+                .map(|a| addr(*a))
+                .collect();
+            format!("[{}]", addresses.join(" "))
+        };
+        changed("stack", stack(self), stack(other));
+        changed(
+            "hires",
+            format!("{}", self.fb.is_hires()),
+            format!("{}", other.fb.is_hires()),
+        );
+        changed(
+            "two-page",
+            format!("{}", self.fb.is_two_page()),
+            format!("{}", other.fb.is_two_page()),
+        );
+        changed(
+            "vblank",
+            format!("{}", self.vblank),
+            format!("{}", other.vblank),
+        );
+        changed(
+            "pitch",
+            format!("{}", self.pitch),
+            format!("{}", other.pitch),
+        );
+        changed(
+            "pattern",
+            format!("{:02X?}", self.pattern),
+            format!("{:02X?}", other.pattern),
+        );
+
+        // runs of changed bytes, split into rows
+        let bytes = |snapshot: &Self, from: usize, to: usize| {
+            let bytes: Vec<String> = (from..to)
+                .map(|addr| format!("{:02X}", snapshot.mem.read(addr).unwrap_or_default()))
+                .collect();
+            bytes.join(" ")
+        };
+        let differs = |addr: usize| self.mem.read(addr) != other.mem.read(addr);
+        let mut addr = 0;
+        while addr < MEMORY_SIZE {
+            if !differs(addr) {
+                addr += 1;
+                continue;
+            }
+            let start = addr;
+            while addr < MEMORY_SIZE && addr - start < DIFF_ROW && differs(addr) {
+                addr += 1;
+            }
+            lines.push(format!(
+                "{start:03X}: {} -> {}",
+                bytes(self, start, addr),
+                bytes(other, start, addr)
+            ));
+        }
+
+        let pixels: u32 = self
+            .fb
+            .rows()
+            .iter()
+            .zip(other.fb.rows())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        if pixels > 0 {
+            lines.push(format!("screen: {pixels} pixels differ"));
+        }
+        lines.join("\n")
+    }
+}
+
+// the file format is bincode, which needs std
+#[cfg(feature = "std")]
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend(bincode::serialize(self).expect("snapshot is always serializable"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        match bytes {
+            [m0, m1, m2, m3, version, data @ ..] if [*m0, *m1, *m2, *m3] == *MAGIC => {
+                if *version != VERSION {
+                    return Err(format!("unsupported savestate version {version}"));
+                }
+                bincode::deserialize(data).map_err(|err| format!("corrupt savestate: {err}"))
+            }
+            _ => Err("not an emuchip savestate".to_string()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|err| format!("could not write {path}: {err}"))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    // to_json into a file, can't be loaded back
+    pub fn dump(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_json()).map_err(|err| format!("could not write {path}: {err}"))
+    }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    use crate::{quirks::Quirks, Chip8};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 0, I = font sprite for 0, draw it, V1 = 0x2A
+    chip8
+        .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x61, 0x2A])
+        .unwrap();
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    let snapshot = Snapshot::from_bytes(&chip8.snapshot().to_bytes()).unwrap();
+
+    chip8.step().unwrap();
+    chip8.framebuffer_mut().clear_buffer();
+    chip8.restore(snapshot);
+    assert_eq!(chip8.regs.get(1), 0);
+    assert_eq!(chip8.mem.pc.0, 0x206);
+    assert!(chip8.framebuffer().get(0, 0));
+
+    assert!(Snapshot::from_bytes(b"nope").is_err());
+}
+
+#[test]
+fn test_snapshot_random() {
+    use crate::{quirks::Quirks, Chip8};
+
+    // V0 = random, forever
+    let mut chip8 = Chip8::with_seed(Quirks::default(), 7);
+    chip8.load_rom(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+    chip8.run_frame(20).unwrap();
+    let snapshot = Snapshot::from_bytes(&chip8.snapshot().to_bytes()).unwrap();
+    let draws = |chip8: &mut Chip8| {
+        (0..8)
+            .map(|_| {
+                chip8.run_frame(2).unwrap();
+                chip8.regs.get(0)
+            })
+            .collect::<Vec<_>>()
+    };
+    let first = draws(&mut chip8);
+    chip8.restore(snapshot);
+    assert_eq!(draws(&mut chip8), first);
+}
+
+#[test]
+fn test_snapshot_diff() {
+    use crate::{quirks::Quirks, Chip8};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 0xF0, V3 = 0x2A, store V0-V3 at 0x300, draw the first of them
+    chip8
+        .load_rom(&[
+            0x60, 0xF0, 0x63, 0x2A, 0xA3, 0x00, 0xF3, 0x55, 0xA3, 0x00, 0xD1, 0x21,
+        ])
+        .unwrap();
+    let before = chip8.snapshot();
+    for _ in 0..6 {
+        chip8.step().unwrap();
+    }
+    let after = chip8.snapshot();
+
+    assert_eq!(before.diff(&before), "");
+    assert_eq!(
+        before.diff(&after).lines().collect::<Vec<_>>(),
+        [
+            "V0: 00 -> F0",
+            "V3: 00 -> 2A",
+            "PC: 200 -> 20C",
+            "I: 000 -> 300",
+            "300: 00 -> F0",
+            "303: 00 -> 2A",
+            "screen: 4 pixels differ",
+        ]
+    );
+}
+
+#[test]
+fn test_snapshot_json() {
+    use crate::{quirks::Quirks, Chip8};
+
+    let mut chip8 = Chip8::new(Quirks::default());
+    // V0 = 0, I = font sprite for 0, draw it, call 20A
+    chip8
+        .load_rom(&[
+            0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x0A, 0x00, 0x00, 0x6F, 0x07,
+        ])
+        .unwrap();
+    for _ in 0..5 {
+        chip8.step().unwrap();
+    }
+    let json: serde_json::Value = serde_json::from_str(&chip8.snapshot().to_json()).unwrap();
+    assert_eq!(json["pc"], 0x20C);
+    assert_eq!(json["v"][0xF], 7);
+    assert_eq!(json["stack"], serde_json::json!([0x208]));
+    assert_eq!(json["memory"].as_array().unwrap().len(), MEMORY_SIZE);
+    assert_eq!(json["memory"][0x200], 0x60);
+    assert_eq!(json["screen"].as_array().unwrap().len(), 32);
+    assert!(json["screen"][0].as_str().unwrap().starts_with("####."));
+    assert_eq!(json["hires"], false);
+}
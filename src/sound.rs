@@ -1,90 +1,369 @@
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{FromSample, Sample, SizedSample};
+use cpal::{FromSample, SizedSample};
+
+use emuchip::audio::AudioPattern;
+
+// full scale is painfully loud
+const AMPLITUDE: f32 = 0.25;
+
+// seconds the tone takes to fade in and out, long enough not to click, short
+// enough to still follow the sound timer
+const ATTACK: f32 = 0.005;
+const RELEASE: f32 = 0.005;
+
+// sample rates to ask a device for, best first, a buzzer gains nothing from
+// the 192kHz some offer
+const STANDARD_RATES: [u32; 2] = [48000, 44100];
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+// What the buzzer sounds like
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    // in Hz
+    pub frequency: f32,
+    // from 0 to 1
+    pub volume: f32,
+}
 
+impl Tone {
+    // `phase` is the position within one period, from 0 to 1
+    pub fn sample(&self, phase: f32) -> f32 {
+        let value = match self.waveform {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        };
+        self.level(value)
+    }
+
+    // scales a sample from -1 to 1 down to the volume
+    fn level(&self, value: f32) -> f32 {
+        value * AMPLITUDE * self.volume
+    }
+}
+
+impl Default for Tone {
+    // the classic buzzer
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 500.0,
+            volume: 1.0,
+        }
+    }
+}
+
+// Turns the tone into samples, or the XO-CHIP pattern once the rom has set one,
+// fading in while on and out while off
+pub struct Voice {
+    tone: Tone,
+    sample_rate: f32,
+    pub on: bool,
+    pub pattern: Option<AudioPattern>,
+    // position within one period of the tone or the whole pattern, from 0 to 1
+    phase: f32,
+    // how far faded in, from 0 to 1
+    gain: f32,
+}
+
+impl Voice {
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Self {
+            tone,
+            sample_rate: sample_rate as f32,
+            on: false,
+            pattern: None,
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let (value, frequency) = match &self.pattern {
+            Some(pattern) => {
+                let bit = if pattern.sample(self.phase) {
+                    1.0
+                } else {
+                    -1.0
+                };
+                (self.tone.level(bit), pattern.rate() / 128.0)
+            }
+            None => (self.tone.sample(self.phase), self.tone.frequency),
+        };
+        self.phase = (self.phase + frequency / self.sample_rate) % 1.0;
+        let step = if self.on {
+            1.0 / (ATTACK * self.sample_rate)
+        } else {
+            -1.0 / (RELEASE * self.sample_rate)
+        };
+        self.gain = (self.gain + step).clamp(0.0, 1.0);
+        value * self.gain
+    }
+}
+
+// what the stream should be playing, handed over once a frame
+#[derive(Clone, Copy, Default)]
+struct Gate {
+    on: bool,
+    pattern: Option<AudioPattern>,
+}
+
+// One output stream that stays open for the whole run, the tone is gated on
+// and off by the sound timer so the emulator never waits on audio
 pub struct Sound {
-    device: cpal::Device,
-    config: cpal::StreamConfig,
-    format: cpal::SampleFormat,
+    // no sound rather than no emulator when there's no audio device
+    _stream: Option<cpal::Stream>,
+    shared: Arc<Mutex<Gate>>,
+    // what beep asked for since the last sync
+    next: Gate,
 }
 
 impl Sound {
-    pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("no output device available");
-        let mut supported_configs_range = device
-            .supported_output_configs()
-            .expect("error while querying configs");
-        let supported_config = supported_configs_range
-            .next()
-            .expect("no supported config?!")
-            .with_max_sample_rate();
-        let sample_format = supported_config.sample_format();
+    // `device` is a name or an index into the output devices, the default
+    // device without one
+    pub fn new(tone: Tone, device: Option<&str>) -> Self {
+        let shared = Arc::new(Mutex::new(Gate::default()));
+        let stream = match Self::open(tone, device, shared.clone()) {
+            Ok(stream) => Some(stream),
+            Err(err) => {
+                eprintln!("no sound: {err}");
+                None
+            }
+        };
         Self {
-            device,
-            config: supported_config.into(),
-            format: sample_format,
+            _stream: stream,
+            shared,
+            next: Gate::default(),
         }
     }
 
-    pub fn beep(&self) {
-        match self.format {
-            cpal::SampleFormat::I8 => self.run::<i8>(),
-            cpal::SampleFormat::I16 => self.run::<i16>(),
-            cpal::SampleFormat::I32 => self.run::<i32>(),
-            cpal::SampleFormat::I64 => self.run::<i64>(),
-            cpal::SampleFormat::U8 => self.run::<u8>(),
-            cpal::SampleFormat::U16 => self.run::<u16>(),
-            cpal::SampleFormat::U32 => self.run::<u32>(),
-            cpal::SampleFormat::U64 => self.run::<u64>(),
-            cpal::SampleFormat::F32 => self.run::<f32>(),
-            cpal::SampleFormat::F64 => self.run::<f64>(),
-            sample_format => panic!("Unsupported sample format '{sample_format}'"),
+    fn open(
+        tone: Tone,
+        device: Option<&str>,
+        shared: Arc<Mutex<Gate>>,
+    ) -> Result<cpal::Stream, String> {
+        let device = Self::device(device)?;
+        let supported = Self::config(&device)?;
+        let config = supported.config();
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::I8 => Self::build::<i8>(&device, &config, tone, shared),
+            cpal::SampleFormat::I16 => Self::build::<i16>(&device, &config, tone, shared),
+            cpal::SampleFormat::I32 => Self::build::<i32>(&device, &config, tone, shared),
+            cpal::SampleFormat::I64 => Self::build::<i64>(&device, &config, tone, shared),
+            cpal::SampleFormat::U8 => Self::build::<u8>(&device, &config, tone, shared),
+            cpal::SampleFormat::U16 => Self::build::<u16>(&device, &config, tone, shared),
+            cpal::SampleFormat::U32 => Self::build::<u32>(&device, &config, tone, shared),
+            cpal::SampleFormat::U64 => Self::build::<u64>(&device, &config, tone, shared),
+            cpal::SampleFormat::F32 => Self::build::<f32>(&device, &config, tone, shared),
+            cpal::SampleFormat::F64 => Self::build::<f64>(&device, &config, tone, shared),
+            sample_format => return Err(format!("unsupported sample format '{sample_format}'")),
+        }?;
+        stream.play().map_err(|err| err.to_string())?;
+        Ok(stream)
+    }
+
+    fn device(wanted: Option<&str>) -> Result<cpal::Device, String> {
+        let host = cpal::default_host();
+        let Some(wanted) = wanted else {
+            return host
+                .default_output_device()
+                .ok_or_else(|| "no output device available".to_string());
         };
+        let mut devices: Vec<cpal::Device> = host
+            .output_devices()
+            .map_err(|err| err.to_string())?
+            .collect();
+        let names: Vec<String> = devices
+            .iter()
+            .map(|device| device.name().unwrap_or_default())
+            .collect();
+        match find_device(&names, wanted) {
+            Some(index) => Ok(devices.swap_remove(index)),
+            None => {
+                let listed: Vec<String> = names
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| format!("{index}: {name}"))
+                    .collect();
+                Err(format!(
+                    "no output device '{wanted}', there are {}",
+                    listed.join(", ")
+                ))
+            }
+        }
+    }
+
+    // the device's own choice if it's at a standard rate, otherwise one that
+    // is, keeping to its sample format and channels where it can
+    fn config(device: &cpal::Device) -> Result<cpal::SupportedStreamConfig, String> {
+        let default = device
+            .default_output_config()
+            .map_err(|err| err.to_string())?;
+        if STANDARD_RATES.contains(&default.sample_rate().0) {
+            return Ok(default);
+        }
+        let standard = device
+            .supported_output_configs()
+            .map_err(|err| err.to_string())?
+            .filter_map(|range| {
+                let rate = standard_rate(range.min_sample_rate().0, range.max_sample_rate().0)?;
+                Some(range.with_sample_rate(cpal::SampleRate(rate)))
+            })
+            .min_by_key(|config| {
+                (
+                    config.sample_format() != default.sample_format(),
+                    config.channels() != default.channels(),
+                    config.sample_rate().0 != STANDARD_RATES[0],
+                )
+            });
+        Ok(standard.unwrap_or(default))
     }
 
-    fn run<T>(&self)
+    fn build<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        tone: Tone,
+        shared: Arc<Mutex<Gate>>,
+    ) -> Result<cpal::Stream, String>
     where
         T: SizedSample + FromSample<f32>,
     {
-        let sample_rate = self.config.sample_rate.0 as f32;
-        let channels = self.config.channels as usize;
-
-        // Produce a sinusoid of maximum amplitude.
-        let mut sample_clock = 0f32;
-        let mut next_value = move || {
-            sample_clock = (sample_clock + 1.0) % sample_rate;
-            (sample_clock * 440.0 * 2.0 * std::f32::consts::PI / sample_rate).sin()
-        };
-
-        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
-
-        let stream = self
-            .device
+        let channels = config.channels as usize;
+        let mut voice = Voice::new(tone, config.sample_rate.0);
+        let mut gate = Gate::default();
+        device
             .build_output_stream(
-                &self.config,
+                config,
                 move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                    Self::write_data(data, channels, &mut next_value)
+                    // never wait on the emulator, keep the last state if it's busy
+                    if let Ok(shared) = shared.try_lock() {
+                        gate = *shared;
+                    }
+                    voice.on = gate.on;
+                    voice.pattern = gate.pattern;
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(T::from_sample(voice.next_sample()));
+                    }
                 },
-                err_fn,
+                |err| eprintln!("an error occurred on stream: {err}"),
                 None,
             )
-            .unwrap();
+            .map_err(|err| err.to_string())
+    }
 
-        stream.play().unwrap();
-        std::thread::sleep(std::time::Duration::from_millis(500));
+    // sounds for as long as it's called every frame
+    pub fn beep(&mut self, pattern: Option<AudioPattern>) {
+        self.next = Gate { on: true, pattern };
     }
 
-    fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
-    where
-        T: Sample + FromSample<f32>,
-    {
-        for frame in output.chunks_mut(channels) {
-            let value: T = T::from_sample(next_sample());
-            for sample in frame.iter_mut() {
-                *sample = value;
-            }
+    // once a frame, turns the tone on or off depending on whether beep was called
+    pub fn sync(&mut self) {
+        let next = std::mem::take(&mut self.next);
+        if let Ok(mut shared) = self.shared.lock() {
+            *shared = next;
         }
     }
 }
+
+// the best standard rate between a device's `min` and `max`
+fn standard_rate(min: u32, max: u32) -> Option<u32> {
+    STANDARD_RATES
+        .into_iter()
+        .find(|rate| (min..=max).contains(rate))
+}
+
+// by index, then by exact name, then by part of one ignoring case
+pub fn find_device(names: &[String], wanted: &str) -> Option<usize> {
+    if let Ok(index) = wanted.parse::<usize>() {
+        return (index < names.len()).then_some(index);
+    }
+    let wanted_lower = wanted.to_lowercase();
+    names.iter().position(|name| name == wanted).or_else(|| {
+        names
+            .iter()
+            .position(|name| name.to_lowercase().contains(&wanted_lower))
+    })
+}
+
+#[test]
+fn test_waveforms() {
+    let tone = |waveform| Tone {
+        waveform,
+        frequency: 500.0,
+        volume: 1.0,
+    };
+    let at = |waveform, phase| tone(waveform).sample(phase) / AMPLITUDE;
+    assert_eq!(
+        (at(Waveform::Square, 0.25), at(Waveform::Square, 0.75)),
+        (1.0, -1.0)
+    );
+    assert_eq!(
+        (at(Waveform::Triangle, 0.0), at(Waveform::Triangle, 0.5)),
+        (1.0, -1.0)
+    );
+    assert_eq!(
+        (at(Waveform::Sawtooth, 0.0), at(Waveform::Sawtooth, 0.75)),
+        (-1.0, 0.5)
+    );
+    assert!((at(Waveform::Sine, 0.25) - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_voice_plays_pattern() {
+    let mut voice = Voice::new(Tone::default(), 8000);
+    let mut bits = [0; 16];
+    bits[0] = 0xC0;
+    voice.pattern = Some(AudioPattern { bits, pitch: 64 });
+    voice.on = true;
+    voice.gain = 1.0;
+    // 4000 bits a second at 8000 samples a second is two samples per bit
+    let samples: Vec<f32> = (0..6).map(|_| voice.next_sample() / AMPLITUDE).collect();
+    assert_eq!(samples, vec![1.0, 1.0, 1.0, 1.0, -1.0, -1.0]);
+}
+
+#[test]
+fn test_envelope() {
+    // 40 samples to fade in or out at 8000 a second
+    let mut voice = Voice::new(Tone::default(), 8000);
+    let level = |voice: &mut Voice| voice.next_sample().abs() / AMPLITUDE;
+    assert_eq!(level(&mut voice), 0.0);
+    voice.on = true;
+    assert!(level(&mut voice) < 0.1);
+    for _ in 0..38 {
+        level(&mut voice);
+    }
+    assert!(level(&mut voice) > 0.999);
+    voice.on = false;
+    assert!(level(&mut voice) > 0.9);
+    for _ in 0..38 {
+        level(&mut voice);
+    }
+    assert_eq!(level(&mut voice), 0.0);
+}
+
+#[test]
+fn test_pick_device_and_rate() {
+    let names = ["default", "HDMI Output", "USB Headset"].map(String::from);
+    assert_eq!(find_device(&names, "1"), Some(1));
+    assert_eq!(find_device(&names, "3"), None);
+    assert_eq!(find_device(&names, "default"), Some(0));
+    assert_eq!(find_device(&names, "usb"), Some(2));
+    assert_eq!(find_device(&names, "speakers"), None);
+
+    assert_eq!(standard_rate(8000, 192000), Some(48000));
+    assert_eq!(standard_rate(44100, 44100), Some(44100));
+    assert_eq!(standard_rate(96000, 192000), None);
+}
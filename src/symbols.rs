@@ -0,0 +1,137 @@
+use std::{fmt, fs};
+
+use crate::memory::TypeAddr;
+
+// Names for addresses, read from a symbol file or kept from assembling Octo
+// source. A symbol file has an address in hex and a name on each line, e.g.
+// `202 draw_ball`, with # starting a comment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    // by address, in the order they were defined for names of the same one
+    entries: Vec<(TypeAddr, String)>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {message}", n + 1);
+            let (addr, name) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [addr, name] => (addr, name),
+                _ => {
+                    return Err(error(format!(
+                        "expected an address and a name, not '{line}'"
+                    )))
+                }
+            };
+            let addr = TypeAddr::from_str_radix(addr.trim_start_matches("0x"), 16)
+                .map_err(|_| error(format!("'{addr}' is not a hex address")))?;
+            symbols.insert(addr, name).map_err(error)?;
+        }
+        Ok(symbols)
+    }
+
+    // names are what breakpoints are set with, so each is only used once
+    pub fn insert(&mut self, addr: TypeAddr, name: &str) -> Result<(), String> {
+        if self.entries.iter().any(|(_, other)| other == name) {
+            return Err(format!("symbol '{name}' is defined twice"));
+        }
+        let at = self.entries.partition_point(|(other, _)| *other <= addr);
+        self.entries.insert(at, (addr, name.to_string()));
+        Ok(())
+    }
+
+    // the first name given to the address
+    pub fn name(&self, addr: TypeAddr) -> Option<&str> {
+        let at = self.entries.partition_point(|(other, _)| *other < addr);
+        match self.entries.get(at) {
+            Some((other, name)) if *other == addr => Some(name),
+            _ => None,
+        }
+    }
+
+    // the closest name at or before the address, with how far past it the
+    // address is, e.g. main+4
+    pub fn locate(&self, addr: TypeAddr) -> Option<String> {
+        let at = self.entries.partition_point(|(other, _)| *other <= addr);
+        let base = self.entries.get(at.checked_sub(1)?)?.0;
+        let name = self.name(base)?;
+        match addr - base {
+            0 => Some(name.to_string()),
+            offset => Some(format!("{name}+{offset:X}")),
+        }
+    }
+
+    // the address of a name, or of a name and a hex offset as locate writes
+    // them
+    pub fn resolve(&self, s: &str) -> Option<TypeAddr> {
+        let (name, offset) = match s.split_once('+') {
+            Some((name, offset)) => (name, TypeAddr::from_str_radix(offset, 16).ok()?),
+            None => (s, 0),
+        };
+        let (addr, _) = self.entries.iter().find(|(_, other)| other == name)?;
+        addr.checked_add(offset)
+    }
+
+    // the address in hex, followed by where it is when there's a name before
+    // it, e.g. 204 <main+4>
+    pub fn describe(&self, addr: TypeAddr) -> String {
+        match self.locate(addr) {
+            Some(place) => format!("{addr:03X} <{place}>"),
+            None => format!("{addr:03X}"),
+        }
+    }
+}
+
+// the symbol file format parse reads
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (addr, name) in &self.entries {
+            writeln!(f, "{addr:03X} {name}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_symbols() {
+    let symbols = Symbols::parse(
+        "# from the assembler\n\
+         200 main\n\
+         0x20A draw_ball # a subroutine\n\
+         \n\
+         200 start\n",
+    )
+    .unwrap();
+    assert_eq!(symbols.name(0x200), Some("main"));
+    assert_eq!(symbols.name(0x202), None);
+    assert_eq!(symbols.locate(0x20A).as_deref(), Some("draw_ball"));
+    assert_eq!(symbols.locate(0x21C).as_deref(), Some("draw_ball+12"));
+    assert_eq!(symbols.locate(0x100), None);
+    assert_eq!(symbols.resolve("start"), Some(0x200));
+    assert_eq!(symbols.resolve("draw_ball+12"), Some(0x21C));
+    assert_eq!(symbols.resolve("missing"), None);
+    assert_eq!(symbols.describe(0x204), "204 <main+4>");
+    assert_eq!(Symbols::parse(&symbols.to_string()), Ok(symbols));
+
+    assert_eq!(
+        Symbols::parse("200 main\n202 main"),
+        Err("line 2: symbol 'main' is defined twice".to_string())
+    );
+    assert!(Symbols::parse("main 200").is_err());
+    assert!(Symbols::parse("200").is_err());
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+// Delay & sound timers, both count down at 60 times / s until 0
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Timers {
+    pub delay: u8,
+    pub sound: u8,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self { delay: 0, sound: 0 }
+    }
+
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // beep when sound timer is non-zero
+    pub fn is_beeping(&self) -> bool {
+        self.sound > 0
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decode::OpCodes,
+    memory::{Memory, TypeAddr},
+    registers::Registers,
+    symbols::Symbols,
+    Chip8,
+};
+
+// binary traces start with these, followed by a format version byte
+const MAGIC: &[u8; 4] = b"EMCT";
+const VERSION: u8 = 1;
+
+// How each traced instruction is written
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TraceFormat {
+    // a line for people to read
+    #[default]
+    Text,
+    // a JSON object per line, for scripts
+    Json,
+    // 22 bytes per instruction after the header, all big-endian: PC, the
+    // opcode, V0-VF and I
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "binary" => Ok(Self::Binary),
+            _ => Err(format!("unknown trace format '{s}'")),
+        }
+    }
+}
+
+// A line of a JSON trace
+#[derive(Serialize)]
+struct Line<'a> {
+    pc: TypeAddr,
+    code: u16,
+    op: &'a str,
+    v: [u8; 16],
+    i: TypeAddr,
+}
+
+// The state an instruction ran with, as read back from a JSON or binary trace
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TraceRecord {
+    pub pc: TypeAddr,
+    pub code: u16,
+    pub v: [u8; 16],
+    pub i: TypeAddr,
+}
+
+impl TraceRecord {
+    // what the next instruction of chip8 runs with
+    pub fn of(chip8: &Chip8) -> Self {
+        let pc = chip8.mem.pc.0;
+        Self {
+            pc,
+            code: chip8.mem.instruction_at(pc).unwrap_or_default(),
+            v: std::array::from_fn(|reg| chip8.regs.get(reg as u8)),
+            i: chip8.mem.index.0,
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let regs: Vec<String> = self.v.iter().map(|value| format!("{value:02X}")).collect();
+        write!(
+            f,
+            "{:03X}: {:04X}  V={} I={:03X}",
+            self.pc,
+            self.code,
+            regs.join(" "),
+            self.i
+        )
+    }
+}
+
+// A trace written with --trace-format json or binary, by emuchip or by
+// anything else writing the same fields. Text traces are only for reading
+pub fn read_trace(bytes: &[u8]) -> Result<Vec<TraceRecord>, String> {
+    if let Some(records) = bytes.strip_prefix(MAGIC) {
+        return match records.split_first() {
+            Some((&VERSION, records)) if records.len() % 22 == 0 => Ok(records
+                .chunks(22)
+                .map(|record| {
+                    let word = |at: usize| u16::from_be_bytes([record[at], record[at + 1]]);
+                    TraceRecord {
+                        pc: word(0),
+                        code: word(2),
+                        v: record[4..20].try_into().expect("16 registers"),
+                        i: word(20),
+                    }
+                })
+                .collect()),
+            Some((&VERSION, _)) => Err("the trace ends partway through a record".to_string()),
+            _ => Err("unsupported trace version".to_string()),
+        };
+    }
+    let text = std::str::from_utf8(bytes).map_err(|_| "not a JSON or binary trace")?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|err| format!("line {}: {err}", n + 1)))
+        .collect()
+}
+
+// Runs along a recorded trace, comparing the state before every instruction
+// with it. The trace has to be unfiltered, and made at the same speed for the
+// timers to line up
+pub struct Differ {
+    expected: Vec<TraceRecord>,
+    // how many instructions have matched
+    matched: usize,
+}
+
+impl Differ {
+    pub fn new(expected: Vec<TraceRecord>) -> Self {
+        Self {
+            expected,
+            matched: 0,
+        }
+    }
+
+    // Err describing the first difference, past the end of the trace
+    // anything goes
+    pub fn check(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let Some(expected) = self.expected.get(self.matched) else {
+            return Ok(());
+        };
+        let actual = TraceRecord::of(chip8);
+        if actual == *expected {
+            self.matched += 1;
+            return Ok(());
+        }
+        let mut differs: Vec<String> = (0..16)
+            .filter(|&reg| actual.v[reg] != expected.v[reg])
+            .map(|reg| format!("V{reg:X}"))
+            .collect();
+        for (name, same) in [
+            ("PC", actual.pc == expected.pc),
+            ("opcode", actual.code == expected.code),
+            ("I", actual.i == expected.i),
+        ] {
+            if !same {
+                differs.push(name.to_string());
+            }
+        }
+        let after = match self.matched.checked_sub(1) {
+            Some(previous) => {
+                let previous = self.expected[previous];
+                let op = OpCodes::decode_raw(previous.code);
+                format!(", after {:03X}: {:04X} {op}", previous.pc, previous.code)
+            }
+            None => String::new(),
+        };
+        Err(format!(
+            "instruction {} differs from the trace{after}\n  expected {expected}\n  got      {actual}\n  differs  {}",
+            self.matched + 1,
+            differs.join(" ")
+        ))
+    }
+
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    pub fn len(&self) -> usize {
+        self.expected.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expected.is_empty()
+    }
+}
+
+// Which executed instructions end up in the trace
+#[derive(Default)]
+pub struct TraceFilter {
+    // inclusive address range
+    pub range: Option<(TypeAddr, TypeAddr)>,
+    // mnemonic names such as DRW or JP, empty means all
+    pub ops: Vec<String>,
+}
+
+impl TraceFilter {
+    fn matches(&self, pc: TypeAddr, mnemonic: &str) -> bool {
+        let in_range = self
+            .range
+            .is_none_or(|(start, end)| (start..=end).contains(&pc));
+        let name = mnemonic.split_whitespace().next().unwrap_or("");
+        let op_matches =
+            self.ops.is_empty() || self.ops.iter().any(|op| op.eq_ignore_ascii_case(name));
+        in_range && op_matches
+    }
+}
+
+// Logs every executed instruction with the machine state before it ran
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    filter: TraceFilter,
+    format: TraceFormat,
+    // names text traces show in place of addresses
+    symbols: Symbols,
+}
+
+impl Tracer {
+    pub fn new(mut out: Box<dyn Write + Send>, filter: TraceFilter, format: TraceFormat) -> Self {
+        if format == TraceFormat::Binary {
+            let _ = out.write_all(MAGIC).and_then(|_| out.write_all(&[VERSION]));
+        }
+        Self {
+            out,
+            filter,
+            format,
+            symbols: Symbols::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn trace(&mut self, pc: TypeAddr, code: u16, op: OpCodes, regs: &Registers, mem: &Memory) {
+        let mnemonic = op.to_string();
+        if !self.filter.matches(pc, &mnemonic) {
+            return;
+        }
+        let v: [u8; 16] = std::array::from_fn(|reg| regs.get(reg as u8));
+        let index = mem.index.0;
+        // tracing is best effort, a closed pipe shouldn't stop emulation
+        let _ = match self.format {
+            TraceFormat::Text => {
+                let regs: Vec<String> = v.iter().map(|value| format!("{value:02X}")).collect();
+                let named = op.mnemonic(|addr| match self.symbols.name(addr) {
+                    Some(name) => name.to_string(),
+                    None => format!("0x{addr:03X}"),
+                });
+                writeln!(
+                    self.out,
+                    "{}: {code:04X}  {named:<18} V={} I={index:03X}",
+                    self.symbols.describe(pc),
+                    regs.join(" "),
+                )
+            }
+            TraceFormat::Json => {
+                let line = Line {
+                    pc,
+                    code,
+                    op: &mnemonic,
+                    v,
+                    i: index,
+                };
+                let json = serde_json::to_string(&line).expect("a trace line is serializable");
+                writeln!(self.out, "{json}")
+            }
+            TraceFormat::Binary => {
+                let mut record = Vec::with_capacity(22);
+                record.extend(pc.to_be_bytes());
+                record.extend(code.to_be_bytes());
+                record.extend(v);
+                record.extend(index.to_be_bytes());
+                self.out.write_all(&record)
+            }
+        };
+    }
+}
+
+#[test]
+fn test_filter() {
+    let filter = TraceFilter {
+        range: Some((0x200, 0x2FF)),
+        ops: vec!["drw".to_string()],
+    };
+    assert!(filter.matches(0x204, "DRW V0, V1, 5"));
+    assert!(!filter.matches(0x204, "JP 0x200"));
+    assert!(!filter.matches(0x300, "DRW V0, V1, 5"));
+    assert!(TraceFilter::default().matches(0x300, "JP 0x200"));
+}
+
+// what a tracer wrote, shared with the test
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_formats() {
+    let mut regs = Registers::new();
+    regs.set_register(0xF, 0x2A);
+    let mem = Memory::new();
+    let traced = |format| {
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), TraceFilter::default(), format);
+        tracer.trace(0x200, 0x6025, OpCodes::decode_raw(0x6025), &regs, &mem);
+        let bytes = out.0.lock().unwrap().clone();
+        bytes
+    };
+
+    let text = String::from_utf8(traced(TraceFormat::Text)).unwrap();
+    assert!(text.starts_with("200: 6025  LD V0, 0x25"));
+    assert!(text.ends_with(" 2A I=000\n"));
+    let out = Shared::default();
+    let mut tracer = Tracer::new(
+        Box::new(out.clone()),
+        TraceFilter::default(),
+        TraceFormat::Text,
+    );
+    tracer.set_symbols(Symbols::parse("200 main\n300 draw").unwrap());
+    tracer.trace(0x204, 0x2300, OpCodes::decode_raw(0x2300), &regs, &mem);
+    let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    assert!(text.starts_with("204 <main+4>: 2300  CALL draw "), "{text}");
+    let json: serde_json::Value = serde_json::from_slice(&traced(TraceFormat::Json)).unwrap();
+    assert_eq!(json["pc"], 0x200);
+    assert_eq!(json["op"], "LD V0, 0x25");
+    assert_eq!(json["v"][15], 0x2A);
+    let binary = traced(TraceFormat::Binary);
+    assert_eq!(binary.len(), 5 + 22);
+    assert_eq!(&binary[..7], b"EMCT\x01\x02\x00");
+    assert_eq!(binary[5 + 4 + 15], 0x2A);
+}
+
+#[test]
+fn test_differ() {
+    use crate::quirks::{Platform, Quirks};
+
+    // V0 = 3, V1 = 10, shift V1 right, loop back to the shift
+    let rom = [0x60, 0x03, 0x61, 0x10, 0x81, 0x06, 0x12, 0x04];
+    let record = |format| {
+        let out = Shared::default();
+        let mut chip8 = Chip8::with_seed(Quirks::default(), 0);
+        chip8.load_rom(&rom).unwrap();
+        let tracer = Tracer::new(Box::new(out.clone()), TraceFilter::default(), format);
+        chip8.set_tracer(Some(tracer));
+        for _ in 0..6 {
+            chip8.step().unwrap();
+        }
+        let bytes = out.0.lock().unwrap().clone();
+        read_trace(&bytes).unwrap()
+    };
+    let trace = record(TraceFormat::Binary);
+    assert_eq!(trace.len(), 6);
+    assert_eq!(trace, record(TraceFormat::Json));
+
+    let run = |quirks| {
+        let mut chip8 = Chip8::with_seed(quirks, 0);
+        chip8.load_rom(&rom).unwrap();
+        let mut differ = Differ::new(trace.clone());
+        for _ in 0..8 {
+            differ.check(&chip8)?;
+            chip8.step().unwrap();
+        }
+        Ok::<_, String>(differ.matched())
+    };
+    assert_eq!(run(Quirks::default()), Ok(6));
+    // the COSMAC VIP shifts VY into VX
+    let err = run(Quirks::from_platform(Platform::Cosmac)).unwrap_err();
+    assert!(
+        err.starts_with("instruction 4 differs from the trace, after 204: 8106 SHR V1, V0"),
+        "{err}"
+    );
+    assert!(err.ends_with("differs  V1 VF"), "{err}");
+    assert!(read_trace(b"EMCT\x01\x02").is_err());
+    assert!(read_trace(b"200: 6003").is_err());
+}
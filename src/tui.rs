@@ -0,0 +1,427 @@
+use std::{
+    io::{self, Stdout, Write},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use emuchip::{
+    audio::AudioPattern,
+    display::FrameBuffer,
+    host::{AudioSink, DisplaySink, Hotkey, InputSource},
+    keyboard::Keyboard,
+    memory::{Memory, MEMORY_SIZE},
+};
+
+use crate::frontend::{HostKey, Keymap, DEFAULT_PALETTE, QUIRK_KEYS};
+
+// Most terminals never report key releases, only repeated presses while a
+// key is held, so a key counts as held for this many frames after each press
+const HOLD_FRAMES: u8 = 10;
+
+// bytes on each line of the memory pane
+const ROW_BYTES: usize = 8;
+
+// Hex dump of memory next to the screen, kept up to date every frame
+struct MemoryPane {
+    mem: Memory,
+    // first line shown, None keeps the program counter in the middle
+    scroll: Option<usize>,
+}
+
+impl MemoryPane {
+    fn first_row(&self, rows: usize) -> usize {
+        let last = (MEMORY_SIZE / ROW_BYTES).saturating_sub(rows);
+        let pc_row = self.mem.pc.0 as usize / ROW_BYTES;
+        self.scroll
+            .unwrap_or(pc_row.saturating_sub(rows / 2))
+            .min(last)
+    }
+
+    // `pages` up when negative, in screens of `rows` lines
+    fn scroll(&mut self, pages: isize, rows: usize) {
+        let first = self.first_row(rows) as isize + pages * rows as isize;
+        self.scroll = Some(first.max(0) as usize);
+    }
+}
+
+// Draws the screen with half blocks, two pixels per character cell
+pub struct Terminal {
+    out: Stdout,
+    keymap: Keymap,
+    foreground: Color,
+    background: Color,
+    // frames left until each key counts as released
+    keys: [u8; 16],
+    rewind: u8,
+    // the terminal reports releases itself, no need to time keys out
+    releases: bool,
+    hotkeys: Vec<Hotkey>,
+    // shown in a status line under the screen
+    title: String,
+    open: bool,
+    redraw: bool,
+    beeping: bool,
+    was_beeping: bool,
+    // shown while Some
+    memory: Option<MemoryPane>,
+}
+
+impl Terminal {
+    pub fn new(palette: Option<(u32, u32)>, keymap: &Keymap) -> Result<Self, String> {
+        let (foreground, background) = palette.unwrap_or(DEFAULT_PALETTE);
+        let mut terminal = Self {
+            out: io::stdout(),
+            keymap: keymap.clone(),
+            foreground: Self::color(foreground),
+            background: Self::color(background),
+            keys: [0; 16],
+            rewind: 0,
+            releases: terminal::supports_keyboard_enhancement().unwrap_or(false),
+            hotkeys: vec![],
+            title: String::new(),
+            open: true,
+            redraw: true,
+            beeping: false,
+            was_beeping: false,
+            memory: None,
+        };
+        // on failure the terminal is restored when it's dropped
+        terminal
+            .setup()
+            .map_err(|err| format!("could not set up terminal: {err}"))?;
+        Ok(terminal)
+    }
+
+    fn setup(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            self.out,
+            EnterAlternateScreen,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        if self.releases {
+            execute!(
+                self.out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn color(rgb: u32) -> Color {
+        Color::Rgb {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        }
+    }
+
+    fn poll_events(&mut self) -> io::Result<()> {
+        if !self.releases {
+            for frames in self.keys.iter_mut().chain([&mut self.rewind]) {
+                *frames = frames.saturating_sub(1);
+            }
+        }
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) => self.handle_key(key),
+                Event::Resize(..) => self.redraw = true,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let held = match key.kind {
+            KeyEventKind::Release => 0,
+            _ if self.releases => u8::MAX,
+            _ => HOLD_FRAMES,
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let press = key.kind == KeyEventKind::Press;
+        // the keypad can have these too
+        match key.code {
+            KeyCode::Up if press => self.hotkeys.push(Hotkey::MenuUp),
+            KeyCode::Down if press => self.hotkeys.push(Hotkey::MenuDown),
+            KeyCode::Enter if press => self.hotkeys.push(Hotkey::MenuPick),
+            _ => {}
+        }
+        match key.code {
+            KeyCode::Esc if press => self.hotkeys.push(Hotkey::Menu),
+            // raw mode swallows the interrupt signal
+            KeyCode::Char('c') if ctrl => self.open = false,
+            // what Shift + 1-4 type on a US keyboard
+            KeyCode::Char(c @ ('!' | '@' | '#' | '$')) if press => {
+                let i = "!@#$".find(c).unwrap_or_default();
+                self.hotkeys.push(Hotkey::ToggleQuirk(QUIRK_KEYS[i]));
+            }
+            KeyCode::Char('p') if press => self.hotkeys.push(Hotkey::TogglePause),
+            KeyCode::Char('m') if press => self.hotkeys.push(Hotkey::ToggleMute),
+            // repeats too, holding N plays in slow motion
+            KeyCode::Char('n') if key.kind != KeyEventKind::Release => {
+                self.hotkeys.push(Hotkey::FrameAdvance)
+            }
+            KeyCode::Char('r') if ctrl && press => self.hotkeys.push(Hotkey::Reset),
+            KeyCode::Char('o') if ctrl && press => self.hotkeys.push(Hotkey::OpenRom),
+            KeyCode::Char('+' | '=') if press => self.hotkeys.push(Hotkey::SpeedUp),
+            KeyCode::Char('-') if press => self.hotkeys.push(Hotkey::SpeedDown),
+            KeyCode::F(12) if press => self.hotkeys.push(Hotkey::Screenshot),
+            KeyCode::F(10) if press => self.hotkeys.push(Hotkey::ToggleGif),
+            KeyCode::F(8) if press => self.hotkeys.push(Hotkey::SaveProfile),
+            KeyCode::F(6) if press => self.hotkeys.push(Hotkey::ToggleSlowMotion),
+            KeyCode::F(slot @ 1..=4) if press => {
+                if key.modifiers.contains(KeyModifiers::SHIFT) {
+                    self.hotkeys.push(Hotkey::LoadState(slot));
+                } else {
+                    self.hotkeys.push(Hotkey::SaveState(slot));
+                }
+            }
+            KeyCode::F(7) if press => {
+                self.memory = match self.memory {
+                    Some(_) => None,
+                    None => Some(MemoryPane {
+                        mem: Memory::new(),
+                        scroll: None,
+                    }),
+                };
+                self.redraw = true;
+            }
+            KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home if self.memory.is_some() => {
+                let rows = terminal::size().map_or(0, |(_, rows)| rows as usize);
+                let Some(pane) = &mut self.memory else {
+                    return;
+                };
+                match key.code {
+                    KeyCode::PageUp => pane.scroll(-1, rows),
+                    KeyCode::PageDown => pane.scroll(1, rows),
+                    _ => pane.scroll = None,
+                }
+            }
+            KeyCode::Backspace => self.rewind = held,
+            code => {
+                let Some(host) = Self::host_key(code) else {
+                    return;
+                };
+                for (key, n) in self.keymap.keys() {
+                    if key == host {
+                        self.keys[n as usize] = held;
+                    }
+                }
+            }
+        }
+    }
+
+    fn host_key(code: KeyCode) -> Option<HostKey> {
+        match code {
+            KeyCode::Char(' ') => Some(HostKey::Space),
+            KeyCode::Char(c) => c.to_string().parse().ok(),
+            KeyCode::Enter => Some(HostKey::Enter),
+            KeyCode::Up => Some(HostKey::Up),
+            KeyCode::Down => Some(HostKey::Down),
+            KeyCode::Left => Some(HostKey::Left),
+            KeyCode::Right => Some(HostKey::Right),
+            _ => None,
+        }
+    }
+
+    fn render(&mut self, fb: &FrameBuffer) -> io::Result<()> {
+        if self.redraw {
+            queue!(self.out, Clear(ClearType::All))?;
+            self.redraw = false;
+        }
+        let color = |on| {
+            if on {
+                self.foreground
+            } else {
+                self.background
+            }
+        };
+        let mut last = None;
+        for row in 0..fb.height() / 2 {
+            queue!(self.out, cursor::MoveTo(0, row as u16))?;
+            for x in 0..fb.width() {
+                // upper pixel in the foreground, lower one behind it
+                let colors = (color(fb.get(x, row * 2)), color(fb.get(x, row * 2 + 1)));
+                if last != Some(colors) {
+                    queue!(
+                        self.out,
+                        SetForegroundColor(colors.0),
+                        SetBackgroundColor(colors.1)
+                    )?;
+                    last = Some(colors);
+                }
+                queue!(self.out, Print('▀'))?;
+            }
+        }
+        queue!(
+            self.out,
+            ResetColor,
+            cursor::MoveTo(0, (fb.height() / 2) as u16),
+            Clear(ClearType::CurrentLine)
+        )?;
+        queue!(self.out, Print(&self.title))?;
+        self.out.flush()
+    }
+
+    // to the right of the screen, starting at column `left`
+    fn render_memory(&mut self, left: u16) -> io::Result<()> {
+        let Some(pane) = &self.memory else {
+            return Ok(());
+        };
+        let (_, rows) = terminal::size()?;
+        let first = pane.first_row(rows as usize);
+        let pc = pane.mem.pc.0 as usize;
+        let index = pane.mem.index.0 as usize;
+        queue!(self.out, ResetColor)?;
+        for row in 0..rows {
+            let start = (first + row as usize) * ROW_BYTES;
+            queue!(
+                self.out,
+                cursor::MoveTo(left, row),
+                Clear(ClearType::UntilNewLine)
+            )?;
+            if start >= MEMORY_SIZE {
+                continue;
+            }
+            queue!(self.out, Print(format!("{start:03X} ")))?;
+            for addr in start..start + ROW_BYTES {
+                // the instruction about to run, and what I points at
+                let attribute = if addr == pc || addr == pc + 1 {
+                    Attribute::Reverse
+                } else if addr == index {
+                    Attribute::Underlined
+                } else {
+                    Attribute::Reset
+                };
+                let byte = pane.mem.read(addr).unwrap_or_default();
+                queue!(
+                    self.out,
+                    Print(' '),
+                    SetAttribute(attribute),
+                    Print(format!("{byte:02X}")),
+                    SetAttribute(Attribute::Reset)
+                )?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn ring_bell(&mut self) -> io::Result<()> {
+        let start = self.beeping && !self.was_beeping;
+        self.was_beeping = std::mem::take(&mut self.beeping);
+        if start {
+            execute!(self.out, Print('\x07'))?;
+        }
+        Ok(())
+    }
+}
+
+impl InputSource for Terminal {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update_keys(&mut self, keyboard: &mut Keyboard) {
+        for (n, frames) in self.keys.iter().enumerate() {
+            keyboard.set_key(n as u8, *frames > 0);
+        }
+    }
+
+    // P pauses, M mutes, N advances a frame, Ctrl + R resets, Ctrl + O opens
+    // another rom, +/- changes speed, F12 takes a screenshot, F10 records a gif,
+    // F8 writes the profile, F7 shows memory, which PageUp/PageDown scroll and
+    // Home centers on the program counter again, F6 toggles slow motion, F1-F4
+    // save and Shift + F1-F4 load the first four savestates; the other slots
+    // need modifier + number combos most terminals can't report. Shift + 1-4
+    // flip QUIRK_KEYS on a US layout, Esc opens the menu, which shows in the
+    // status line
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
+
+    fn rewind_held(&self) -> bool {
+        self.rewind > 0
+    }
+}
+
+impl DisplaySink for Terminal {
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.redraw = true;
+    }
+
+    fn set_palette(&mut self, (foreground, background): (u32, u32)) {
+        self.foreground = Self::color(foreground);
+        self.background = Self::color(background);
+        self.redraw = true;
+    }
+
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        let mut result = self.poll_events().and_then(|()| self.ring_bell());
+        if result.is_ok() && (fb.take_update() || self.redraw) {
+            result = self.render(fb);
+        }
+        if result.is_ok() {
+            result = self.render_memory(fb.width() as u16 + 2);
+        }
+        // stdout went away, nothing left to show the game on
+        if result.is_err() {
+            self.open = false;
+        }
+    }
+
+    fn show_memory(&mut self, mem: &Memory) {
+        if let Some(pane) = &mut self.memory {
+            pane.mem = mem.clone();
+        }
+    }
+}
+
+impl AudioSink for Terminal {
+    // the terminal bell, rung once at the start of every beep
+    fn beep(&mut self, _: Option<AudioPattern>) {
+        self.beeping = true;
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, ResetColor, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[test]
+fn test_memory_pane_scroll() {
+    let mut pane = MemoryPane {
+        mem: Memory::new(),
+        scroll: None,
+    };
+    pane.mem.set_pc(0x280);
+    // the program counter's line in the middle
+    assert_eq!(pane.first_row(16), 0x280 / ROW_BYTES - 8);
+    pane.scroll(-1, 16);
+    assert_eq!(pane.first_row(16), 0x280 / ROW_BYTES - 24);
+    pane.scroll(-100, 16);
+    assert_eq!(pane.first_row(16), 0);
+    // stops with the last line at the bottom
+    pane.scroll(1000, 16);
+    assert_eq!(pane.first_row(16), MEMORY_SIZE / ROW_BYTES - 16);
+}
@@ -0,0 +1,64 @@
+// Bindings for the browser frontend in web/, built with
+// wasm-pack build --target web --no-default-features --features web
+//
+// The page owns the canvas, keyboard and WebAudio, this only runs the machine.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{quirks::Quirks, Chip8};
+
+#[wasm_bindgen]
+pub struct WebChip8 {
+    chip8: Chip8,
+    // instructions per frame
+    cycles: u32,
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    #[wasm_bindgen(constructor)]
+    // throws when the rom doesn't fit in memory
+    pub fn new(rom: &[u8], speed: u32) -> Result<WebChip8, String> {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.load_rom(rom)?;
+        Ok(Self {
+            chip8,
+            cycles: speed / 60,
+        })
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.chip8.set_key(key, pressed);
+    }
+
+    // one 60Hz frame worth of emulation, throws when the rom crashes, once
+    // the rom has exited the screen just stays as it was left
+    pub fn frame(&mut self) -> Result<(), String> {
+        match self.chip8.run_frame(self.cycles) {
+            Err(err) if err.is_exit() => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.chip8.framebuffer().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip8.framebuffer().height()
+    }
+
+    // whether the screen changed since the last call
+    pub fn take_update(&mut self) -> bool {
+        self.chip8.framebuffer_mut().take_update()
+    }
+
+    // one byte per pixel, 1 when lit, row by row
+    pub fn pixels(&self) -> Vec<u8> {
+        self.chip8.framebuffer().pixels().map(u8::from).collect()
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.chip8.is_beeping()
+    }
+}
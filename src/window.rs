@@ -0,0 +1,485 @@
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, WindowOptions};
+
+use emuchip::{
+    audio::AudioPattern,
+    display::{FrameBuffer, HEIGHT, WIDTH},
+    host::{AudioSink, DisplaySink, Hotkey, InputSource, Stats},
+    keyboard::Keyboard,
+};
+
+use crate::{
+    crt::Crt,
+    frontend::{HostKey, Keymap, DEFAULT_PALETTE, QUIRK_KEYS, TITLE},
+    overlay::{self, Keypad, Message},
+    sound::Sound,
+};
+
+// QWERTY layout of the hex keypad
+//
+const LETTERS: [Key; 26] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+
+const DIGITS: [Key; 10] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+];
+
+fn host_key(key: HostKey) -> Key {
+    match key {
+        HostKey::Letter(i) => LETTERS[i as usize],
+        HostKey::Digit(i) => DIGITS[i as usize],
+        HostKey::Space => Key::Space,
+        HostKey::Enter => Key::Enter,
+        HostKey::Up => Key::Up,
+        HostKey::Down => Key::Down,
+        HostKey::Left => Key::Left,
+        HostKey::Right => Key::Right,
+        HostKey::Comma => Key::Comma,
+        HostKey::Period => Key::Period,
+        HostKey::Apostrophe => Key::Apostrophe,
+        HostKey::Semicolon => Key::Semicolon,
+    }
+}
+
+pub struct Window {
+    window: minifb::Window,
+    // kept for when the window is opened again
+    title: String,
+    borderless: bool,
+    palette: (u32, u32),
+    // at the size of the window
+    pixel_buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+    // the screen scaled up as far as the window fits it, so the CRT effects
+    // have room to work with, and where it's centered in the window
+    screen: Vec<u32>,
+    area: Area,
+    crt: Crt,
+    crt_on: bool,
+    // the debug overlay, drawn while on
+    stats: Option<Stats>,
+    overlay_on: bool,
+    // the clickable keypad, and what it shows held
+    keypad_on: bool,
+    keypad_held: [bool; 16],
+    // shown until it expires
+    message: Option<Message>,
+    // the pause menu's lines, drawn while it's open
+    menu: Option<Vec<String>>,
+    // the palette changed, the whole window is drawn again
+    repaint: bool,
+    keys: Vec<(Key, u8)>,
+    sound: Sound,
+}
+
+// Part of the window, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Area {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Area {
+    // the low resolution screen at the largest whole number scale that fits a
+    // window, centered
+    fn fit(width: usize, height: usize) -> Self {
+        let scale = (width / WIDTH).min(height / HEIGHT).max(1);
+        let (screen_width, screen_height) = (WIDTH * scale, HEIGHT * scale);
+        Self {
+            left: width.saturating_sub(screen_width) / 2,
+            top: height.saturating_sub(screen_height) / 2,
+            width: screen_width,
+            height: screen_height,
+        }
+    }
+}
+
+impl Window {
+    // scale is the initial on screen size of a low resolution pixel, the
+    // window can be resized from there
+    pub fn new(
+        scale: usize,
+        palette: Option<(u32, u32)>,
+        keymap: &Keymap,
+        crt: Option<Crt>,
+        sound: Sound,
+        borderless: bool,
+        keypad: bool,
+    ) -> Self {
+        let (width, height) = (WIDTH * scale, HEIGHT * scale);
+        let window = Self::open(width, height, borderless, TITLE, None);
+        let palette = palette.unwrap_or(DEFAULT_PALETTE);
+        let mut window = Self {
+            window,
+            title: TITLE.to_string(),
+            borderless,
+            palette,
+            pixel_buffer: vec![],
+            width: 0,
+            height: 0,
+            screen: vec![],
+            area: Area::fit(width, height),
+            crt: crt.unwrap_or_default(),
+            crt_on: crt.is_some(),
+            stats: None,
+            overlay_on: false,
+            keypad_on: keypad,
+            keypad_held: [false; 16],
+            message: None,
+            menu: None,
+            repaint: false,
+            keys: keymap.keys().map(|(key, n)| (host_key(key), n)).collect(),
+            sound,
+        };
+        window.resize(width, height);
+        window
+    }
+
+    // never smaller than the screen at scale 1, minifb squeezes that into a
+    // window that is
+    fn resize(&mut self, width: usize, height: usize) {
+        let (width, height) = (width.max(WIDTH), height.max(HEIGHT));
+        self.area = Area::fit(width, height);
+        self.screen = vec![0; self.area.width * self.area.height];
+        self.pixel_buffer = vec![self.palette.1; width * height];
+        (self.width, self.height) = (width, height);
+    }
+
+    // minifb has no fullscreen, and can't change a window's borders either,
+    // only open another one
+    fn open(
+        width: usize,
+        height: usize,
+        borderless: bool,
+        title: &str,
+        position: Option<(isize, isize)>,
+    ) -> minifb::Window {
+        let options = WindowOptions {
+            borderless,
+            resize: true,
+            ..WindowOptions::default()
+        };
+        let mut window = minifb::Window::new(title, width, height, options).unwrap();
+        // otherwise wherever the desktop puts it
+        if let Some((x, y)) = position {
+            window.set_position(x, y);
+        }
+        // the main loop paces the frames, more precisely, and once for all
+        // the windows there are
+        window.limit_update_rate(None);
+        window
+    }
+
+    // under the mouse while the left button is down, which is also what a
+    // touch comes through as
+    fn clicked_key(&self) -> Option<u8> {
+        if !self.keypad_on || !self.window.get_mouse_down(MouseButton::Left) {
+            return None;
+        }
+        let (x, y) = self.window.get_mouse_pos(MouseMode::Discard)?;
+        let Area {
+            left,
+            top,
+            width,
+            height,
+        } = self.area;
+        let x = (x as usize).checked_sub(left)?;
+        let y = (y as usize).checked_sub(top)?;
+        Keypad::new(width, height).key_at(x, y)
+    }
+
+    // of the top left corner on the desktop
+    pub fn set_position(&mut self, x: isize, y: isize) {
+        self.window.set_position(x, y);
+    }
+}
+
+impl InputSource for Window {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn update_keys(&mut self, keyboard: &mut Keyboard) {
+        let clicked = self.clicked_key();
+        for &(key, n) in &self.keys {
+            let held = self.window.is_key_down(key) || clicked == Some(n);
+            keyboard.set_key(n, held);
+            self.keypad_held[n as usize] = held;
+        }
+    }
+
+    // Ctrl + 1-9 saves, Alt + 1-9 loads, F1-F4 save and Shift + F1-F4 load the
+    // first four slots, Shift + 1-4 flip QUIRK_KEYS, P pauses, M mutes, N
+    // advances a frame, Ctrl + R resets, Ctrl + O opens another rom, +/-
+    // changes speed, F12 takes a screenshot, F10 records a gif, F9 toggles the
+    // CRT effects, F8 writes the profile, F7 the debug overlay, F6 toggles slow
+    // motion, F5 the clickable keypad, F11 the window borders, Esc opens the
+    // menu
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        let ctrl =
+            self.window.is_key_down(Key::LeftCtrl) || self.window.is_key_down(Key::RightCtrl);
+        let alt = self.window.is_key_down(Key::LeftAlt) || self.window.is_key_down(Key::RightAlt);
+        let shift =
+            self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+
+        let mut hotkeys = vec![];
+        let menu_keys = [
+            (Key::Escape, Hotkey::Menu),
+            (Key::Up, Hotkey::MenuUp),
+            (Key::Down, Hotkey::MenuDown),
+            (Key::Enter, Hotkey::MenuPick),
+        ];
+        for (key, hotkey) in menu_keys {
+            if self.window.is_key_pressed(key, KeyRepeat::No) {
+                hotkeys.push(hotkey);
+            }
+        }
+        if self.window.is_key_pressed(Key::P, KeyRepeat::No) {
+            hotkeys.push(Hotkey::TogglePause);
+        }
+        if self.window.is_key_pressed(Key::M, KeyRepeat::No) {
+            hotkeys.push(Hotkey::ToggleMute);
+        }
+        if self.window.is_key_pressed(Key::F12, KeyRepeat::No) {
+            hotkeys.push(Hotkey::Screenshot);
+        }
+        if self.window.is_key_pressed(Key::F10, KeyRepeat::No) {
+            hotkeys.push(Hotkey::ToggleGif);
+        }
+        if self.window.is_key_pressed(Key::F8, KeyRepeat::No) {
+            hotkeys.push(Hotkey::SaveProfile);
+        }
+        if self.window.is_key_pressed(Key::F6, KeyRepeat::No) {
+            hotkeys.push(Hotkey::ToggleSlowMotion);
+        }
+        if self.window.is_key_pressed(Key::N, KeyRepeat::Yes) {
+            hotkeys.push(Hotkey::FrameAdvance);
+        }
+        if ctrl && self.window.is_key_pressed(Key::R, KeyRepeat::No) {
+            hotkeys.push(Hotkey::Reset);
+        }
+        if ctrl && self.window.is_key_pressed(Key::O, KeyRepeat::No) {
+            hotkeys.push(Hotkey::OpenRom);
+        }
+        for key in [Key::Equal, Key::NumPadPlus] {
+            if self.window.is_key_pressed(key, KeyRepeat::Yes) {
+                hotkeys.push(Hotkey::SpeedUp);
+            }
+        }
+        for key in [Key::Minus, Key::NumPadMinus] {
+            if self.window.is_key_pressed(key, KeyRepeat::Yes) {
+                hotkeys.push(Hotkey::SpeedDown);
+            }
+        }
+        for (i, key) in DIGITS[1..].iter().enumerate() {
+            if !self.window.is_key_pressed(*key, KeyRepeat::No) {
+                continue;
+            }
+            let slot = i as u8 + 1;
+            if ctrl {
+                hotkeys.push(Hotkey::SaveState(slot));
+            } else if alt {
+                hotkeys.push(Hotkey::LoadState(slot));
+            } else if let Some(quirk) = QUIRK_KEYS.get(i).filter(|_| shift) {
+                hotkeys.push(Hotkey::ToggleQuirk(quirk));
+            }
+        }
+        for (i, key) in [Key::F1, Key::F2, Key::F3, Key::F4].iter().enumerate() {
+            if !self.window.is_key_pressed(*key, KeyRepeat::No) {
+                continue;
+            }
+            let slot = i as u8 + 1;
+            if shift {
+                hotkeys.push(Hotkey::LoadState(slot));
+            } else {
+                hotkeys.push(Hotkey::SaveState(slot));
+            }
+        }
+        hotkeys
+    }
+
+    fn rewind_held(&self) -> bool {
+        self.window.is_key_down(Key::Backspace)
+    }
+}
+
+impl DisplaySink for Window {
+    fn set_title(&mut self, title: &str) {
+        self.window.set_title(title);
+        self.title = title.to_string();
+    }
+
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        self.sound.sync();
+        let reopened = self.window.is_key_pressed(Key::F11, KeyRepeat::No);
+        if reopened {
+            self.borderless = !self.borderless;
+            self.window = Self::open(
+                self.width,
+                self.height,
+                self.borderless,
+                &self.title,
+                Some(self.window.get_position()),
+            );
+        }
+        let (width, height) = self.window.get_size();
+        let resized = (width.max(WIDTH), height.max(HEIGHT)) != (self.width, self.height)
+            || std::mem::take(&mut self.repaint);
+        if resized {
+            self.resize(width, height);
+        }
+        let toggled = self.window.is_key_pressed(Key::F9, KeyRepeat::No);
+        if toggled {
+            self.crt_on = !self.crt_on;
+        }
+        let overlay_toggled = self.window.is_key_pressed(Key::F7, KeyRepeat::No);
+        if overlay_toggled {
+            self.overlay_on = !self.overlay_on;
+        }
+        let dirty = fb.take_dirty_rows();
+        // the overlay changes every frame
+        let keypad_toggled = self.window.is_key_pressed(Key::F5, KeyRepeat::No);
+        if keypad_toggled {
+            self.keypad_on = !self.keypad_on;
+        }
+        // the screen under the message comes back once it's gone
+        let expired = self.message.as_ref().is_some_and(Message::expired);
+        if expired {
+            self.message = None;
+        }
+        let everything = reopened
+            || resized
+            || toggled
+            || overlay_toggled
+            || keypad_toggled
+            || expired
+            || self.overlay_on
+            || self.keypad_on
+            || self.message.is_some()
+            || self.menu.is_some();
+        if dirty.is_none() && !everything {
+            // still pump window events so input keeps flowing
+            self.window.update();
+            return;
+        }
+
+        let crt = if self.crt_on { self.crt } else { Crt::OFF };
+        let Area {
+            left,
+            top,
+            width,
+            height,
+        } = self.area;
+        let rows = match dirty {
+            Some(rows) if !everything && crt == Crt::OFF => {
+                Crt::render_rows(fb, self.palette, &mut self.screen, width, height, rows)
+            }
+            _ => {
+                crt.render(fb, self.palette, &mut self.screen, width, height);
+                0..height
+            }
+        };
+        if let Some(menu) = &self.menu {
+            overlay::draw_menu(menu, &mut self.screen, width, height, self.palette.0);
+        } else if let Some(stats) = self.stats.as_ref().filter(|_| self.overlay_on) {
+            overlay::draw_stats(stats, &mut self.screen, width, height, self.palette.0);
+        }
+        if let Some(message) = &self.message {
+            message.draw(&mut self.screen, width, height, self.palette.0);
+        }
+        if self.keypad_on {
+            Keypad::new(width, height).draw(
+                &mut self.screen,
+                width,
+                self.keypad_held,
+                self.palette.0,
+            );
+        }
+        for y in rows {
+            let start = (top + y) * self.width + left;
+            self.pixel_buffer[start..start + width]
+                .copy_from_slice(&self.screen[y * width..(y + 1) * width]);
+        }
+        self.window
+            .update_with_buffer(&self.pixel_buffer, self.width, self.height)
+            .unwrap();
+    }
+
+    fn show_stats(&mut self, stats: Stats) {
+        self.stats = Some(stats);
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = Some(Message::new(message));
+    }
+
+    fn show_menu(&mut self, menu: Option<Vec<String>>) {
+        self.menu = menu;
+    }
+
+    fn set_palette(&mut self, palette: (u32, u32)) {
+        self.palette = palette;
+        self.repaint = true;
+    }
+}
+
+impl AudioSink for Window {
+    fn beep(&mut self, pattern: Option<AudioPattern>) {
+        self.sound.beep(pattern);
+    }
+}
+
+#[test]
+fn test_fit_screen_to_window() {
+    // 10x scale, 40 pixels to spare on either side
+    let area = Area::fit(720, 330);
+    assert_eq!(
+        area,
+        Area {
+            left: 40,
+            top: 5,
+            width: 640,
+            height: 320,
+        }
+    );
+    // too small for anything bigger than scale 1
+    assert_eq!(Area::fit(100, 40).width, WIDTH);
+    assert_eq!(Area::fit(WIDTH * 16, HEIGHT * 16).left, 0);
+}
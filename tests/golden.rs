@@ -0,0 +1,48 @@
+// Runs roms for a fixed number of instructions and compares the screen with
+// the ascii art in tests/golden, run with UPDATE_GOLDEN=1 to accept changes
+
+use std::{env, fs, path::Path};
+
+use emuchip::{quirks::Quirks, Chip8};
+
+const STEPS: usize = 200;
+
+fn check(name: &str, rom: &str, quirks: Quirks) -> Result<(), String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut chip8 = Chip8::new(quirks);
+    chip8.set_seed(0);
+    chip8.load_rom(&fs::read(dir.join("roms").join(rom)).unwrap())?;
+    for _ in 0..STEPS {
+        chip8.step()?;
+    }
+    let screen = chip8.framebuffer().to_string();
+
+    let golden = dir.join("golden").join(format!("{name}.txt"));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &screen).unwrap();
+        return Ok(());
+    }
+    let expected = fs::read_to_string(&golden).map_err(|err| format!("{name}: {err}"))?;
+    if screen != expected {
+        return Err(format!("{name} is drawn differently now:\n{screen}"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_golden_screens() {
+    let wrap = Quirks {
+        wrap_sprites: true,
+        ..Quirks::default()
+    };
+    let failures: Vec<String> = [
+        ("font", "font.ch8", Quirks::default()),
+        ("schip", "schip.ch8", Quirks::default()),
+        ("clip", "clip.ch8", Quirks::default()),
+        ("wrap", "clip.ch8", wrap),
+    ]
+    .into_iter()
+    .filter_map(|(name, rom, quirks)| check(name, rom, quirks).err())
+    .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
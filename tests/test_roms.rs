@@ -0,0 +1,46 @@
+// Runs every rom listed in tests/roms/roms.txt without a window and checks
+// the final screen
+
+use std::{fs, path::Path};
+
+use emuchip::{
+    quirks::{Platform, Quirks},
+    Chip8,
+};
+
+// same speed as `emuchip test`
+const CYCLES: u32 = 700 / 60;
+
+fn run(rom: &[u8], frames: u32, quirks: Quirks) -> Result<String, String> {
+    let mut chip8 = Chip8::new(quirks);
+    chip8.set_seed(0);
+    chip8.load_rom(rom)?;
+    for _ in 0..frames {
+        chip8.run_frame(CYCLES)?;
+    }
+    Ok(chip8.framebuffer().sha1())
+}
+
+#[test]
+fn test_roms() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let list = fs::read_to_string(dir.join("roms.txt")).unwrap();
+    let mut failures = vec![];
+    for line in list.lines().filter(|line| !line.starts_with('#')) {
+        let [file, frames, platform, expected] = line.split_whitespace().collect::<Vec<_>>()[..]
+        else {
+            continue;
+        };
+        let quirks = match platform {
+            "-" => Quirks::default(),
+            platform => Quirks::from_platform(platform.parse::<Platform>().unwrap()),
+        };
+        let rom = fs::read(dir.join(file)).unwrap();
+        match run(&rom, frames.parse().unwrap(), quirks) {
+            Ok(sha1) if sha1 == expected => {}
+            Ok(sha1) => failures.push(format!("{file}: screen is {sha1}, expected {expected}")),
+            Err(err) => failures.push(format!("{file}: {err}")),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
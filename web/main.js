@@ -0,0 +1,97 @@
+import init, { WebChip8 } from "./pkg/emuchip.js";
+
+const FOREGROUND = [0xaa, 0xcc, 0xff];
+const BACKGROUND = [0x22, 0x22, 0x33];
+
+// same QWERTY layout as the desktop frontends, by physical key position
+const KEYS = {
+  Digit1: 0x1, Digit2: 0x2, Digit3: 0x3, Digit4: 0xc,
+  KeyQ: 0x4, KeyW: 0x5, KeyE: 0x6, KeyR: 0xd,
+  KeyA: 0x7, KeyS: 0x8, KeyD: 0x9, KeyF: 0xe,
+  KeyZ: 0xa, KeyX: 0x0, KeyC: 0xb, KeyV: 0xf,
+};
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+
+let chip8 = null;
+let audio = null;
+let gain = null;
+
+// browsers only allow audio to start from a user gesture
+function startAudio() {
+  if (audio) return;
+  audio = new AudioContext();
+  const oscillator = audio.createOscillator();
+  oscillator.type = "square";
+  oscillator.frequency.value = 440;
+  gain = audio.createGain();
+  gain.gain.value = 0;
+  oscillator.connect(gain).connect(audio.destination);
+  oscillator.start();
+}
+
+function setKey(event, pressed) {
+  const key = KEYS[event.code];
+  if (chip8 && key !== undefined) {
+    chip8.set_key(key, pressed);
+    event.preventDefault();
+  }
+}
+
+document.addEventListener("keydown", (event) => setKey(event, true));
+document.addEventListener("keyup", (event) => setKey(event, false));
+
+function draw() {
+  const [width, height] = [chip8.width(), chip8.height()];
+  if (canvas.width !== width) {
+    canvas.width = width;
+    canvas.height = height;
+  }
+  const image = context.createImageData(width, height);
+  chip8.pixels().forEach((on, i) => {
+    image.data.set(on ? FOREGROUND : BACKGROUND, i * 4);
+    image.data[i * 4 + 3] = 0xff;
+  });
+  context.putImageData(image, 0, 0);
+}
+
+// a fixed 60Hz step, whatever rate the display refreshes at
+let last = performance.now();
+let pending = 0;
+function loop(now) {
+  pending += now - last;
+  last = now;
+  while (chip8 && pending >= 1000 / 60) {
+    try {
+      chip8.frame();
+    } catch (err) {
+      // the rom crashed, leave its last screen up
+      console.error(err);
+      chip8 = null;
+    }
+    pending -= 1000 / 60;
+  }
+  pending = Math.min(pending, 1000 / 60);
+  if (chip8 && chip8.take_update()) draw();
+  if (gain) gain.gain.value = chip8 && chip8.is_beeping() ? 0.1 : 0;
+  requestAnimationFrame(loop);
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) return;
+  startAudio();
+  const rom = new Uint8Array(await file.arrayBuffer());
+  const speed = Number(document.getElementById("speed").value);
+  try {
+    chip8 = new WebChip8(rom, speed);
+  } catch (err) {
+    console.error(err);
+    return;
+  }
+  draw();
+});
+
+await init();
+requestAnimationFrame(loop);